@@ -1,4 +1,5 @@
 mod file;
+mod wgsl;
 
 use std::{
     collections::hash_map::Entry,
@@ -15,6 +16,8 @@ use thiserror::Error;
 use regex::Regex;
 use pi_atom::Atom;
 
+pub use wgsl::glsl_to_wgsl;
+
 pub type ShaderId = Atom;
 
 pub enum ShaderPath {
@@ -77,6 +80,8 @@ pub struct Parser {
 	gen_paths: Vec<String>,
 	// 顶点、像素着色器组，每组为一个shaderprogram
 	vert_frag_groups: Vec<ProgramDesc>,
+	// 构建期翻译为wgsl的宏组合 (program, 宏)，program为None时对所有program生效
+	wgsl_variants: Vec<(Option<String>, Vec<String>)>,
 }
 
 pub struct ProgramDesc {
@@ -121,6 +126,15 @@ impl Parser {
 		self
 	}
 
+	/// 添加构建期翻译为wgsl的宏组合，翻译结果写入生成的ShaderMeta.wgsl（运行时开启render_core的wgsl特性时使用）
+	/// * program - 顶点着色器路径去掉后缀， 例如： src/shaders/color； 为None时对所有program生效
+	/// * defines - 开启的宏，与program无关的宏被忽略
+	/// 翻译失败时，parse返回错误
+	pub fn push_wgsl_variant(&mut self, program: Option<&str>, defines: &[&str]) -> &mut Self {
+		self.wgsl_variants.push((program.map(|r| r.to_string()), defines.iter().map(|r| r.to_string()).collect()));
+		self
+	}
+
 	/// 解析shader
 	pub fn parse(&mut self) -> Result<PasreResult, CompileShaderError> {
 		let mut built_temp = BuildTemp::default();
@@ -401,6 +415,14 @@ impl Parser {
 		let defines_static = defines.iter().map(|r| {
 			format!(r#"pub static ref {r}_DEFINE: pi_atom::Atom = pi_atom::Atom::from("{r}");"#) 
 		}).collect::<Vec<String>>();
+
+		let program = &vert_path_str[0..vert_path_str.len() - 5];
+		let wgsl_variants = self.wgsl_variants
+			.iter()
+			.filter(|r| r.0.as_ref().map_or(true, |r| r.as_str() == program))
+			.map(|r| &r.1)
+			.collect::<Vec<&Vec<String>>>();
+		let wgsl_code = wgsl::wgsl_meta_code(&vs_shader_id, &fs_shader_id, &built_temp.built_slice, &wgsl_variants)?;
 		
 		let out_str = format!(r#"
 			use pi_render::rhi::shader::{{ShaderMeta, CodeSlice, BlockCodeAtom, InOut, Define,  ShaderVarying, ShaderInput, ShaderOutput, ShaderProgram, {}}};
@@ -437,6 +459,7 @@ impl Parser {
 					meta.outs = ShaderOutput(vec![
 						{}
 					]);
+					{}
 					meta
 				}}
 			}}
@@ -474,6 +497,7 @@ impl Parser {
 			share_import.join("\n"),
 			
 			varing_list.join(","), in_list.join(","), out_list.join(","),
+			wgsl_code,
 			vs_compile_result.push_code.join("\n"),
 			fs_compile_result.push_code.join("\n"),
 			vs_compile_result.codes.join(",\n"),
@@ -762,7 +786,7 @@ impl Parser {
 			in_list, 
 			out_list, 
 			default_value: default_map, 
-			// source: last_code.into(),
+			source: shader_str,
 			path: path.clone(),
 			imports,
 			stage: match shader_stage {
@@ -812,7 +836,8 @@ pub struct ShaderSlice {
 	default_value: XHashMap<String, String>,
 	
 
-	// source: Cow<'static, str>,
+	// 原始代码，构建期翻译wgsl时使用
+	source: Cow<'static, str>,
 	stage: ShaderStageTy,
 	path: PathBuf,
 	imports: Vec<(ShaderImport,Vec<(bool, String)>)>,
@@ -863,6 +888,8 @@ pub struct ShaderProcessor1 {
 	import_custom_path_regex: Regex,
 	// #define_import_path ...
 	define_import_path_regex: Regex,
+	// #version、#extension
+	version_regex: Regex,
 	layout_simple_regex: Regex,
 	layout_struct_regex: Regex,
 	default_value_regex: Regex,
@@ -877,6 +904,8 @@ pub struct ShaderProcessor1 {
 	ifndef_regex: Regex,
 	else_regex: Regex,
 	endif_regex: Regex,
+	// #if、#elif、#define、#undef，构建期翻译wgsl时不支持
+	wgsl_unsupported_regex: Regex,
 	entry_regex: Regex,
 }
 
@@ -901,6 +930,7 @@ impl Default for ShaderProcessor1 {
             import_custom_path_regex: Regex::new(r"^\s*#\s*import\s*([a-zA-Z_0-9:]+)").unwrap(),
 			// #define_import_path ...
 			define_import_path_regex: Regex::new(r"^\s*#\s*define_import_path\s+([a-zA-Z_0-9:]+)").unwrap(),
+			version_regex: Regex::new(r"^\s*#\s*(version|extension)\b").unwrap(),
 			// #xx
 			// other_cmd_regex: Regex::new(r"^\s*#\s*[a-zA-Z]+\s*(.+)").unwrap(),
 
@@ -908,6 +938,7 @@ impl Default for ShaderProcessor1 {
             ifndef_regex: Regex::new(r"^\s*#\s*ifndef\s*([\w|\d|_]+)").unwrap(),
             else_regex: Regex::new(r"^\s*#\s*else").unwrap(),
             endif_regex: Regex::new(r"^\s*#\s*endif").unwrap(),
+			wgsl_unsupported_regex: Regex::new(r"^\s*#\s*(if|elif|define|undef)\b").unwrap(),
 			entry_regex: Regex::new(r#"^\s*void\s*main\s*\(\s*\)\s*(.+)"#).unwrap(),
 		 }
 	}
@@ -1011,6 +1042,22 @@ pub enum CompileShaderError {
 	#[error("GLSL Parse Error: {0:?}")]
     GlslParse1(Vec<pi_naga::front::glsl::Error>),
 
+	// 以下为翻译wgsl时产生的错误（使用官方naga）
+	#[error("GLSL to WGSL Parse Error: {0:?}")]
+    WgslTranslateParse(Vec<naga::front::glsl::Error>),
+
+	#[error("#{1} is not supported when translating to WGSL, shader: {0}")]
+    WgslUnsupportedDirective(String, String),
+
+	#[error("WGSL translate fail, shader: {0}, defines: {1:?}, err: {2}")]
+    WgslTranslate(String, Vec<String>, Box<CompileShaderError>),
+
+	#[error(transparent)]
+    WgslTranslateValidation(#[from] naga::WithSpan<naga::valid::ValidationError>),
+
+	#[error(transparent)]
+    WgslOut(#[from] naga::back::wgsl::Error),
+
     // #[error(transparent)]
     // SpirVParse1(#[from] pi_naga::front::spv::Error),
 
@@ -1144,7 +1191,7 @@ fn compile_uniform(
 					));
 				}
 			},
-			BindingType::Image(dim, class, len) => {
+			BindingType::Image(dim, arrayed, class, len) => {
 				let (sample_type, multi) = match class {
 					ImageClass::Sampled { kind, multi } => (
 						match kind {
@@ -1160,11 +1207,14 @@ fn compile_uniform(
 					}
 					ImageClass::Storage { .. } => todo!(),
 				};
-				let dim = match dim {
-					ImageDimension::D1 => "D1",
-					ImageDimension::D2 => "D2",
-					ImageDimension::D3 => "D2",
-					ImageDimension::Cube => "Cube",
+				// 对应 wgpu::TextureViewDimension
+				let dim = match (dim, arrayed) {
+					(ImageDimension::D1, _) => "D1",
+					(ImageDimension::D2, false) => "D2",
+					(ImageDimension::D2, true) => "D2Array",
+					(ImageDimension::D3, _) => "D3",
+					(ImageDimension::Cube, false) => "Cube",
+					(ImageDimension::Cube, true) => "CubeArray",
 				};
 
 				// "kind" {
@@ -1279,7 +1329,8 @@ pub struct UniformCode {
 #[derive(Debug, Clone)]
 enum BindingType {
 	Buffer(LayoutInfo),
-	Image(ImageDimension, ImageClass, ArrayLen),
+	/// (维度, 是否纹理数组, 类型, 绑定数组长度)
+	Image(ImageDimension, bool, ImageClass, ArrayLen),
 	Sampler(bool, ArrayLen)
 }
 
@@ -1887,8 +1938,8 @@ fn get_binding_types<'a, 'b>(
 	constants: &'a Arena<Constant>,
 ) -> Result<BindingType, CompileShaderError> {
     match &ty.inner {
-        TypeInner::Image { dim, class, .. } => {
-			Ok(BindingType::Image(*dim, *class, ArrayLen::None))
+        TypeInner::Image { dim, arrayed, class } => {
+			Ok(BindingType::Image(*dim, *arrayed, *class, ArrayLen::None))
         },
 		TypeInner::Sampler { comparison } => {
 			Ok(BindingType::Sampler(*comparison, ArrayLen::None))
@@ -1920,8 +1971,8 @@ fn get_binding_types<'a, 'b>(
 					cur_info.alignment_size = layout_info.alignment_size;
 					Ok(BindingType::Buffer(cur_info))
 				},
-				BindingType::Image(r1, r2, _r3) => {
-					Ok(BindingType::Image(r1, r2, arr_len))
+				BindingType::Image(r1, r2, r3, _r4) => {
+					Ok(BindingType::Image(r1, r2, r3, arr_len))
 				},
 				BindingType::Sampler(r1, _r2) => {
 					Ok(BindingType::Sampler(r1, arr_len))
@@ -0,0 +1,199 @@
+use std::path::Path;
+
+use naga::{back::wgsl::WriterFlags, valid::{Capabilities, ValidationFlags, Validator}, ShaderStage};
+use pi_atom::Atom;
+use pi_hash::{XHashMap, XHashSet};
+
+use crate::{CompileShaderError, ShaderImport, ShaderSlice, SHADER_PROCESSOR};
+
+/// 将完整的glsl代码翻译为wgsl代码
+/// 用于构建期（build.rs）生成wgsl，使WebGPU目标运行时无需naga的glsl前端
+/// 注意：此处使用官方naga，而非pi_naga，输入必须是完整可通过语法检查的着色器
+pub fn glsl_to_wgsl(source: &str, stage: ShaderStage) -> Result<String, CompileShaderError> {
+	let mut parser = naga::front::glsl::Frontend::default();
+	let module = parser
+		.parse(&naga::front::glsl::Options::from(stage), source)
+		.map_err(CompileShaderError::WgslTranslateParse)?;
+
+	let info = Validator::new(ValidationFlags::default(), Capabilities::default()).validate(&module)?;
+
+	Ok(naga::back::wgsl::write_string(&module, &info, WriterFlags::EXPLICIT_TYPES)?)
+}
+
+/// 生成 ShaderMeta.wgsl 的赋值代码，variants 为需要翻译的宏组合；没有需要翻译的组合时 为空字符串
+pub(crate) fn wgsl_meta_code(vs: &Atom, fs: &Atom, slices: &XHashMap<Atom, ShaderSlice>, variants: &[&Vec<String>]) -> Result<String, CompileShaderError> {
+	if variants.is_empty() {
+		return Ok(String::new());
+	}
+	let mut all_defines = XHashSet::default();
+	collect_defines(vs, slices, &mut XHashSet::default(), &mut all_defines)?;
+	collect_defines(fs, slices, &mut XHashSet::default(), &mut all_defines)?;
+
+	let mut built: Vec<Vec<String>> = Vec::new();
+	let mut codes = Vec::new();
+	for variant in variants.iter() {
+		// 与着色器无关的宏被忽略，相同的组合只翻译一次
+		let mut defines = variant.iter().filter(|r| all_defines.contains(*r)).cloned().collect::<Vec<String>>();
+		defines.sort();
+		defines.dedup();
+		if built.contains(&defines) {
+			continue;
+		}
+		let set = defines.iter().cloned().collect::<XHashSet<String>>();
+		let translate = |id: &Atom, stage: ShaderStage| -> Result<String, CompileShaderError> {
+			let mut code = String::new();
+			expand_source(id, slices, &set, &mut XHashSet::default(), &mut code)?;
+			glsl_to_wgsl(&code, stage).map_err(|e| CompileShaderError::WgslTranslate(id.to_string(), defines.clone(), Box::new(e)))
+		};
+		codes.push(format!(
+			"pi_render::rhi::shader::WgslVariant {{ defines: vec![{}], vs: pi_atom::Atom::from(r#\"{}\"#), fs: pi_atom::Atom::from(r#\"{}\"#) }}",
+			atom_list(&defines),
+			translate(vs, ShaderStage::Vertex)?,
+			translate(fs, ShaderStage::Fragment)?,
+		));
+		built.push(defines);
+	}
+
+	let mut all_defines = all_defines.into_iter().collect::<Vec<String>>();
+	all_defines.sort();
+	Ok(format!(
+		"meta.wgsl = pi_render::rhi::shader::ShaderWgsl {{ defines: vec![{}], variants: vec![{}] }};",
+		atom_list(&all_defines),
+		codes.join(",\n"),
+	))
+}
+
+fn atom_list(defines: &[String]) -> String {
+	defines.iter().map(|r| format!(r#"pi_atom::Atom::from("{}")"#, r)).collect::<Vec<String>>().join(",")
+}
+
+/// 着色器及其导入的文件中 使用的所有宏
+fn collect_defines(id: &Atom, slices: &XHashMap<Atom, ShaderSlice>, visited: &mut XHashSet<Atom>, defines: &mut XHashSet<String>) -> Result<(), CompileShaderError> {
+	if !visited.insert(id.clone()) {
+		return Ok(());
+	}
+	let slice = slices.get(id).ok_or_else(|| CompileShaderError::ShaderNotExist(id.clone()))?;
+	defines.extend(slice.defines.iter().cloned());
+	for (import, _) in slice.imports.iter() {
+		if let ShaderImport::Path(path) = import {
+			collect_defines(&resolve_import(&slice.path, path)?, slices, visited, defines)?;
+		}
+	}
+	Ok(())
+}
+
+/// 按宏展开着色器源码，得到可直接翻译的完整glsl
+/// * 处理 #ifdef、#ifndef、#else、#endif
+/// * #if、#elif、#define、#undef 返回错误，避免与运行时 ShaderProcessor 的处理结果不一致
+/// * 内联 #import 的文件（每个文件只内联一次），并去掉其中的 #version、#extension
+/// * 去掉 @default、#define_import_path 等本编译程序扩展的语法
+fn expand_source(id: &Atom, slices: &XHashMap<Atom, ShaderSlice>, defines: &XHashSet<String>, included: &mut XHashSet<Atom>, code: &mut String) -> Result<(), CompileShaderError> {
+	let slice = slices.get(id).ok_or_else(|| CompileShaderError::ShaderNotExist(id.clone()))?;
+	let is_root = included.is_empty();
+	included.insert(id.clone());
+
+	let mut scopes = vec![true];
+	for line in slice.source.lines() {
+		let active = *scopes.last().unwrap();
+		if let Some(cap) = SHADER_PROCESSOR.wgsl_unsupported_regex.captures(line) {
+			return Err(CompileShaderError::WgslUnsupportedDirective(id.to_string(), cap.get(1).unwrap().as_str().to_string()));
+		} else if let Some(cap) = SHADER_PROCESSOR.ifdef_regex.captures(line) {
+			scopes.push(active && defines.contains(cap.get(1).unwrap().as_str()));
+		} else if let Some(cap) = SHADER_PROCESSOR.ifndef_regex.captures(line) {
+			scopes.push(active && !defines.contains(cap.get(1).unwrap().as_str()));
+		} else if SHADER_PROCESSOR.else_regex.is_match(line) {
+			let parent = scopes.len() < 2 || scopes[scopes.len() - 2];
+			let last = scopes.last_mut().unwrap();
+			*last = parent && !*last;
+		} else if SHADER_PROCESSOR.endif_regex.is_match(line) {
+			if scopes.len() < 2 {
+				return Err(CompileShaderError::TypeNotSupport(format!("unmatched #endif in {}", id)));
+			}
+			scopes.pop();
+		} else if !active {
+			// 未开启的分支
+		} else if let Some(cap) = SHADER_PROCESSOR.import_asset_path_regex.captures(line).or_else(|| SHADER_PROCESSOR.import_custom_path_regex.captures(line)) {
+			let import = resolve_import(&slice.path, cap.get(1).unwrap().as_str())?;
+			if !included.contains(&import) {
+				expand_source(&import, slices, defines, included, code)?;
+			}
+		} else if SHADER_PROCESSOR.default_value_regex.is_match(line) || SHADER_PROCESSOR.define_import_path_regex.is_match(line) {
+			// 扩展语法
+		} else if !is_root && SHADER_PROCESSOR.version_regex.is_match(line) {
+			// 导入的文件 只保留主文件的版本、扩展声明
+		} else {
+			code.push_str(line);
+			code.push('\n');
+		}
+	}
+	Ok(())
+}
+
+/// 将 #import 的模块路径（如 super::camera）解析为同名 .glsl 文件
+fn resolve_import(from: &Path, import: &str) -> Result<Atom, CompileShaderError> {
+	let mut segments = import.split("::").peekable();
+	if segments.next() != Some("super") {
+		return Err(CompileShaderError::InvalidImportPath(import.to_string()));
+	}
+	let mut path = from.parent().map(Path::to_path_buf).unwrap_or_default();
+	while segments.peek() == Some(&"super") {
+		segments.next();
+		path.pop();
+	}
+	let mut is_empty = true;
+	for segment in segments {
+		path.push(segment);
+		is_empty = false;
+	}
+	if is_empty {
+		return Err(CompileShaderError::InvalidImportPath(import.to_string()));
+	}
+	path.set_extension("glsl");
+	Ok(Atom::from(path.to_string_lossy().to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+	use std::{borrow::Cow, path::PathBuf};
+
+	use super::*;
+	use crate::ShaderStageTy;
+
+	fn slices(source: &'static str) -> (Atom, XHashMap<Atom, ShaderSlice>) {
+		let id = Atom::from("test.vert");
+		let slice = ShaderSlice {
+			code: Vec::new(),
+			in_list: Vec::new(),
+			out_list: Vec::new(),
+			default_value: XHashMap::default(),
+			source: Cow::Borrowed(source),
+			stage: ShaderStageTy::Vert,
+			path: PathBuf::from("test.vert"),
+			imports: Vec::new(),
+			binding_defines: XHashMap::default(),
+			defines: XHashSet::default(),
+		};
+		let mut slices = XHashMap::default();
+		slices.insert(id.clone(), slice);
+		(id, slices)
+	}
+
+	#[test]
+	fn expand_ifdef() {
+		let (id, slices) = slices("#ifdef A\na\n#else\nb\n#endif\n#ifndef B\nc\n#endif\n");
+		let mut defines = XHashSet::default();
+		defines.insert("A".to_string());
+		let mut code = String::new();
+		expand_source(&id, &slices, &defines, &mut XHashSet::default(), &mut code).unwrap();
+		assert_eq!(code, "a\nc\n");
+	}
+
+	#[test]
+	fn expand_unsupported_directive() {
+		for (source, directive) in [("#if A > 1\n#endif\n", "if"), ("#ifdef A\n#elif B\n#endif\n", "elif"), ("#define A 1\n", "define"), ("# undef A\n", "undef")] {
+			let (id, slices) = slices(source);
+			let r = expand_source(&id, &slices, &XHashSet::default(), &mut XHashSet::default(), &mut String::new());
+			assert!(matches!(r, Err(CompileShaderError::WgslUnsupportedDirective(_, d)) if d == directive), "{source}");
+		}
+	}
+}
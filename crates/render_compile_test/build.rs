@@ -18,7 +18,14 @@ fn main() -> Result<(), CompileShaderError> {
 
     let mut parser = Parser::default();
 
-    let r = parser.push_gen_path(&["src/shader/"]).parse()?;
+    let r = parser
+        .push_gen_path(&["src/shaders/"])
+        // 构建期翻译为wgsl
+        .push_wgsl_variant(Some("src/shaders/image"), &[])
+        .push_wgsl_variant(Some("src/shaders/color"), &[])
+        .push_wgsl_variant(Some("src/shaders/text"), &[])
+        .push_wgsl_variant(Some("src/shaders/test"), &["AAA", "BBB", "CCC", "DD", "EEE", "FFF", "GGG"])
+        .parse()?;
 
     for shader in r.shader_result.iter() {
         std::fs::write(&shader.0, &shader.1).unwrap();
@@ -1,7 +1,7 @@
-// #[macro_use]
-// extern crate lazy_static;
+#[macro_use]
+extern crate lazy_static;
 
-// pub mod shaders;
+pub mod shaders;
 
 
 // use cargo_manifest::{DepsSet, Manifest};
@@ -94,3 +94,60 @@ fn main() {
 //     //     .unwrap();
 //     // println!("main fast ======{:?}", manifest);
 }
+
+#[cfg(test)]
+mod tests {
+    use pi_atom::Atom;
+    use pi_hash::XHashSet;
+    use pi_render::rhi::shader::ShaderProgram;
+
+    use crate::shaders;
+
+    // glsl、wgsl两种形式都必须能通过naga的解析与验证，且构建期翻译与运行时处理后的glsl翻译结果一致
+    fn round_trip<P: ShaderProgram>(defines: &XHashSet<Atom>) {
+        let meta = P::create_meta();
+        for (visibility, stage) in [
+            (wgpu::ShaderStages::VERTEX, naga::ShaderStage::Vertex),
+            (wgpu::ShaderStages::FRAGMENT, naga::ShaderStage::Fragment),
+        ] {
+            let glsl = meta.to_code(defines, visibility);
+            let module = naga::front::glsl::Frontend::default()
+                .parse(&naga::front::glsl::Options::from(stage), &glsl)
+                .unwrap_or_else(|e| panic!("glsl parse fail, name={}, stage={stage:?}, err={e:?}\n{glsl}", meta.name));
+            validate(&module);
+
+            let wgsl = render_compile::glsl_to_wgsl(&glsl, stage).unwrap();
+            let module = naga::front::wgsl::parse_str(&wgsl)
+                .unwrap_or_else(|e| panic!("wgsl parse fail, name={}, stage={stage:?}, err={e:?}\n{wgsl}", meta.name));
+            validate(&module);
+
+            assert_eq!(meta.to_wgsl(defines, visibility).unwrap(), wgsl);
+
+            // 构建期翻译的wgsl
+            let built = meta.wgsl.get(defines, visibility)
+                .unwrap_or_else(|| panic!("wgsl not built, name={}, stage={stage:?}", meta.name));
+            let module = naga::front::wgsl::parse_str(built)
+                .unwrap_or_else(|e| panic!("built wgsl parse fail, name={}, stage={stage:?}, err={e:?}\n{built}", meta.name));
+            validate(&module);
+            assert_eq!(built, wgsl, "built wgsl differs from runtime, name={}, stage={stage:?}", meta.name);
+        }
+    }
+
+    fn validate(module: &naga::Module) {
+        naga::valid::Validator::new(naga::valid::ValidationFlags::default(), naga::valid::Capabilities::default())
+            .validate(module)
+            .unwrap();
+    }
+
+    #[test]
+    fn wgsl_round_trip() {
+        let empty = XHashSet::default();
+        round_trip::<shaders::image::ProgramMeta>(&empty);
+        round_trip::<shaders::color::ProgramMeta>(&empty);
+        round_trip::<shaders::text::ProgramMeta>(&empty);
+
+        let mut defines = XHashSet::default();
+        defines.extend(["AAA", "BBB", "CCC", "DD", "EEE", "FFF", "GGG"].into_iter().map(Atom::from));
+        round_trip::<shaders::test::ProgramMeta>(&defines);
+    }
+}
@@ -1,4 +1,5 @@
 pub mod target_alloc;
 pub mod render_target;
+pub mod render_scale;
 #[cfg(feature = "debug_info")]
 pub mod target_alloc_debug;
@@ -0,0 +1,130 @@
+//! 动态分辨率
+//! * 由 GPU 帧耗时驱动，负载高时降低渲染缩放，负载低时恢复
+//! * 缩放按 step 量化，且耗时需连续超出容差若干帧才调整，避免渲染目标尺寸频繁变化
+//! * 结果通过 SafeAtlasAllocator::set_render_scale 或 set_type_render_scale 应用到之后的分配
+
+use super::target_alloc::{SafeAtlasAllocator, TargetType};
+
+/// 动态分辨率控制器
+#[derive(Debug, Clone)]
+pub struct RenderScaleController {
+    /// 目标 GPU 帧耗时（毫秒）
+    pub target_frame_time: f32,
+    /// 容差比例，耗时在 target_frame_time * (1 ± tolerance) 内不调整
+    pub tolerance: f32,
+    pub min_scale: f32,
+    pub max_scale: f32,
+    /// 每次调整的步长
+    pub step: f32,
+    /// 超出容差持续多少帧才调整
+    pub hold_frames: u32,
+    /// 帧耗时平滑系数 (0, 1]，越小越平滑
+    pub smoothing: f32,
+
+    scale: f32,
+    frame_time: Option<f32>,
+    // 正数为连续过慢的帧数，负数为连续过快的帧数
+    pending: i32,
+}
+
+impl RenderScaleController {
+    /// 低端设备常用设置：缩放 0.7 ~ 1.0
+    pub fn new(target_frame_time: f32) -> Self {
+        Self {
+            target_frame_time,
+            tolerance: 0.1,
+            min_scale: 0.7,
+            max_scale: 1.0,
+            step: 0.05,
+            hold_frames: 30,
+            smoothing: 0.1,
+            scale: 1.0,
+            frame_time: None,
+            pending: 0,
+        }
+    }
+
+    /// 当前缩放
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// 平滑后的帧耗时
+    pub fn frame_time(&self) -> Option<f32> {
+        self.frame_time
+    }
+
+    /// 输入一帧的 GPU 耗时（毫秒），返回缩放是否改变
+    pub fn update(&mut self, gpu_frame_time: f32) -> bool {
+        let frame_time = match self.frame_time {
+            Some(r) => r + (gpu_frame_time - r) * self.smoothing,
+            None => gpu_frame_time,
+        };
+        self.frame_time = Some(frame_time);
+
+        if frame_time > self.target_frame_time * (1.0 + self.tolerance) {
+            self.pending = self.pending.max(0) + 1;
+        } else if frame_time < self.target_frame_time * (1.0 - self.tolerance) {
+            self.pending = self.pending.min(0) - 1;
+        } else {
+            self.pending = 0;
+        }
+        if self.pending == 0 || self.pending.unsigned_abs() < self.hold_frames {
+            return false;
+        }
+
+        let step = if self.pending > 0 { -self.step } else { self.step };
+        self.pending = 0;
+        let old = self.scale;
+        self.scale = (self.scale + step).clamp(self.min_scale, self.max_scale);
+        old != self.scale
+    }
+
+    /// 输入一帧的 GPU 耗时，并将缩放应用为全局缩放
+    pub fn update_allocator(&mut self, gpu_frame_time: f32, allocator: &SafeAtlasAllocator) -> bool {
+        let changed = self.update(gpu_frame_time);
+        if changed {
+            allocator.set_render_scale(self.scale);
+        }
+        changed
+    }
+
+    /// 输入一帧的 GPU 耗时，并将缩放应用到指定的渲染目标类型（如离屏 UI 特效）
+    pub fn update_types(&mut self, gpu_frame_time: f32, allocator: &SafeAtlasAllocator, target_types: &[TargetType]) -> bool {
+        let changed = self.update(gpu_frame_time);
+        if changed {
+            for target_type in target_types.iter() {
+                allocator.set_type_render_scale(*target_type, Some(self.scale));
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scale_follows_frame_time() {
+        let mut controller = RenderScaleController::new(16.0);
+        controller.hold_frames = 3;
+        controller.smoothing = 1.0;
+        assert!(!controller.update(30.0));
+        assert!(!controller.update(30.0));
+        assert!(controller.update(30.0));
+        assert!((controller.scale() - 0.95).abs() < 1e-5);
+        for _ in 0..30 {
+            controller.update(30.0);
+        }
+        assert_eq!(controller.scale(), 0.7);
+        // 容差内不调整
+        for _ in 0..10 {
+            assert!(!controller.update(16.5));
+        }
+        for _ in 0..3 {
+            controller.update(5.0);
+        }
+        assert!((controller.scale() - 0.75).abs() < 1e-5);
+    }
+}
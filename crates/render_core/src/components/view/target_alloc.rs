@@ -9,7 +9,7 @@ use derive_deref_rs::Deref;
 use guillotiere::{Size, Allocation, Rectangle, Point};
 use pi_assets::{asset::{Handle, Droper}, mgr::AssetMgr, homogeneous::HomogeneousMgr};
 use pi_null::Null;
-use pi_share::{Share, ShareRwLock};
+use pi_share::{Share, ShareMutex, ShareRwLock, ShareWeak};
 use pi_slotmap::{DefaultKey, SlotMap, SecondaryMap};
 use pi_hash::{DefaultHasher, XHashMap};
 use pi_atom::Atom;
@@ -37,6 +37,16 @@ pub struct TextureDescriptor {
 	pub view_dimension: Option<TextureViewDimension>,
 }
 
+impl TextureDescriptor {
+	/// 渲染目标的数组层数，view_dimension 为 D2Array 时取 array_layer_count，否则为 1
+	pub fn layer_count(&self) -> u32 {
+		match (self.view_dimension, self.array_layer_count) {
+			(Some(TextureViewDimension::D2Array), Some(count)) => count.max(1),
+			_ => 1,
+		}
+	}
+}
+
 
 /// 渲染目标描述
 #[derive(Debug, Hash, Clone)]
@@ -71,8 +81,100 @@ pub struct TargetDescriptor {
 pub struct Fbo {
 	pub depth: Option<(Handle<AssetWithId<TextureRes>>, Share<wgpu::Texture>)>, // 深度附件
 	pub colors: SmallVec<[(Handle<AssetWithId<TextureRes>>, Share<wgpu::Texture>);1]>, // 颜色附件
+	pub resolves: SmallVec<[Option<(Handle<AssetWithId<TextureRes>>, Share<wgpu::Texture>)>;1]>, // 颜色附件的解析纹理（开启MSAA时存在）
+	pub sample_count: u32, // 多重采样数，1表示未开启
 	pub width: u32,  // 纹理实际宽度
 	pub height: u32, // 纹理实际高度
+	pub layers: SmallVec<[FboLayer;1]>, // 数组渲染目标每一层的附件视图，非数组渲染目标为空
+}
+
+/// 数组渲染目标中一层的附件视图
+#[derive(Debug, Default)]
+pub struct FboLayer {
+	pub colors: SmallVec<[wgpu::TextureView;1]>,
+	pub resolves: SmallVec<[Option<wgpu::TextureView>;1]>,
+	pub depth: Option<wgpu::TextureView>,
+}
+
+impl FboLayer {
+	fn create_view(texture: &wgpu::Texture, layer: u32, aspect: TextureAspect) -> wgpu::TextureView {
+		texture.create_view(&wgpu::TextureViewDescriptor {
+			dimension: Some(TextureViewDimension::D2),
+			aspect,
+			base_mip_level: 0,
+			mip_level_count: Some(1),
+			base_array_layer: layer,
+			array_layer_count: Some(1),
+			..Default::default()
+		})
+	}
+}
+
+impl Fbo {
+	/// 数组层数
+	pub fn layer_count(&self) -> u32 {
+		self.layers.len().max(1) as u32
+	}
+
+	/// 指定层的颜色附件，非数组渲染目标忽略layer
+	pub fn layer_color_attachment(&self, index: usize, layer: u32, ops: wgpu::Operations<wgpu::Color>) -> wgpu::RenderPassColorAttachment<'_> {
+		match self.layers.get(layer as usize) {
+			Some(r) => wgpu::RenderPassColorAttachment {
+				view: &r.colors[index],
+				resolve_target: r.resolves.get(index).and_then(|r| r.as_ref()),
+				ops,
+			},
+			None => self.color_attachment(index, ops),
+		}
+	}
+
+	/// 指定层的所有颜色附件
+	pub fn layer_color_attachments(&self, layer: u32, ops: wgpu::Operations<wgpu::Color>) -> SmallVec<[Option<wgpu::RenderPassColorAttachment<'_>>;1]> {
+		(0..self.colors.len()).map(|i| Some(self.layer_color_attachment(i, layer, ops))).collect()
+	}
+
+	/// 指定层的深度附件视图
+	pub fn layer_depth_view(&self, layer: u32) -> Option<&wgpu::TextureView> {
+		match self.layers.get(layer as usize) {
+			Some(r) => r.depth.as_ref(),
+			None => self.depth.as_ref().map(|r| &*r.0.texture_view),
+		}
+	}
+
+	/// 用于采样的颜色纹理，开启MSAA时为解析纹理
+	pub fn sampled_color(&self, index: usize) -> &Handle<AssetWithId<TextureRes>> {
+		match self.resolves.get(index) {
+			Some(Some(r)) => &r.0,
+			_ => &self.colors[index].0,
+		}
+	}
+
+	/// 颜色附件，开启MSAA时带有resolve_target
+	pub fn color_attachment(&self, index: usize, ops: wgpu::Operations<wgpu::Color>) -> wgpu::RenderPassColorAttachment<'_> {
+		let resolve_target: Option<&wgpu::TextureView> = match self.resolves.get(index) {
+			Some(Some(r)) => Some(&r.0.texture_view),
+			_ => None,
+		};
+		wgpu::RenderPassColorAttachment {
+			view: &self.colors[index].0.texture_view,
+			resolve_target,
+			ops,
+		}
+	}
+
+	/// 所有颜色附件
+	pub fn color_attachments(&self, ops: wgpu::Operations<wgpu::Color>) -> SmallVec<[Option<wgpu::RenderPassColorAttachment<'_>>;1]> {
+		(0..self.colors.len()).map(|i| Some(self.color_attachment(i, ops))).collect()
+	}
+
+	/// 在该目标上绘制的管线应使用的多重采样状态
+	pub fn multisample_state(&self) -> wgpu::MultisampleState {
+		wgpu::MultisampleState {
+			count: self.sample_count,
+			mask: !0,
+			alpha_to_coverage_enabled: false,
+		}
+	}
 }
 
 // TODO Send问题， 临时解决
@@ -88,6 +190,8 @@ pub struct TargetView {
     rect: Rectangle,            // 实际可用区域（不含边框）
     target: Share<Fbo>,         // 关联的FBO
     is_hold: AtomicBool,        // 是否持有该分配区域
+    scale: f32,                 // 分配时的渲染缩放，rect为缩放后的区域
+    layer: u32,                 // 所在的数组层
 }
 
 impl TargetView {
@@ -103,6 +207,26 @@ impl TargetView {
 	pub fn rect(&self) -> &Rectangle {
 		&self.rect
 	}
+	/// 所在的数组层，非数组渲染目标为0
+	pub fn layer(&self) -> u32 {
+		self.layer
+	}
+	/// 所在层的所有颜色附件
+	pub fn color_attachments(&self, ops: wgpu::Operations<wgpu::Color>) -> SmallVec<[Option<wgpu::RenderPassColorAttachment<'_>>;1]> {
+		self.target.layer_color_attachments(self.layer, ops)
+	}
+	/// 所在层的深度附件视图
+	pub fn depth_view(&self) -> Option<&wgpu::TextureView> {
+		self.target.layer_depth_view(self.layer)
+	}
+	/// 分配时使用的渲染缩放（rect 为请求尺寸乘以该值）
+	pub fn scale(&self) -> f32 {
+		self.scale
+	}
+	/// 视口，x, y, w, h 为 0. ~ 1. 的相对值，应用到可用区域上（跟随渲染缩放）
+	pub fn viewport(&self, x: f32, y: f32, w: f32, h: f32) -> (f32, f32, f32, f32) {
+		rect_viewport(&self.rect, x, y, w, h)
+	}
 	/// 拿到分配的uv
 	pub fn uv(&self) -> [f32;8] {
 		let (xmin, xmax, ymin, ymax) = (
@@ -213,6 +337,7 @@ impl<T: GetTargetView + 'static, O: std::ops::Deref<Target=T>> GetTargetView for
 }
 
 /// 线程安全的纹理分配器
+/// * 调用方须在每帧结束时调用 next_frame，否则历史渲染目标不会交换，trim 也无法计算缓冲纹理的空闲帧数
 #[derive(Clone)]
 pub struct SafeAtlasAllocator(pub(crate) Share<ShareRwLock<AtlasAllocator>>);
 impl SafeAtlasAllocator {
@@ -237,6 +362,27 @@ impl SafeAtlasAllocator {
 		self.0.write().unwrap().get_or_create_type(descript)
 	}
 
+	/// 获取或创建多重采样的渲染目标类型，每个颜色附件配有一张解析纹理（Fbo::resolves）
+	/// 采样数按设备对所有附件格式的支持向下取值，都不支持时、或为数组渲染目标时 退化为普通渲染目标
+	pub fn get_or_create_msaa_type(&self, mut descript: TargetDescriptor, sample_count: u32) -> TargetType {
+		let mut allocator = self.0.write().unwrap();
+		let mut count = sample_count;
+		for color in descript.colors_descriptor.iter() {
+			count = count.min(supported_sample_count(&allocator.device, color.format, sample_count));
+		}
+		if let Some(depth) = &descript.depth_descriptor {
+			count = count.min(supported_sample_count(&allocator.device, depth.format, sample_count));
+		}
+		for color in descript.colors_descriptor.iter_mut().chain(descript.depth_descriptor.iter_mut()) {
+			color.sample_count = count;
+			if count > 1 {
+				// 多重采样纹理只能有一级
+				color.mip_level_count = 1;
+			}
+		}
+		allocator.get_or_create_type(descript)
+	}
+
 	/// 创建一个渲染目标类型，并且不共享（get_or_create_type无法通过hash命中该类型）
 	#[inline]
 	pub fn create_type(&self, descript: TargetDescriptor) -> TargetType {
@@ -283,6 +429,101 @@ impl SafeAtlasAllocator {
 	pub fn targetview_size(&self, target: &TargetView) -> usize {
 		self.0.read().unwrap().targetview_size(target)
 	}
+
+	/// 进入下一帧，每帧结束时调用一次
+	/// 交换所有历史渲染目标的 当前、上一帧，并计算缓冲纹理的空闲帧数
+	/// * 分配器不挂接任何帧回调，须由持有者（如渲染图的驱动循环）自行调用
+	pub fn next_frame(&self) {
+		let histories = {
+			let mut allocator = self.0.write().unwrap();
+			allocator.frame += 1;
+			allocator.histories.retain(|r| r.strong_count() > 0);
+			allocator.histories.clone()
+		};
+		for history in histories.iter() {
+			if let Some(history) = history.upgrade() {
+				history.lock().swap();
+			}
+		}
+	}
+
+	/// 创建历史渲染目标（上一帧、当前帧两个独立的渲染目标），用于TAA、运动模糊等时域效果
+	/// 两个渲染目标在帧间位置不变，next_frame时自动交换
+	pub fn allocate_history(&self, width: u32, height: u32, target_type: TargetType) -> HistoryTarget {
+		let state = HistoryTargetState {
+			width,
+			height,
+			target_type,
+			scale: self.type_render_scale(target_type),
+			views: self.allocate_history_views(width, height, target_type),
+			current: 0,
+			frames: 0,
+		};
+		let history = HistoryTarget(Share::new(ShareMutex::new(state)));
+		self.0.write().unwrap().histories.push(Share::downgrade(&history.0));
+		history
+	}
+
+	/// 尺寸、渲染目标类型（格式）或渲染缩放改变时重新分配历史渲染目标，上一帧内容失效
+	/// 返回是否重新分配
+	pub fn resize_history(&self, history: &HistoryTarget, width: u32, height: u32, target_type: TargetType) -> bool {
+		let scale = self.type_render_scale(target_type);
+		let mut state = history.0.lock();
+		if state.width == width && state.height == height && state.target_type.0 == target_type.0 && state.scale == scale {
+			return false;
+		}
+		// 先释放旧的渲染目标，使其空间可被复用
+		state.views = [None, None];
+		state.views = self.allocate_history_views(width, height, target_type);
+		state.width = width;
+		state.height = height;
+		state.target_type = target_type;
+		state.scale = scale;
+		state.frames = 0;
+		true
+	}
+
+	fn allocate_history_views(&self, width: u32, height: u32, target_type: TargetType) -> [Option<ShareTargetView>; 2] {
+		let first = Share::new(self.allocate_alone_not_share(width, height, target_type, std::iter::empty::<&ShareTargetView>(), true));
+		let second = Share::new(self.allocate_alone_not_share(width, height, target_type, [&first].into_iter(), true));
+		[Some(first), Some(second)]
+	}
+
+	/// 全局渲染缩放，分配的尺寸为请求尺寸乘以该值，只影响之后的分配
+	pub fn render_scale(&self) -> f32 {
+		self.0.read().unwrap().render_scale
+	}
+
+	/// 设置全局渲染缩放，取值 (0, 1]
+	pub fn set_render_scale(&self, scale: f32) {
+		self.0.write().unwrap().render_scale = scale.clamp(f32::EPSILON, 1.0);
+	}
+
+	/// 设置某种渲染目标类型的渲染缩放，None时使用全局缩放
+	pub fn set_type_render_scale(&self, target_type: TargetType, scale: Option<f32>) {
+		if let Some(group) = self.0.write().unwrap().all_allocator.get_mut(target_type.0) {
+			group.info.render_scale = scale.map(|r| r.clamp(f32::EPSILON, 1.0));
+		}
+	}
+
+	/// 某种渲染目标类型实际使用的渲染缩放
+	pub fn type_render_scale(&self, target_type: TargetType) -> f32 {
+		let allocator = self.0.read().unwrap();
+		allocator.all_allocator.get(target_type.0).and_then(|r| r.info.render_scale).unwrap_or(allocator.render_scale)
+	}
+
+	/// 按策略释放空闲、过大的缓冲纹理，可定期调用
+	/// 释放的纹理句柄交还纹理资源管理器，由其容量、超时规则最终销毁
+	#[inline]
+	pub fn trim(&self, policy: &TrimPolicy) -> TrimReport {
+		self.0.write().unwrap().trim(policy)
+	}
+
+	/// 释放所有缓冲纹理，用于内存紧张时
+	#[inline]
+	pub fn trim_all(&self) -> TrimReport {
+		self.0.write().unwrap().trim(&TrimPolicy { idle_frames: 0, shrink_ratio: 1.0, shrink_frames: 0 })
+	}
 }
 
 /// 线程不安全的渲染目标分配器
@@ -309,6 +550,12 @@ pub(crate) struct AtlasAllocator {
 
 	// 当前分配需要排除的纹理
 	excludes: SecondaryMap<DefaultKey, bool>,
+	// 当前帧，用于回收空闲的缓冲纹理
+	frame: u64,
+	// 历史渲染目标，next_frame时交换
+	histories: Vec<ShareWeak<ShareMutex<HistoryTargetState>>>,
+	// 全局渲染缩放
+	render_scale: f32,
 }
 
 const PADDING: i32 = 1;
@@ -350,7 +597,51 @@ impl AtlasAllocator {
 			texture_assets_mgr,
 			key_alloter,
 			excludes: SecondaryMap::new(),
+			frame: 0,
+			histories: Vec::new(),
+			render_scale: 1.0,
+		}
+	}
+
+	/// 按策略释放缓冲纹理
+	fn trim(&mut self, policy: &TrimPolicy) -> TrimReport {
+		// 纹理hash 对应的默认尺寸，多个类型共用同一hash时取最大值
+		let mut defaults: XHashMap<u64, (u32, u32)> = XHashMap::default();
+		let mut insert_default = |hash: u64, width: u32, height: u32| {
+			let r = defaults.entry(hash).or_insert((width, height));
+			*r = (r.0.max(width), r.1.max(height));
+		};
+		for (_, group) in self.all_allocator.iter() {
+			let info = &group.info;
+			let (width, height) = (info.descript.default_width, info.descript.default_height);
+			for hash in info.texture_hash.iter().chain(info.resolve_hash.iter().flatten()) {
+				insert_default(*hash, width, height);
+			}
+			if info.descript.need_depth {
+				insert_default(info.depth_hash, width, height);
+			}
+		}
+
+		let frame = self.frame;
+		let is_oversized = |t: &UnuseTexture| match defaults.get(&t.hash) {
+			Some((width, height)) => t.width as f32 > *width as f32 * policy.shrink_ratio || t.height as f32 > *height as f32 * policy.shrink_ratio,
+			None => false,
+		};
+		let mut report = TrimReport::default();
+		while let Some(r) = self.unuse_textures.pop_by_filter(|t| {
+			let idle = frame.saturating_sub(t.parked_frame);
+			idle >= policy.idle_frames || (idle >= policy.shrink_frames && is_oversized(t))
+		}) {
+			if frame.saturating_sub(r.parked_frame) >= policy.idle_frames {
+				report.released_count += 1;
+				report.released_size += r.size;
+			} else {
+				report.shrunk_count += 1;
+				report.shrunk_size += r.size;
+			}
 		}
+		log::trace!("trim target textures, frame: {:?}, report: {:?}", frame, report);
+		report
 	}
 
 	/// 获取或创建渲染目标类型
@@ -378,6 +669,9 @@ impl AtlasAllocator {
 			Some(r) => r,
 			None => panic!("TargetType is not exist: {:?}", target_type),
 		};
+		// 按渲染缩放计算实际分配的尺寸
+		let scale = list.info.render_scale.unwrap_or(self.render_scale);
+		let (width, height) = (scale_size(width, scale), scale_size(height, scale));
 		self.excludes.clear();
 		// 将需要排除的渲染目标插入到slotmap中，后续可以更快的判断一个纹理是否需要排除
 		for i in exclude {
@@ -389,54 +683,61 @@ impl AtlasAllocator {
 			}
 		}
 		for (index, item) in list.list.iter_mut(){
-			let (offset, width, height) = if is_alone && item.hold_count == 0 && item.target.width == width && item.target.height == height { 
-				(0, width, height)
-			} else {
-				// 不在需要排除的渲染目标上分配
-				if self.excludes.get(index).is_some() {
-					continue;
-				}
-
-				// 数量等于0，保持原大小，否则需要padding
-				// 原因是，为了重用屏幕渲染使用的深度缓冲区，通常，fbo的大小与屏幕等大
-				// 同时，需要分配的矩形，也很可能与屏幕等大，如果这里不判断item.count == 0，大部分fbo无法容纳与屏幕等大的矩形
-				if item.hold_count == 0 {
+			// 数组渲染目标，依次在每一层上分配
+			for layer in 0..item.allocators.len() {
+				let (offset, width, height) = if is_alone && item.hold_counts[layer] == 0 && item.target.width == width && item.target.height == height { 
 					(0, width, height)
 				} else {
-					(PADDING, width + DOUBLE_PADDING, height + DOUBLE_PADDING)
-				}
-			};
-			
+					// 不在需要排除的渲染目标上分配
+					if self.excludes.get(index).is_some() {
+						continue;
+					}
 
-			match item.allocator.allocate(Size::new(width as i32, height as i32)) {
-				Some(allocation) => {
-					// log::warn!("alloct========================{:?}, {:?}, {}, {:?}, {:?}, \n{:?}", std::thread::current().id(), &self.excludes.len(), ii, index, self.excludes.get(index).is_some(), &self.excludes);
-					// 在已有的rendertarget中分配成功，直接返回
-					item.count += 1;
-					let rectangle = &allocation.rectangle;
-					let rect = Rectangle::new(
-						Point::new(rectangle.min.x + offset, rectangle.min.y + offset),
-						Point::new(rectangle.max.x - offset, rectangle.max.y - offset)
-					);
-					// 如果不需要占有该空间， 则立即释放该空间
-					if !is_hold {
-						item.allocator.deallocate(allocation.id);
+					// 数量等于0，保持原大小，否则需要padding
+					// 原因是，为了重用屏幕渲染使用的深度缓冲区，通常，fbo的大小与屏幕等大
+					// 同时，需要分配的矩形，也很可能与屏幕等大，如果这里不判断item.count == 0，大部分fbo无法容纳与屏幕等大的矩形
+					if item.hold_counts[layer] == 0 {
+						(0, width, height)
 					} else {
-						item.hold_count += 1;
+						(PADDING, width + DOUBLE_PADDING, height + DOUBLE_PADDING)
 					}
-					log::trace!("allocate1, is_hold: {:?}, ty_index: {:?}, target_index: {:?}, key: {:?}, rect: {:?}", is_hold, target_type.0, index, allocation.id, rect);
-
-					return TargetView {
-						info: allocation,
-						rect,
-						ty_index: target_type.0,
-						index,
-						target: item.target.clone(),
-						is_hold: AtomicBool::new(is_hold),
-					};
-				},
-				None => (),
-			};
+				};
+				
+
+				match item.allocators[layer].allocate(Size::new(width as i32, height as i32)) {
+					Some(allocation) => {
+						// log::warn!("alloct========================{:?}, {:?}, {}, {:?}, {:?}, \n{:?}", std::thread::current().id(), &self.excludes.len(), ii, index, self.excludes.get(index).is_some(), &self.excludes);
+						// 在已有的rendertarget中分配成功，直接返回
+						item.count += 1;
+						let rectangle = &allocation.rectangle;
+						let rect = Rectangle::new(
+							Point::new(rectangle.min.x + offset, rectangle.min.y + offset),
+							Point::new(rectangle.max.x - offset, rectangle.max.y - offset)
+						);
+						// 如果不需要占有该空间， 则立即释放该空间
+						if !is_hold {
+							item.allocators[layer].deallocate(allocation.id);
+						} else {
+							item.hold_counts[layer] += 1;
+						}
+						#[cfg(feature = "debug_info")]
+						item.records.push((allocation.rectangle, rect, is_hold, layer as u32));
+						log::trace!("allocate1, is_hold: {:?}, ty_index: {:?}, target_index: {:?}, layer: {:?}, key: {:?}, rect: {:?}", is_hold, target_type.0, index, layer, allocation.id, rect);
+
+						return TargetView {
+							info: allocation,
+							rect,
+							ty_index: target_type.0,
+							index,
+							target: item.target.clone(),
+							is_hold: AtomicBool::new(is_hold),
+							scale,
+							layer: layer as u32,
+						};
+					},
+					None => (),
+				};
+			}
 		}
 
 		let target = if is_alone {
@@ -446,27 +747,30 @@ impl AtlasAllocator {
 		};
 
 		// self.debugList.push(Cmd::Create(self.cur_allocator_index, w , h));
-		let mut atlas_allocator= guillotiere::AtlasAllocator::new(
-			guillotiere::Size::new(target.width as i32, target.height as i32));
+		// 每一层一个分配器，在第0层上分配
+		let mut allocators: SmallVec<[guillotiere::AtlasAllocator;1]> = (0..target.layer_count()).map(|_| guillotiere::AtlasAllocator::new(
+			guillotiere::Size::new(target.width as i32, target.height as i32))).collect();
 		// self.debugList.push(Cmd::Allocate(self.cur_allocator_index, width as i32 , height as i32));
-		let allocation= match atlas_allocator.allocate(guillotiere::Size::new(width as i32, height as i32)) {
+		let allocation= match allocators[0].allocate(guillotiere::Size::new(width as i32, height as i32)) {
 			Some(r) => r,
 			None => panic!("AtlasAllocator allocate first fail, width: {:?}, height: {:?}, target_width : {:?}, target_height: {:?}", width, height, target.width, target.height),
 		};
 		// 如果不需要占有该空间， 则立即释放该空间
-		let hold_count = if !is_hold {
-			atlas_allocator.deallocate(allocation.id);
-			0
+		let mut hold_counts: SmallVec<[usize;1]> = smallvec::smallvec![0; allocators.len()];
+		if !is_hold {
+			allocators[0].deallocate(allocation.id);
 		} else {
-			1
-		};
+			hold_counts[0] = 1;
+		}
 
 		let list = &mut self.all_allocator[target_type.0];
 		let index = list.list.insert(SingleAllocator {
-			allocator:atlas_allocator,
+			allocators,
 			target: target.clone(),
 			count: 1,
-			hold_count: hold_count,
+			hold_counts,
+			#[cfg(feature = "debug_info")]
+			records: vec![(allocation.rectangle, allocation.rectangle, is_hold, 0)],
 		});
 		let rect = allocation.rectangle.clone();
 		log::trace!("allocate2, is_hold: {:?}, ty_index: {:?}, target_index: {:?}, key: {:?}, rect: {:?}", is_hold, target_type.0, index, allocation.id, rect);
@@ -477,6 +781,8 @@ impl AtlasAllocator {
 			index,
 			ty_index: target_type.0,
 			is_hold: AtomicBool::new(is_hold),
+			scale,
+			layer: 0,
 		}
 	}
 
@@ -496,10 +802,14 @@ impl AtlasAllocator {
 		// 如果TargetView中独占该空间， 则在此时释放分配空间
 		let is_hold = view.is_hold.load(std::sync::atomic::Ordering::Relaxed);
 		if view.is_hold.load(std::sync::atomic::Ordering::Relaxed) {
-			alloctor.allocator.deallocate(view.info.id);
-			alloctor.hold_count -= 1;
+			alloctor.allocators[view.layer as usize].deallocate(view.info.id);
+			alloctor.hold_counts[view.layer as usize] -= 1;
 		}
 		alloctor.count -= 1;
+		#[cfg(feature = "debug_info")]
+		if let Some(i) = alloctor.records.iter().position(|r| r == &(view.info.rectangle, view.rect, is_hold, view.layer)) {
+			alloctor.records.swap_remove(i);
+		}
 		log::trace!("deallocate, count: {:?}, ty_index: {:?}, target_index: {:?}, is_hold: {:?}, key: {:?}, rect: {:?}", alloctor.count, view.ty_index, view.index, is_hold, view.info.id, &view.rect);
 
 		if alloctor.count == 0 {
@@ -515,6 +825,8 @@ impl AtlasAllocator {
 					width: t.target.width, 
 					height: t.target.height, 
 					hash: self.all_allocator[view.ty_index].info.depth_hash, // 深度hash为0，是否需要修改为其他数字，TODO 
+					size: r.0.size,
+					parked_frame: self.frame,
 				}, size_of::<UnuseTexture>())); 
 				// self.unuse_textures.push(
 				// 	UnuseTexture { 
@@ -528,6 +840,21 @@ impl AtlasAllocator {
 				// 	});
 			}
 
+			// 缓冲解析纹理
+			for (color_index, resolve) in t.target.resolves.iter().enumerate() {
+				if let (Some(r), Some(hash)) = (resolve, self.all_allocator[view.ty_index].info.resolve_hash[color_index]) {
+					self.unuse_textures.create(RenderRes::new(UnuseTexture { 
+						view: r.0.clone(),
+						texture: r.1.clone(), 
+						width: t.target.width, 
+						height: t.target.height, 
+						hash,
+						size: r.0.size,
+						parked_frame: self.frame,
+					}, size_of::<UnuseTexture>()));
+				}
+			}
+
 			// 缓冲颜色纹理
 			for color_index in 0..t.target.colors.len() {
 				log::trace!("drop=====ty_index:{:?}, view_index: {:?}, width: {:?}, height: {:?}, target: {:?}", view.ty_index, view.index, t.target.width, t.target.height, self.all_allocator[view.ty_index].info.texture_hash[color_index]);
@@ -537,6 +864,8 @@ impl AtlasAllocator {
 					width: t.target.width, 
 					height: t.target.height, 
 					hash: self.all_allocator[view.ty_index].info.texture_hash[color_index],
+					size: t.target.colors[color_index].0.size,
+					parked_frame: self.frame,
 				}, size_of::<UnuseTexture>()));
 				// self.unuse_textures.push(
 				// 	UnuseTexture { 
@@ -554,6 +883,8 @@ impl AtlasAllocator {
 	fn to_not_hold(&mut self, view: &TargetView) -> TargetView {
 		let alloctor = &mut self.all_allocator[view.ty_index].list[view.index];
 		alloctor.count += 1;
+		#[cfg(feature = "debug_info")]
+		alloctor.records.push((view.info.rectangle, view.rect, false, view.layer));
 		TargetView {
 			ty_index: view.ty_index,
 			index: view.index,
@@ -561,6 +892,8 @@ impl AtlasAllocator {
 			rect: view.rect,
 			target: view.target.clone(),
 			is_hold: AtomicBool::new(false),
+			scale: view.scale,
+			layer: view.layer,
 		}
 	}
 
@@ -585,6 +918,9 @@ impl AtlasAllocator {
 				view_formats: &[],
 			};
 			size += calc_texture_size(&desc);
+			if descriptor.sample_count > 1 {
+				size += calc_texture_size(&wgpu::TextureDescriptor { sample_count: 1, ..desc });
+			}
 		}
 
 		if info.descript.need_depth {
@@ -630,12 +966,16 @@ impl AtlasAllocator {
 		// let mut width = info.descript.default_width.max(min_width);
 		// let mut height = info.descript.default_height.max(min_height);
 		let len = info.descript.colors_descriptor.len();
+		let layers = info.layers;
 
 		let mut target = Fbo {
 			depth: None,
 			colors: SmallVec::new(),
+			resolves: SmallVec::new(),
+			sample_count: 1,
 			width,
 			height,
+			layers: SmallVec::new(),
 		};
 
 		for i in 0..len {
@@ -647,6 +987,7 @@ impl AtlasAllocator {
 				TextureAspect::All,
 				info.texture_hash[i],
 				len,
+				layers,
 			);
 			if len == 1 {
 				width = r.2;
@@ -656,6 +997,20 @@ impl AtlasAllocator {
 			}
 			
 			target.colors.push((r.0, r.1));
+			target.sample_count = target.sample_count.max(descriptor.sample_count);
+		}
+
+		// 多重采样的颜色附件，创建同尺寸的解析纹理
+		for i in 0..len {
+			let resolve = match info.resolve_hash[i] {
+				Some(hash) => {
+					let descriptor = resolve_descriptor(&info.descript.colors_descriptor[i]);
+					let r = self.get_or_create_texture(width, height, &descriptor, TextureAspect::All, hash, 2, layers);
+					Some((r.0, r.1))
+				},
+				None => None,
+			};
+			target.resolves.push(resolve);
 		}
 
 		if info.descript.need_depth {
@@ -671,11 +1026,20 @@ impl AtlasAllocator {
 				wgpu::TextureAspect::DepthOnly,
 				depth_hash,
 				2, // 
-
+				layers,
 			);
 			target.depth = Some((r.0, r.1));
 		}
 
+		// 数组渲染目标，为每一层创建附件视图
+		if layers > 1 {
+			target.layers = (0..layers).map(|layer| FboLayer {
+				colors: target.colors.iter().map(|r| FboLayer::create_view(&r.1, layer, TextureAspect::All)).collect(),
+				resolves: target.resolves.iter().map(|r| r.as_ref().map(|r| FboLayer::create_view(&r.1, layer, TextureAspect::All))).collect(),
+				depth: target.depth.as_ref().map(|r| FboLayer::create_view(&r.1, layer, TextureAspect::DepthOnly)),
+			}).collect();
+		}
+
 		return target;
 	}
 
@@ -688,6 +1052,7 @@ impl AtlasAllocator {
 		aspect: TextureAspect,
 		hash: u64,
 		len: usize,
+		layers: u32,
 	) -> (Handle<AssetWithId<TextureRes>>, Share<wgpu::Texture>, u32, u32) {
 		// 找到一个匹配的纹理，直接返回
 		let unuse =  self.unuse_textures.pop_by_filter(|t| {
@@ -711,7 +1076,7 @@ impl AtlasAllocator {
 
 		let desc = wgpu::TextureDescriptor {
 			label: None,
-			size: wgpu::Extent3d {width: width as u32, height: height as u32, depth_or_array_layers: 1},
+			size: wgpu::Extent3d {width: width as u32, height: height as u32, depth_or_array_layers: layers},
 			mip_level_count: descript.mip_level_count,
 			sample_count: descript.sample_count,
 			dimension: descript.dimension,
@@ -751,20 +1116,44 @@ impl AtlasAllocator {
 	}
 
 	
-	fn create_type_inner(all_allocator: &mut SlotMap<DefaultKey, AllocatorGroup>, descript: TargetDescriptor, mut default_depth_hash: u64) -> DefaultKey {
+	fn create_type_inner(all_allocator: &mut SlotMap<DefaultKey, AllocatorGroup>, mut descript: TargetDescriptor, mut default_depth_hash: u64) -> DefaultKey {
+		// WebGPU 的多重采样纹理只能有一层，数组渲染目标退化为不采样（在写锁内，不能 panic）
+		let layers = descript.colors_descriptor.first().map(|r| r.layer_count()).unwrap_or(1);
+		if layers > 1 && descript.colors_descriptor.iter().chain(descript.depth_descriptor.iter()).any(|r| r.sample_count > 1) {
+			log::warn!("multisampled target can not be an array, sample_count is clamped to 1, layers: {:?}, descript: {:?}", layers, descript);
+			for r in descript.colors_descriptor.iter_mut().chain(descript.depth_descriptor.iter_mut()) {
+				r.sample_count = 1;
+			}
+		}
+
 		let mut texture_hashs = SmallVec::with_capacity(descript.colors_descriptor.len());
+		let mut resolve_hashs = SmallVec::with_capacity(descript.colors_descriptor.len());
+		let mut sample_count = 1;
 		for i in descript.colors_descriptor.iter() {
 			texture_hashs.push(calc_hash(i));
+			resolve_hashs.push(if i.sample_count > 1 { Some(calc_hash(&resolve_descriptor(i))) } else { None });
+			sample_count = sample_count.max(i.sample_count);
+		}
+		// 深度附件的采样数须与颜色附件一致
+		if descript.need_depth && descript.depth_descriptor.is_none() && sample_count > 1 {
+			descript.depth_descriptor = Some(TextureDescriptor { sample_count, ..create_default_depth_descriptor() });
 		}
 		if let Some(r) = &descript.depth_descriptor {
 			default_depth_hash = calc_hash(r);
 		}
+		// 数组渲染目标的深度纹理层数不同，不能与普通深度纹理共用缓冲
+		if descript.need_depth && layers > 1 {
+			default_depth_hash = calc_hash(&(default_depth_hash, layers));
+		}
 		let ty = all_allocator.insert(
 			AllocatorGroup { 
 				info: AllocatorGroupInfo { 
 					descript: descript, 
 					texture_hash: texture_hashs, 
+					resolve_hash: resolve_hashs,
 					depth_hash: default_depth_hash,
+					render_scale: None,
+					layers,
 					// hash: 0, // TODO
 				}, 
 				list: SlotMap::new() });
@@ -773,10 +1162,15 @@ impl AtlasAllocator {
 }
 
 pub(super) struct SingleAllocator {
-	pub(super) allocator: guillotiere::AtlasAllocator,
+	// 每一层一个分配器，非数组渲染目标只有一个
+	pub(super) allocators: SmallVec<[guillotiere::AtlasAllocator;1]>,
 	pub(super) target: Share<Fbo>,
 	pub(super) count: usize,
-	pub(super) hold_count: usize,
+	// 每一层独占分配的数量
+	pub(super) hold_counts: SmallVec<[usize;1]>,
+	/// 当前存活的分配记录（含边框区域，可用区域，是否独占，所在层），仅用于调试输出
+	#[cfg(feature = "debug_info")]
+	pub(super) records: Vec<(Rectangle, Rectangle, bool, u32)>,
 }
 
 #[derive(Debug)]
@@ -788,6 +1182,111 @@ pub struct UnuseTexture {
 	width: u32,
 	height: u32,
 	hash: u64,
+	size: usize, // 纹理字节数
+	parked_frame: u64, // 放入缓冲时的帧
+}
+
+/// 历史渲染目标的状态
+pub struct HistoryTargetState {
+	width: u32,
+	height: u32,
+	target_type: TargetType,
+	// 分配时的渲染缩放
+	scale: f32,
+	views: [Option<ShareTargetView>; 2],
+	current: usize,
+	// 当前尺寸、格式下已经结束的帧数
+	frames: usize,
+}
+
+impl HistoryTargetState {
+	fn swap(&mut self) {
+		self.current = 1 - self.current;
+		self.frames += 1;
+	}
+}
+
+/// 历史渲染目标，包含上一帧、当前帧两个渲染目标
+/// 由SafeAtlasAllocator::allocate_history创建，SafeAtlasAllocator::next_frame时交换
+#[derive(Clone)]
+pub struct HistoryTarget(Share<ShareMutex<HistoryTargetState>>);
+
+impl HistoryTarget {
+	/// 本帧绘制的渲染目标
+	pub fn current(&self) -> ShareTargetView {
+		let state = self.0.lock();
+		state.views[state.current].clone().unwrap()
+	}
+	/// 上一帧的渲染目标，刚创建或重新分配后内容无效，返回None
+	pub fn previous(&self) -> Option<ShareTargetView> {
+		let state = self.0.lock();
+		if state.frames == 0 {
+			None
+		} else {
+			state.views[1 - state.current].clone()
+		}
+	}
+	/// 上一帧内容是否有效
+	pub fn is_valid(&self) -> bool {
+		self.0.lock().frames > 0
+	}
+	pub fn width(&self) -> u32 {
+		self.0.lock().width
+	}
+	pub fn height(&self) -> u32 {
+		self.0.lock().height
+	}
+	pub fn target_type(&self) -> TargetType {
+		self.0.lock().target_type
+	}
+}
+
+impl std::fmt::Debug for HistoryTarget {
+	fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+		let state = self.0.lock();
+		f.debug_struct("HistoryTarget")
+			.field("width", &state.width)
+			.field("height", &state.height)
+			.field("current", &state.current)
+			.field("frames", &state.frames)
+			.finish()
+	}
+}
+
+/// 缓冲纹理的回收策略
+/// Fbo上最后一个TargetView释放时，Fbo即被拆分，其纹理放入缓冲（unuse_textures）等待复用，
+/// 因此按放入缓冲的帧数回收，等同于回收空闲的整个Fbo
+#[derive(Debug, Clone, Copy)]
+pub struct TrimPolicy {
+	/// 缓冲纹理空闲超过该帧数则释放
+	pub idle_frames: u64,
+	/// 缓冲纹理宽或高超过 默认尺寸 * shrink_ratio 时视为过大
+	pub shrink_ratio: f32,
+	/// 过大的缓冲纹理空闲超过该帧数则释放，之后按默认尺寸重新创建
+	pub shrink_frames: u64,
+}
+
+impl Default for TrimPolicy {
+	fn default() -> Self {
+		Self { idle_frames: 600, shrink_ratio: 1.5, shrink_frames: 60 }
+	}
+}
+
+/// 一次回收的结果
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TrimReport {
+	/// 因空闲释放的纹理数量
+	pub released_count: usize,
+	pub released_size: usize,
+	/// 因过大释放的纹理数量
+	pub shrunk_count: usize,
+	pub shrunk_size: usize,
+}
+
+impl TrimReport {
+	pub fn total_size(&self) -> usize {
+		self.released_size + self.shrunk_size
+	}
 }
 
 pub struct AllocatorGroup {
@@ -798,16 +1297,50 @@ pub struct AllocatorGroup {
 pub struct AllocatorGroupInfo {
 	pub(super) descript: TargetDescriptor,
 	pub(super) texture_hash: SmallVec<[u64;1]>,
+	pub(super) resolve_hash: SmallVec<[Option<u64>;1]>,
 	pub(super) depth_hash: u64,
+	// 该类型的渲染缩放，None时使用全局缩放
+	pub(super) render_scale: Option<f32>,
+	// 数组层数，由第一个颜色附件描述决定
+	pub(super) layers: u32,
 	// hash: u64,
 }
 
+/// 视口，x, y, w, h 为 0. ~ 1. 的相对值，应用到 rect 上
+pub fn rect_viewport(rect: &Rectangle, x: f32, y: f32, w: f32, h: f32) -> (f32, f32, f32, f32) {
+	let (width, height) = (rect.width() as f32, rect.height() as f32);
+	(rect.min.x as f32 + x * width, rect.min.y as f32 + y * height, w * width, h * height)
+}
+
+/// 按缩放计算尺寸，向上取整，至少为1
+fn scale_size(size: u32, scale: f32) -> u32 {
+	if scale == 1.0 {
+		return size;
+	}
+	((size as f32 * scale).ceil() as u32).max(1)
+}
+
 fn calc_hash<T: Hash>(v: &T)-> u64 {
 	let mut hasher = DefaultHasher::default();
 	v.hash(&mut hasher);
 	hasher.finish()
 }
 
+/// 设备对该格式支持的、不超过 requested 的最大采样数
+pub fn supported_sample_count(device: &RenderDevice, format: TextureFormat, requested: u32) -> u32 {
+	let flags = format.guaranteed_format_features(device.features()).flags;
+	[16, 8, 4, 2].into_iter().find(|count| *count <= requested && flags.sample_count_supported(*count)).unwrap_or(1)
+}
+
+/// 多重采样颜色附件对应的解析纹理描述
+fn resolve_descriptor(descriptor: &TextureDescriptor) -> TextureDescriptor {
+	TextureDescriptor {
+		sample_count: 1,
+		usage: descriptor.usage | TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT,
+		..*descriptor
+	}
+}
+
 fn create_default_depth_descriptor() -> TextureDescriptor {
 	TextureDescriptor {
 		mip_level_count: 1,
@@ -843,3 +1376,222 @@ fn test() {
 
 }
 
+
+#[cfg(test)]
+mod tests {
+	use pi_assets::asset::GarbageEmpty;
+
+	use crate::rhi::test_util::run;
+
+	use super::*;
+
+	fn create_allocator(device: RenderDevice) -> SafeAtlasAllocator {
+		SafeAtlasAllocator::new(
+			device,
+			AssetMgr::<AssetWithId<TextureRes>>::new(GarbageEmpty(), false, 64 * 1024 * 1024, 60 * 1000),
+			HomogeneousMgr::<RenderRes<UnuseTexture>>::new(GarbageEmpty(), 64 * 1024 * 1024, 60 * 1000),
+			Share::new(pi_key_alloter::KeyAlloter::new(0)),
+		)
+	}
+
+	fn color_descriptor(format: TextureFormat) -> TextureDescriptor {
+		TextureDescriptor {
+			mip_level_count: 1,
+			sample_count: 1,
+			dimension: TextureDimension::D2,
+			format,
+			usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::TEXTURE_BINDING,
+			base_mip_level: 0,
+			base_array_layer: 0,
+			array_layer_count: None,
+			view_dimension: None,
+		}
+	}
+
+	fn target_descriptor(color: TextureDescriptor, need_depth: bool) -> TargetDescriptor {
+		TargetDescriptor {
+			colors_descriptor: smallvec::smallvec![color],
+			need_depth,
+			depth_descriptor: None,
+			default_width: 64,
+			default_height: 64,
+		}
+	}
+
+	#[test]
+	fn msaa_resolve_descriptor() {
+		let descriptor = TextureDescriptor { sample_count: 4, usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC, ..color_descriptor(TextureFormat::Rgba8Unorm) };
+		let resolve = resolve_descriptor(&descriptor);
+		assert_eq!(resolve.sample_count, 1);
+		assert_eq!(resolve.format, descriptor.format);
+		assert!(resolve.usage.contains(TextureUsages::COPY_SRC | TextureUsages::TEXTURE_BINDING | TextureUsages::RENDER_ATTACHMENT));
+		assert_ne!(calc_hash(&resolve), calc_hash(&descriptor));
+	}
+
+	#[test]
+	fn msaa_sample_count_and_type() {
+		run(|device, _queue| {
+			// Rgba8Unorm 保证支持 4 倍采样
+			assert_eq!(supported_sample_count(&device, TextureFormat::Rgba8Unorm, 1), 1);
+			assert_eq!(supported_sample_count(&device, TextureFormat::Rgba8Unorm, 4), 4);
+			assert!(supported_sample_count(&device, TextureFormat::Rgba8Unorm, 16) >= 4);
+
+			let allocator = create_allocator(device);
+			let descriptor = target_descriptor(TextureDescriptor { mip_level_count: 3, ..color_descriptor(TextureFormat::Rgba8Unorm) }, true);
+			let msaa = allocator.get_or_create_msaa_type(descriptor.clone(), 4);
+			// 相同描述命中同一类型，与普通类型不同
+			assert_eq!(allocator.get_or_create_msaa_type(descriptor.clone(), 4).0, msaa.0);
+			assert_ne!(allocator.get_or_create_type(descriptor).0, msaa.0);
+			{
+				let inner = allocator.0.read().unwrap();
+				let info = &inner.all_allocator[msaa.0].info;
+				let color = &info.descript.colors_descriptor[0];
+				assert_eq!((color.sample_count, color.mip_level_count), (4, 1));
+				assert_eq!(info.resolve_hash[0], Some(calc_hash(&resolve_descriptor(color))));
+				assert_eq!(info.descript.depth_descriptor.map(|r| r.sample_count), Some(4));
+				assert_ne!(info.depth_hash, inner.default_depth_hash);
+			}
+
+			let view = allocator.allocate(32, 32, msaa, std::iter::empty::<&ShareTargetView>());
+			let fbo = view.target();
+			assert_eq!(fbo.sample_count, 4);
+			assert!(fbo.resolves[0].is_some());
+			assert_eq!(fbo.multisample_state().count, 4);
+		});
+	}
+
+	#[test]
+	fn trim_idle_and_oversized() {
+		run(|device, _queue| {
+			let allocator = create_allocator(device);
+			let ty = allocator.get_or_create_type(target_descriptor(color_descriptor(TextureFormat::Bgra8Unorm), false));
+
+			// 默认尺寸 64x64 的纹理，与独立分配的 200x200 纹理，释放后进入缓冲
+			drop(allocator.allocate(32, 32, ty, std::iter::empty::<&ShareTargetView>()));
+			drop(allocator.allocate_alone(200, 200, ty, std::iter::empty::<&ShareTargetView>()));
+
+			let policy = TrimPolicy { idle_frames: 3, shrink_ratio: 1.5, shrink_frames: 1 };
+			let report = allocator.trim(&policy);
+			assert_eq!(report.total_size(), 0);
+
+			// 过大的纹理先被释放
+			allocator.next_frame();
+			let report = allocator.trim(&policy);
+			assert_eq!((report.released_count, report.shrunk_count), (0, 1));
+			assert_eq!(report.shrunk_size, 200 * 200 * 4);
+
+			allocator.next_frame();
+			assert_eq!(allocator.trim(&policy).total_size(), 0);
+
+			// 空闲超过 idle_frames
+			allocator.next_frame();
+			let report = allocator.trim(&policy);
+			assert_eq!((report.released_count, report.shrunk_count), (1, 0));
+			assert_eq!(report.released_size, 64 * 64 * 4);
+			assert_eq!(allocator.trim_all().total_size(), 0);
+
+			// 缓冲中的纹理可被 trim_all 立即释放
+			drop(allocator.allocate(32, 32, ty, std::iter::empty::<&ShareTargetView>()));
+			assert_eq!(allocator.trim_all().released_count, 1);
+		});
+	}
+
+	#[test]
+	fn history_swap_and_resize() {
+		run(|device, _queue| {
+			let allocator = create_allocator(device);
+			let rgba = allocator.get_or_create_type(target_descriptor(color_descriptor(TextureFormat::Rgba8Unorm), false));
+			let history = allocator.allocate_history(32, 32, rgba);
+			assert!(history.previous().is_none());
+			assert!(!history.is_valid());
+
+			// 交换后 上一帧为原来的当前帧
+			let first = history.current();
+			allocator.next_frame();
+			let second = history.current();
+			assert!(!Share::ptr_eq(&first, &second));
+			assert!(Share::ptr_eq(&history.previous().unwrap(), &first));
+			allocator.next_frame();
+			assert!(Share::ptr_eq(&history.current(), &first));
+			assert!(Share::ptr_eq(&history.previous().unwrap(), &second));
+			drop((first, second));
+
+			// 参数不变不重新分配
+			assert!(!allocator.resize_history(&history, 32, 32, rgba));
+			assert!(history.is_valid());
+
+			// 尺寸改变，上一帧失效
+			assert!(allocator.resize_history(&history, 48, 32, rgba));
+			assert!(history.previous().is_none());
+			assert_eq!((history.width(), history.height()), (48, 32));
+			allocator.next_frame();
+			assert!(history.previous().is_some());
+
+			// 格式改变
+			let hdr = allocator.get_or_create_type(target_descriptor(color_descriptor(TextureFormat::Rgba16Float), false));
+			assert!(allocator.resize_history(&history, 48, 32, hdr));
+			assert!(history.previous().is_none());
+			assert_eq!(history.target_type().0, hdr.0);
+			let current = history.current();
+			assert_eq!(current.target().colors[0].0.format, TextureFormat::Rgba16Float);
+			drop(current);
+			allocator.next_frame();
+
+			// 渲染缩放改变
+			allocator.set_type_render_scale(hdr, Some(0.5));
+			assert!(allocator.resize_history(&history, 48, 32, hdr));
+			assert!(history.previous().is_none());
+			let rect = *history.current().rect();
+			assert_eq!((rect.width(), rect.height()), (24, 16));
+			assert!(!allocator.resize_history(&history, 48, 32, hdr));
+		});
+	}
+
+	#[test]
+	fn array_layer_allocate() {
+		run(|device, _queue| {
+			let allocator = create_allocator(device);
+			let color = TextureDescriptor { view_dimension: Some(TextureViewDimension::D2Array), array_layer_count: Some(2), ..color_descriptor(TextureFormat::Rgba8Unorm) };
+			let ty = allocator.get_or_create_type(target_descriptor(color, true));
+			let hold_counts = |view: &ShareTargetView| -> Vec<usize> {
+				let inner = allocator.0.read().unwrap();
+				inner.all_allocator[ty.0].list[view.target_index()].hold_counts.to_vec()
+			};
+			let allocate = || allocator.allocate(64, 64, ty, std::iter::empty::<&ShareTargetView>());
+
+			// 第0层占满后 在第1层上分配
+			let first = allocate();
+			assert_eq!(first.layer(), 0);
+			assert_eq!(hold_counts(&first), vec![1, 0]);
+			let second = allocate();
+			assert_eq!(second.layer(), 1);
+			assert_eq!(second.target_index(), first.target_index());
+			assert_eq!(hold_counts(&first), vec![1, 1]);
+			let fbo = second.target();
+			assert_eq!(fbo.layer_count(), 2);
+			assert!(fbo.layer_depth_view(1).is_some());
+
+			// 所有层占满后 创建新的渲染目标
+			let third = allocate();
+			assert_eq!(third.layer(), 0);
+			assert_ne!(third.target_index(), first.target_index());
+
+			// 释放第1层，该层可再次分配
+			drop((second, third));
+			assert_eq!(hold_counts(&first), vec![1, 0]);
+			let fourth = allocate();
+			assert_eq!((fourth.layer(), fourth.target_index()), (1, first.target_index()));
+			assert_eq!(hold_counts(&first), vec![1, 1]);
+
+			// 多重采样不能是数组，采样数退化为1，分配器仍可使用
+			let msaa = TextureDescriptor { sample_count: 4, ..color };
+			let ty = allocator.get_or_create_type(target_descriptor(msaa, true));
+			let view = allocator.allocate(64, 64, ty, std::iter::empty::<&ShareTargetView>());
+			let fbo = view.target();
+			assert_eq!(fbo.sample_count, 1);
+			assert_eq!(fbo.colors[0].1.sample_count(), 1);
+			assert_eq!(fbo.layer_count(), 2);
+			assert!(fbo.layers[0].resolves.iter().all(|r| r.is_none()));
+		});
+	}
+}
@@ -1,4 +1,7 @@
 //! 渲染目标分配器的debug信息
+//! * to_svg 将分配状态绘制为 svg 图片，stats 统计每种 TargetType 的填充率
+use std::fmt::Write;
+
 use pi_slotmap::DefaultKey;
 use serde::{Serialize, Deserialize};
 
@@ -26,8 +29,45 @@ pub struct Target {
     rect_count: usize,
     width: usize,
     height: usize,
+    layers: u32,
+    rects: Vec<TargetRect>,
+}
+
+/// 一次分配的矩形，x、y、width、height 为含边框区域
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TargetRect {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub padding: i32,
+    /// 是否独占该区域；共享的分配不占空间，可能与其他分配重叠
+    pub hold: bool,
+    /// 所在的数组层
+    pub layer: u32,
+}
+
+/// 每种 TargetType 的填充统计
+#[derive(Debug, Serialize, Deserialize, Default)]
+pub struct TargetStats {
+    pub key: DefaultKey,
+    pub format: String,
+    pub targets: usize,
+    /// 所有 Fbo 的总面积（像素）
+    pub total_area: u64,
+    /// 独占分配的面积（含边框）
+    pub hold_area: u64,
+    /// 共享分配的面积（含边框），可能重叠
+    pub shared_area: u64,
+    /// hold_area / total_area
+    pub fill_ratio: f32,
 }
 
+/// svg 中 Fbo 缩放后的最大边长
+const SVG_TARGET_SIZE: f32 = 256.0;
+const SVG_MARGIN: f32 = 16.0;
+const SVG_LABEL_HEIGHT: f32 = 20.0;
+
 impl AtlasAllocatorDebuger {
 	pub fn debug_info(alloter: &SafeAtlasAllocator) -> Self {
         let alloter = alloter.0.write().unwrap();
@@ -43,6 +83,16 @@ impl AtlasAllocatorDebuger {
                     width: target.target.width as usize, 
                     height: target.target.height as usize,
                     rect_count: target.count,
+                    layers: target.target.layer_count(),
+                    rects: target.records.iter().map(|(border, rect, hold, layer)| TargetRect {
+                        x: border.min.x,
+                        y: border.min.y,
+                        width: border.width(),
+                        height: border.height(),
+                        padding: rect.min.x - border.min.x,
+                        hold: *hold,
+                        layer: *layer,
+                    }).collect(),
                 });
             }
             desc.colors_descriptor = info.descript.colors_descriptor.iter().map(|r| {TextureDescriptor::from(r)}).collect();
@@ -55,6 +105,107 @@ impl AtlasAllocatorDebuger {
 		}
 		list
 	}
+
+    /// 每种 TargetType 的填充率
+    pub fn stats(&self) -> Vec<TargetStats> {
+        self.0.iter().map(|desc| {
+            let mut stats = TargetStats {
+                key: desc.key,
+                format: desc.format_label(),
+                targets: desc.targets.len(),
+                ..Default::default()
+            };
+            for target in desc.targets.iter() {
+                stats.total_area += (target.width * target.height) as u64 * target.layers as u64;
+                for rect in target.rects.iter() {
+                    let area = rect.width as u64 * rect.height as u64;
+                    if rect.hold {
+                        stats.hold_area += area;
+                    } else {
+                        stats.shared_area += area;
+                    }
+                }
+            }
+            if stats.total_area > 0 {
+                stats.fill_ratio = stats.hold_area as f32 / stats.total_area as f32;
+            }
+            stats
+        }).collect()
+    }
+
+    /// 绘制为 svg
+    /// * 每种 TargetType 一行，行首标注格式、默认尺寸与填充率
+    /// * 每个 Fbo 按统一比例缩放，标注尺寸；独占分配为实心矩形，虚线框内为去掉边框后的可用区域；共享分配为虚线矩形
+    /// * 数组渲染目标各层的分配画在同一个 Fbo 中
+    pub fn to_svg(&self) -> String {
+        let max_size = self.0.iter()
+            .flat_map(|desc| desc.targets.iter())
+            .map(|target| target.width.max(target.height))
+            .max()
+            .unwrap_or(1)
+            .max(1);
+        let scale = SVG_TARGET_SIZE / max_size as f32;
+        let stats = self.stats();
+
+        let mut body = String::new();
+        let mut y = SVG_MARGIN;
+        let mut width = SVG_MARGIN;
+        for (desc, stats) in self.0.iter().zip(stats.iter()) {
+            let _ = writeln!(body,
+                r#"<text x="{}" y="{}" font-size="14">{:?} {} default {}x{} targets {} fill {:.1}%</text>"#,
+                SVG_MARGIN, y + 14.0, desc.key, stats.format, desc.default_width, desc.default_height, stats.targets, stats.fill_ratio * 100.0
+            );
+            y += SVG_LABEL_HEIGHT;
+
+            let mut x = SVG_MARGIN;
+            let mut row_height: f32 = 0.0;
+            for target in desc.targets.iter() {
+                let (w, h) = (target.width as f32 * scale, target.height as f32 * scale);
+                let _ = writeln!(body, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#f4f4f4" stroke="#000"/>"##, x, y, w, h);
+                for rect in target.rects.iter() {
+                    let (rx, ry) = (x + rect.x as f32 * scale, y + rect.y as f32 * scale);
+                    let (rw, rh) = (rect.width as f32 * scale, rect.height as f32 * scale);
+                    if rect.hold {
+                        let _ = writeln!(body, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="#4a90d9" fill-opacity="0.5" stroke="#1f4e8c"/>"##, rx, ry, rw, rh);
+                        if rect.padding > 0 {
+                            let padding = rect.padding as f32 * scale;
+                            let _ = writeln!(body,
+                                r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#fff" stroke-dasharray="2"/>"##,
+                                rx + padding, ry + padding, (rw - 2.0 * padding).max(0.0), (rh - 2.0 * padding).max(0.0)
+                            );
+                        }
+                    } else {
+                        let _ = writeln!(body, r##"<rect x="{}" y="{}" width="{}" height="{}" fill="none" stroke="#d0021b" stroke-dasharray="4"/>"##, rx, ry, rw, rh);
+                    }
+                }
+                let _ = writeln!(body, r#"<text x="{}" y="{}" font-size="10">{}x{}x{} ({})</text>"#, x, y + h + 10.0, target.width, target.height, target.layers, target.rect_count);
+                x += w + SVG_MARGIN;
+                row_height = row_height.max(h + 12.0);
+            }
+            width = width.max(x);
+            y += row_height + SVG_MARGIN;
+        }
+
+        format!(
+            r#"<svg xmlns="http://www.w3.org/2000/svg" width="{}" height="{}" font-family="monospace">
+{}</svg>
+"#,
+            width.max(SVG_TARGET_SIZE + 2.0 * SVG_MARGIN), y, body
+        )
+    }
+}
+
+impl TargetDescriptor {
+    fn format_label(&self) -> String {
+        let mut label = self.colors_descriptor.iter().map(|r| r.format.as_str()).collect::<Vec<_>>().join("+");
+        if let Some(depth) = &self.depth_descriptor {
+            if !label.is_empty() {
+                label.push('+');
+            }
+            label.push_str(&depth.format);
+        }
+        label
+    }
 }
 
 #[derive(Debug, Hash, Clone, Serialize, Deserialize)]
@@ -84,4 +235,22 @@ impl TextureDescriptor {
             view_dimension: v.view_dimension.map(|v| format!("{:?}", v)),
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stats_fill_ratio() {
+        let rect = |x, hold| TargetRect { x, y: 0, width: 32, height: 32, padding: 1, hold, layer: 0 };
+        let mut desc = TargetDescriptor::default();
+        desc.targets.push(Target { key: DefaultKey::default(), rect_count: 3, width: 64, height: 64, layers: 1, rects: vec![rect(0, true), rect(32, true), rect(0, false)] });
+        let debuger = AtlasAllocatorDebuger(vec![desc]);
+
+        let stats = debuger.stats();
+        assert_eq!(stats[0].hold_area, 2048);
+        assert_eq!(stats[0].shared_area, 1024);
+        assert_eq!(stats[0].fill_ratio, 0.5);
+        assert!(debuger.to_svg().contains("fill 50.0%"));
+    }
+}
@@ -481,6 +481,11 @@ impl<Context: ThreadSync + 'static, Bind: ThreadSync + 'static + Null + Clone> D
 		}
     }
 
+    /// 是否为终点（渲染到屏幕的节点）
+    pub fn is_finish(&self, id: NodeId) -> bool {
+        self.finish_nodes.contains(&id)
+    }
+
     pub fn set_is_build(
         &mut self,
         label: impl Into<NodeLabel>,
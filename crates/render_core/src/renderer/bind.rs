@@ -31,6 +31,25 @@ pub struct KeyBindLayoutTexture2DArray {
     pub view_dimension: wgpu::TextureViewDimension,
 }
 
+/// 立方体纹理，array 为 true 时是立方体纹理数组
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyBindLayoutTextureCube {
+    pub visibility: EShaderStage,
+    pub texture_sample_type: wgpu::TextureSampleType,
+    pub array: bool,
+}
+impl KeyBindLayoutTextureCube {
+    pub fn view_dimension(&self) -> wgpu::TextureViewDimension {
+        if self.array { wgpu::TextureViewDimension::CubeArray } else { wgpu::TextureViewDimension::Cube }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyBindLayoutTexture3D {
+    pub visibility: EShaderStage,
+    pub texture_sample_type: wgpu::TextureSampleType,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct KeyBindLayoutSampler {
     pub visibility: EShaderStage,
@@ -43,6 +62,8 @@ pub enum KeyBindLayout {
     Texture2D(KeyBindLayoutTexture2D),
     Sampler(KeyBindLayoutSampler),
     Texture2DArray(KeyBindLayoutTexture2DArray),
+    TextureCube(KeyBindLayoutTextureCube),
+    Texture3D(KeyBindLayoutTexture3D),
     // SamplerArray(KeyBindSamplerArray),
 }
 impl KeyBindLayout {
@@ -80,12 +101,27 @@ impl KeyBindLayout {
                     count: NonZeroU32::new(val.count as u32),
                 }
             },
+            KeyBindLayout::TextureCube(val) => {
+                wgpu::BindGroupLayoutEntry {
+                    binding: binding as u32,
+                    visibility: val.visibility.mode(),
+                    ty: wgpu::BindingType::Texture { sample_type: val.texture_sample_type, view_dimension: val.view_dimension(), multisampled: false },
+                    count: None,
+                }
+            },
+            KeyBindLayout::Texture3D(val) => {
+                wgpu::BindGroupLayoutEntry {
+                    binding: binding as u32,
+                    visibility: val.visibility.mode(),
+                    ty: wgpu::BindingType::Texture { sample_type: val.texture_sample_type, view_dimension: wgpu::TextureViewDimension::D3, multisampled: false },
+                    count: None,
+                }
+            },
             // KeyBind::SamplerArray(val) => val.entry.clone(),
         }
     }
 }
 
-
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct KeyBindBuffer {
     pub data: BindBufferRange,
@@ -104,6 +140,18 @@ pub struct KeyBindTexture2DArray {
     pub layout: KeyBindLayoutTexture2DArray,
 }
 
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyBindTextureCube {
+    pub data: BindDataTexture2D,
+    pub layout: KeyBindLayoutTextureCube,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct KeyBindTexture3D {
+    pub data: BindDataTexture2D,
+    pub layout: KeyBindLayoutTexture3D,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct KeyBindSampler {
     pub data: BindDataSampler,
@@ -127,6 +175,8 @@ pub enum EKeyBind {
     Buffer(KeyBindBuffer),
     Texture2D(KeyBindTexture2D),
     Sampler(KeyBindSampler),
+    TextureCube(KeyBindTextureCube),
+    Texture3D(KeyBindTexture3D),
     // Texture2DArray(KeyBindTexture2DArray),
     // SamplerArray(KeyBindSamplerArray),
 }
@@ -148,6 +198,12 @@ impl EKeyBind {
             Self::Sampler(val) => {
                 wgpu::BindingResource::Sampler(&val.data.0.0)
             },
+            Self::TextureCube(val) => {
+                wgpu::BindingResource::TextureView(val.data.view())
+            },
+            Self::Texture3D(val) => {
+                wgpu::BindingResource::TextureView(val.data.view())
+            },
             // Self::Texture2DArray(val) => {
             //     wgpu::BindingResource::TextureViewArray(&val.data.array())
             // }
@@ -158,6 +214,8 @@ impl EKeyBind {
             EKeyBind::Buffer(val) => KeyBindLayout::Buffer(val.layout.clone()),
             EKeyBind::Texture2D(val) => KeyBindLayout::Texture2D(val.layout.clone()),
             EKeyBind::Sampler(val) => KeyBindLayout::Sampler(val.layout.clone()),
+            EKeyBind::TextureCube(val) => KeyBindLayout::TextureCube(val.layout.clone()),
+            EKeyBind::Texture3D(val) => KeyBindLayout::Texture3D(val.layout.clone()),
             // EKeyBind::Texture2DArray(val) => KeyBindLayout::Texture2DArray(val.layout.clone()),
         }
     }
@@ -129,6 +129,8 @@ impl KeyBindGroup {
                 },
                 EKeyBind::Texture2D(_) => {},
                 EKeyBind::Sampler(_) => {},
+                EKeyBind::TextureCube(_) => {},
+                EKeyBind::Texture3D(_) => {},
                 // EKeyBind::Texture2DArray(_) => {},
             }
         });
@@ -13,6 +13,8 @@ impl ShaderVarUniform {
     pub const CAMERA_DEPTH_TEXUTRE      : &'static str = "_CameraDepthTexture"  ;
     
     pub const ENVIRONMENT_TEXUTRE       : &'static str = "_EnvironmentTexture"  ;
+    pub const SKYBOX_TEXTURE            : &'static str = "_SkyboxTexture"       ;
+    pub const COLOR_GRADING_LUT         : &'static str = "_ColorGradingLUT"     ;
     pub const IBL_X                     : &'static str = "_SphericalX"          ;
     pub const IBL_Y                     : &'static str = "_SphericalY"          ;
     pub const IBL_Z                     : &'static str = "_SphericalZ"          ;
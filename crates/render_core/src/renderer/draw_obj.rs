@@ -7,7 +7,7 @@ use wgpu::RenderPass;
 
 use crate::rhi::{dyn_uniform_buffer::BufferGroup, asset::RenderRes, bind_group::BindGroup, pipeline::RenderPipeline, shader::{Uniform, BindLayout}};
 
-use super::{vertices::{RenderVertices, RenderIndices}, bind_group::BindGroupUsage};
+use super::{vertices::{RenderVertices, RenderIndices}, bind_group::BindGroupUsage, indirect::DrawIndirect};
 
 pub trait TBindGroups: Clone {
     fn bindgroups<'a>(&'a self) -> std::slice::Iter<'a, Option<BindGroupUsage>>;
@@ -112,11 +112,18 @@ pub struct DrawObj {
     pub instances: Range<u32>,
     pub vertex: Range<u32>,
     pub indices: Option<RenderIndices>,
+    /// 裁剪区域 x, y, w, h，TargetView 局部像素坐标（已按 TargetView::scale 缩放），None 时使用渲染通道的裁剪区域
+    /// * 只由 DrawList::render_with_scissor 应用，DrawObj::draw 与 DrawList::render 忽略
+    pub scissor: Option<[u32; 4]>,
+    /// 模板参考值，None 时保持上一次的值
+    pub stencil_reference: Option<u32>,
+    /// 间接绘制参数，Some 时忽略 instances、vertex 与索引范围，由参数 Buffer 决定
+    pub indirect: Option<DrawIndirect>,
 }
 
 impl Default for DrawObj {
     fn default() -> Self {
-        Self { pipeline: Default::default(), bindgroups: Default::default(), vertices: Default::default(), instances: 0..1, vertex: 0..0, indices: Default::default() }
+        Self { pipeline: Default::default(), bindgroups: Default::default(), vertices: Default::default(), instances: 0..1, vertex: 0..0, indices: Default::default(), scissor: None, stencil_reference: None, indirect: None }
     }
 }
 
@@ -125,10 +132,14 @@ impl DrawObj {
 		self.vertices.insert(vertices.slot, vertices);
 	}
 
+	/// 绘制，不设置裁剪区域，scissor 只由 DrawList::render_with_scissor 应用
 	pub fn draw<'w, 'a>(&'a self, renderpass: &'w mut RenderPass<'a>) {
 		if let Some(pipeline) = &self.pipeline {
 			renderpass.set_pipeline(pipeline);
 			self.bindgroups.set(renderpass);
+			if let Some(reference) = self.stencil_reference {
+				renderpass.set_stencil_reference(reference);
+			}
 
 			// let mut vertex_range = 0..0;
 			let mut v_iter = self.vertices.iter();
@@ -148,12 +159,19 @@ impl DrawObj {
 
 			let instance_range = self.instances.clone();
 
-			match &self.indices {
-				Some(indices) => {
+			match (&self.indices, &self.indirect) {
+				(Some(indices), Some(indirect)) => {
+					renderpass.set_index_buffer(indices.slice(), indices.format);
+					indirect.draw_indexed(renderpass);
+				},
+				(Some(indices), None) => {
 					renderpass.set_index_buffer(indices.slice(), indices.format);
 					renderpass.draw_indexed(indices.value_range(), 0 as i32, instance_range);
 				},
-				None => {
+				(None, Some(indirect)) => {
+					indirect.draw(renderpass);
+				},
+				(None, None) => {
 					if !self.vertex.is_empty() {
 						renderpass.draw(self.vertex.clone(), instance_range);
 					}
@@ -169,6 +187,8 @@ pub(crate) struct TempDrawInfoRecord<'a> {
     vertices: [Option<&'a RenderVertices>; TempDrawInfoRecord::MAX_VERTICE_SLOT],
     indices: Option<&'a RenderIndices>,
     bindgroups: [Option<&'a DrawBindGroup>; TempDrawInfoRecord::MAX_BINDGROUP_SLOT],
+    scissor: Option<[u32; 4]>,
+    stencil_reference: Option<u32>,
 }
 impl<'a> TempDrawInfoRecord<'a> {
     pub const MAX_VERTICE_SLOT: usize = 16;
@@ -231,6 +251,22 @@ impl<'a> TempDrawInfoRecord<'a> {
         
         result
     }
+    pub(crate) fn record_scissor_and_check_diff_with_last(
+        &mut self,
+        scissor: [u32; 4],
+    ) -> bool {
+        let result = self.scissor != Some(scissor);
+        self.scissor = Some(scissor);
+        result
+    }
+    pub(crate) fn record_stencil_reference_and_check_diff_with_last(
+        &mut self,
+        reference: u32,
+    ) -> bool {
+        let result = self.stencil_reference != Some(reference);
+        self.stencil_reference = Some(reference);
+        result
+    }
     // fn get(&mut self, slot: usize) -> Option<&'a RenderVertices> {
     //     let oldlen = self.vertices.len();
     //     let mut addcount = 0;
@@ -242,3 +278,76 @@ impl<'a> TempDrawInfoRecord<'a> {
     //     self.vertices.get(slot).unwrap().as_ref()
     // }
 }
+
+/// 嵌套裁剪区域栈，用于 UI 的多层裁剪
+/// * push 时与栈顶求交，current 为 DrawObj::scissor 应使用的值
+#[derive(Debug, Default, Clone)]
+pub struct ScissorStack {
+    stack: Vec<[u32; 4]>,
+}
+impl ScissorStack {
+    /// 压入裁剪区域 x, y, w, h，返回与外层求交后的区域
+    pub fn push(&mut self, rect: [u32; 4]) -> [u32; 4] {
+        let rect = match self.stack.last() {
+            Some(top) => intersect_scissor(top, &rect),
+            None => rect,
+        };
+        self.stack.push(rect);
+        rect
+    }
+    pub fn pop(&mut self) -> Option<[u32; 4]> {
+        self.stack.pop()
+    }
+    pub fn current(&self) -> Option<[u32; 4]> {
+        self.stack.last().copied()
+    }
+    pub fn depth(&self) -> usize {
+        self.stack.len()
+    }
+    pub fn clear(&mut self) {
+        self.stack.clear();
+    }
+}
+
+/// 两个裁剪区域求交，不相交时宽高为 0
+pub fn intersect_scissor(a: &[u32; 4], b: &[u32; 4]) -> [u32; 4] {
+    let x = a[0].max(b[0]);
+    let y = a[1].max(b[1]);
+    let right = (a[0] + a[2]).min(b[0] + b[2]);
+    let bottom = (a[1] + a[3]).min(b[1] + b[3]);
+    [x, y, right.saturating_sub(x), bottom.saturating_sub(y)]
+}
+
+/// DrawObj::scissor（TargetView 局部坐标）平移到渲染通道区域 pass 原点并与其求交，None 时为 pass
+pub fn resolve_scissor(scissor: Option<[u32; 4]>, pass: &[u32; 4]) -> [u32; 4] {
+    match scissor {
+        Some([x, y, w, h]) => intersect_scissor(&[pass[0] + x, pass[1] + y, w, h], pass),
+        None => *pass,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn scissor_stack_and_record() {
+        let mut stack = ScissorStack::default();
+        assert_eq!(stack.push([0, 0, 100, 100]), [0, 0, 100, 100]);
+        assert_eq!(stack.push([50, 80, 100, 100]), [50, 80, 50, 20]);
+        assert_eq!(stack.push([0, 0, 10, 10]), [50, 80, 0, 0]);
+        stack.pop();
+        assert_eq!(stack.current(), Some([50, 80, 50, 20]));
+
+        let pass = [100, 100, 64, 64];
+        assert_eq!(resolve_scissor(None, &pass), pass);
+        assert_eq!(resolve_scissor(Some([10, 20, 30, 30]), &pass), [110, 120, 30, 30]);
+        assert_eq!(resolve_scissor(Some([40, 40, 100, 100]), &pass), [140, 140, 24, 24]);
+
+        let mut record = TempDrawInfoRecord::default();
+        assert!(record.record_scissor_and_check_diff_with_last([0, 0, 10, 10]));
+        assert!(!record.record_scissor_and_check_diff_with_last([0, 0, 10, 10]));
+        assert!(record.record_stencil_reference_and_check_diff_with_last(1));
+        assert!(!record.record_stencil_reference_and_check_diff_with_last(1));
+    }
+}
@@ -1,6 +1,8 @@
 use std::sync::Arc;
 
-use crate::renderer::draw_obj::TempDrawInfoRecord;
+use guillotiere::Rectangle;
+
+use crate::{components::view::target_alloc::{rect_viewport, TargetView}, renderer::draw_obj::{resolve_scissor, TempDrawInfoRecord}};
 
 use super::draw_obj::DrawObj;
 
@@ -12,13 +14,52 @@ pub struct DrawList {
     pub viewport: (f32, f32, f32, f32, f32, f32),
 }
 impl DrawList {
+    /// 应用到渲染目标可用区域上的视口，跟随渲染目标的渲染缩放
+    pub fn viewport_in(&self, view: &TargetView) -> (f32, f32, f32, f32, f32, f32) {
+        self.viewport_in_rect(view.rect())
+    }
+    /// 应用到 rect 上的视口
+    pub fn viewport_in_rect(&self, rect: &Rectangle) -> (f32, f32, f32, f32, f32, f32) {
+        let (x, y, w, h, min_depth, max_depth) = self.viewport;
+        let (x, y, w, h) = rect_viewport(rect, x, y, w, h);
+        (x, y, w, h, min_depth, max_depth)
+    }
+    /// 设置视口
+    pub fn set_viewport<'a>(&self, view: &TargetView, renderpass: &mut wgpu::RenderPass<'a>) {
+        let (x, y, w, h, min_depth, max_depth) = self.viewport_in(view);
+        renderpass.set_viewport(x, y, w, h, min_depth, max_depth);
+    }
+    /// 绘制，不设置裁剪区域（沿用调用方设置的裁剪区域），DrawObj::scissor 被忽略
+    /// * 需要按 DrawObj::scissor 裁剪时 使用 render_with_scissor
     pub fn render<'a, T: AsRef<DrawObj>>(
         draws: &'a [T],
         renderpass: & mut wgpu::RenderPass<'a>,
+    ) {
+        Self::render_inner(draws, renderpass, None);
+    }
+    /// 绘制，scissor 为渲染通道的裁剪区域 x, y, w, h（附件像素坐标）
+    /// * 开始时先重置为 scissor，避免上一次的裁剪区域泄漏到本次绘制
+    /// * DrawObj::scissor 为 TargetView 局部坐标（已按 TargetView::scale 缩放），平移到 scissor 原点并与其求交后设置；None 时使用 scissor
+    /// * 裁剪区域、模板参考值 与上一次相同时 不重复设置
+    pub fn render_with_scissor<'a, T: AsRef<DrawObj>>(
+        draws: &'a [T],
+        renderpass: & mut wgpu::RenderPass<'a>,
+        scissor: [u32; 4],
+    ) {
+        Self::render_inner(draws, renderpass, Some(scissor));
+    }
+    fn render_inner<'a, T: AsRef<DrawObj>>(
+        draws: &'a [T],
+        renderpass: & mut wgpu::RenderPass<'a>,
+        scissor: Option<[u32; 4]>,
     ) {
         // let time = pi_time::Instant::now();
 
         let mut temp_vertex_record: TempDrawInfoRecord = TempDrawInfoRecord::default();
+        if let Some([x, y, w, h]) = scissor {
+            temp_vertex_record.record_scissor_and_check_diff_with_last([x, y, w, h]);
+            renderpass.set_scissor_rect(x, y, w, h);
+        }
         let mut pipelinekey = 0;
         let mut draw_count: u64 = 0;
         draws.iter().for_each(|draw| {
@@ -40,6 +81,18 @@ impl DrawList {
                     }
                 }
 
+                if let Some(scissor) = &scissor {
+                    let [x, y, w, h] = resolve_scissor(draw.scissor, scissor);
+                    if temp_vertex_record.record_scissor_and_check_diff_with_last([x, y, w, h]) {
+                        renderpass.set_scissor_rect(x, y, w, h);
+                    }
+                }
+                if let Some(reference) = draw.stencil_reference {
+                    if temp_vertex_record.record_stencil_reference_and_check_diff_with_last(reference) {
+                        renderpass.set_stencil_reference(reference);
+                    }
+                }
+
                 draw.vertices.iter().for_each(|(item, _)| {
                     // log::info!("vertex_range {:?}", item.buffer_range.clone());
 					// log::info!("vertex_range {:?}", item.value_range().clone());
@@ -50,16 +103,24 @@ impl DrawList {
     
                 // log::info!("vertex_range {:?}", vertex_range.clone());
 
-                match &draw.indices {
-                    Some(indices) => {
-                        if temp_vertex_record.record_indices_and_check_diff_with_last(indices) {
-                            // log::warn!("Buffer  {:?}", indices.buffer.buffer());
-                            renderpass.set_index_buffer(indices.slice(), indices.format);
-                        }
+                if let Some(indices) = &draw.indices {
+                    if temp_vertex_record.record_indices_and_check_diff_with_last(indices) {
+                        // log::warn!("Buffer  {:?}", indices.buffer.buffer());
+                        renderpass.set_index_buffer(indices.slice(), indices.format);
+                    }
+                }
+                match (&draw.indices, &draw.indirect) {
+                    (Some(_), Some(indirect)) => {
+                        indirect.draw_indexed(renderpass);
+                    },
+                    (Some(indices), None) => {
                         // log::warn!("indices {:?}", indices.value_range());
                         renderpass.draw_indexed(indices.value_range(), 0 as i32, instance_range);
                     },
-                    None => {
+                    (None, Some(indirect)) => {
+                        indirect.draw(renderpass);
+                    },
+                    (None, None) => {
                         renderpass.draw(vertex_range, instance_range);
                     },
                 }
@@ -0,0 +1,158 @@
+//! 间接绘制
+//! * 绘制参数位于 GPU Buffer 中，可由计算着色器剔除后写入
+//! * 设备支持 MULTI_DRAW_INDIRECT 时 多个参数一次提交，否则逐个提交
+//! * 需要适配器支持 DownlevelFlags::INDIRECT_EXECUTION（WebGL2 不支持）
+//! * first_instance 非 0 时 需要设备支持 INDIRECT_FIRST_INSTANCE
+
+use std::{mem::size_of, ops::Range};
+
+use bytemuck::{Pod, Zeroable};
+
+use crate::rhi::device::RenderDevice;
+
+use super::{vertex_buffer::EVertexBufferRange, vertices::{RenderIndices, RenderVertices}};
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum EIndirectError {
+    #[error("indirect execution is not supported by adapter")]
+    NotSupported,
+    #[error("first_instance {0} needs feature INDIRECT_FIRST_INSTANCE")]
+    FirstInstanceNotSupported(u32),
+    #[error("indirect buffer allocate fail, size {0}")]
+    AllocateFail(u32),
+}
+
+/// 检查参数的 first_instance，设备不支持 INDIRECT_FIRST_INSTANCE 时 必须为 0
+pub fn check_first_instance(features: wgpu::Features, first_instances: impl IntoIterator<Item = u32>) -> Result<(), EIndirectError> {
+    if features.contains(wgpu::Features::INDIRECT_FIRST_INSTANCE) {
+        return Ok(());
+    }
+    match first_instances.into_iter().find(|v| *v != 0) {
+        Some(first_instance) => Err(EIndirectError::FirstInstanceNotSupported(first_instance)),
+        None => Ok(()),
+    }
+}
+
+/// draw_indirect 的参数，与 wgpu 的内存布局一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct DrawIndirectArgs {
+    pub vertex_count: u32,
+    pub instance_count: u32,
+    pub first_vertex: u32,
+    pub first_instance: u32,
+}
+impl DrawIndirectArgs {
+    pub const SIZE: wgpu::BufferAddress = size_of::<Self>() as wgpu::BufferAddress;
+
+    pub fn new(vertex: Range<u32>, instances: Range<u32>) -> Self {
+        Self {
+            vertex_count: vertex.end - vertex.start,
+            instance_count: instances.end - instances.start,
+            first_vertex: vertex.start,
+            first_instance: instances.start,
+        }
+    }
+    /// 由顶点数据的范围创建
+    pub fn from_vertices(vertices: &RenderVertices, instances: Range<u32>) -> Self {
+        Self::new(vertices.value_range(), instances)
+    }
+}
+
+/// draw_indexed_indirect 的参数，与 wgpu 的内存布局一致
+#[repr(C)]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Pod, Zeroable)]
+pub struct DrawIndexedIndirectArgs {
+    pub index_count: u32,
+    pub instance_count: u32,
+    pub first_index: u32,
+    pub base_vertex: i32,
+    pub first_instance: u32,
+}
+impl DrawIndexedIndirectArgs {
+    pub const SIZE: wgpu::BufferAddress = size_of::<Self>() as wgpu::BufferAddress;
+
+    pub fn new(indices: Range<u32>, base_vertex: i32, instances: Range<u32>) -> Self {
+        Self {
+            index_count: indices.end - indices.start,
+            instance_count: instances.end - instances.start,
+            first_index: indices.start,
+            base_vertex,
+            first_instance: instances.start,
+        }
+    }
+    /// 由索引数据的范围创建
+    pub fn from_indices(indices: &RenderIndices, base_vertex: i32, instances: Range<u32>) -> Self {
+        Self::new(indices.value_range(), base_vertex, instances)
+    }
+}
+
+/// 间接绘制，DrawObj 有索引时 参数为 DrawIndexedIndirectArgs，否则为 DrawIndirectArgs
+#[derive(Debug, Clone)]
+pub struct DrawIndirect {
+    /// 参数 Buffer，需要 INDIRECT 用途
+    pub buffer: EVertexBufferRange,
+    /// 第一个参数在 buffer 范围内的字节偏移
+    pub offset: wgpu::BufferAddress,
+    /// 参数个数，大于 1 时为多重绘制
+    pub count: u32,
+    /// 设备是否支持 MULTI_DRAW_INDIRECT
+    pub multi_draw: bool,
+}
+impl DrawIndirect {
+    /// * downlevel 为适配器的 DownlevelCapabilities::flags，不支持 INDIRECT_EXECUTION 时返回错误
+    pub fn new(device: &RenderDevice, downlevel: wgpu::DownlevelFlags, buffer: EVertexBufferRange, offset: wgpu::BufferAddress, count: u32) -> Result<Self, EIndirectError> {
+        if !downlevel.contains(wgpu::DownlevelFlags::INDIRECT_EXECUTION) {
+            return Err(EIndirectError::NotSupported);
+        }
+        Ok(Self { buffer, offset, count, multi_draw: device.features().contains(wgpu::Features::MULTI_DRAW_INDIRECT) })
+    }
+
+    fn start(&self) -> wgpu::BufferAddress {
+        self.buffer.range().start + self.offset
+    }
+
+    pub fn draw<'a>(&'a self, renderpass: &mut wgpu::RenderPass<'a>) {
+        let (buffer, start) = (&**self.buffer.buffer(), self.start());
+        if self.count > 1 && self.multi_draw {
+            renderpass.multi_draw_indirect(buffer, start, self.count);
+        } else {
+            for i in 0..self.count as wgpu::BufferAddress {
+                renderpass.draw_indirect(buffer, start + i * DrawIndirectArgs::SIZE);
+            }
+        }
+    }
+
+    pub fn draw_indexed<'a>(&'a self, renderpass: &mut wgpu::RenderPass<'a>) {
+        let (buffer, start) = (&**self.buffer.buffer(), self.start());
+        if self.count > 1 && self.multi_draw {
+            renderpass.multi_draw_indexed_indirect(buffer, start, self.count);
+        } else {
+            for i in 0..self.count as wgpu::BufferAddress {
+                renderpass.draw_indexed_indirect(buffer, start + i * DrawIndexedIndirectArgs::SIZE);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn indirect_args_layout() {
+        assert_eq!(DrawIndirectArgs::SIZE, 16);
+        assert_eq!(DrawIndexedIndirectArgs::SIZE, 20);
+        let args = DrawIndexedIndirectArgs::new(6..12, -1, 2..5);
+        assert_eq!(bytemuck::cast_slice::<_, u32>(&[args]), &[6, 3, 6, u32::MAX, 2]);
+    }
+
+    #[test]
+    fn indirect_first_instance() {
+        let args = [DrawIndirectArgs::new(0..3, 0..1), DrawIndirectArgs::new(0..3, 4..5)];
+        let first_instances = args.iter().map(|v| v.first_instance);
+        assert_eq!(check_first_instance(wgpu::Features::empty(), first_instances.clone()), Err(EIndirectError::FirstInstanceNotSupported(4)));
+        assert_eq!(check_first_instance(wgpu::Features::INDIRECT_FIRST_INSTANCE, first_instances), Ok(()));
+        assert_eq!(check_first_instance(wgpu::Features::empty(), args[..1].iter().map(|v| v.first_instance)), Ok(()));
+    }
+}
@@ -7,8 +7,10 @@ use pi_share::Share;
 pub mod draw_obj;
 pub mod draw_sort;
 pub mod draw_obj_list;
+pub mod render_pass;
 pub mod vertices;
 pub mod indices;
+pub mod indirect;
 pub mod buffer;
 pub mod bind_buffer;
 pub mod bind;
@@ -23,6 +25,7 @@ pub mod vertex_buffer_loader;
 pub mod vertex_buffer_desc;
 pub mod vertex_format;
 pub mod pipeline;
+pub mod pipeline_check;
 pub mod texture;
 pub mod sampler;
 pub mod shader_stage;
@@ -59,6 +59,12 @@ impl KeyRenderPipelineState {
     pub fn target_state(&self) -> Vec<Option<wgpu::ColorTargetState>> {
         vec![self.target_state.clone()]
     }
+    /// 使用渲染目标的多重采样状态（如 Fbo::multisample_state），保留 alpha_to_coverage 设置
+    pub fn with_multisample(mut self, multisample: wgpu::MultisampleState) -> Self {
+        self.multisample.count = multisample.count;
+        self.multisample.mask = multisample.mask;
+        self
+    }
 }
 
 
@@ -0,0 +1,405 @@
+//! 创建 RenderPipeline 前，用 naga 反射信息 检查 shader 与 布局 是否一致
+//! * BindGroupLayout: 缺少的 binding、类型不匹配、不可见的 stage、未使用的 binding
+//! * 顶点布局: 缺少的 顶点属性、属性标量类型不匹配（分量数可以不同，与 wgpu 的校验一致）
+//! * 颜色目标: 缺少的 target、格式不匹配
+//! 避免错误在 create_render_pipeline 内部以 wgpu 校验 panic 的形式出现
+
+use naga::{AddressSpace, Binding, ImageClass, ImageDimension, ScalarKind, ShaderStage, TypeInner};
+use thiserror::Error;
+
+use crate::rhi::shader::ShaderReflection;
+
+use super::{
+    attributes::KeyAttributesLayouts,
+    bind::KeyBindLayout,
+    bind_group::KeyBindGroupLayout,
+    pipeline::KeyRenderPipeline,
+    shader::{Shader, TKeyShaderSetBlock},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+pub enum EPipelineCheckError {
+    #[error("entry point {1:?} not found in {0:?} shader")]
+    EntryPointNotFound(ShaderStage, String),
+    #[error("binding (set={set}, binding={binding}) used by {stage:?} shader is missing in bind group layout")]
+    MissingBinding { stage: ShaderStage, set: u32, binding: u32 },
+    #[error("binding (set={set}, binding={binding}) type mismatch, shader: {shader}, layout: {layout}")]
+    BindingTypeMismatch { set: u32, binding: u32, shader: String, layout: String },
+    #[error("binding (set={set}, binding={binding}) is not visible to {stage:?} shader")]
+    BindingNotVisible { stage: ShaderStage, set: u32, binding: u32 },
+    #[error("uniform buffer (set={set}, binding={binding}) too small, shader: {shader} bytes, layout min_binding_size: {layout} bytes")]
+    BufferTooSmall { set: u32, binding: u32, shader: u32, layout: u32 },
+    #[error("binding (set={set}, binding={binding}) is not used by any shader")]
+    UnusedBinding { set: u32, binding: u32 },
+    #[error("vertex attribute (location={0}) is missing in vertex buffer layouts")]
+    MissingVertexAttribute(u32),
+    #[error("vertex attribute (location={location}) format mismatch, shader: {shader:?}x{components}, layout: {format:?}")]
+    VertexFormatMismatch { location: u32, shader: ScalarKind, components: u32, format: wgpu::VertexFormat },
+    #[error("color target (location={0}) written by fragment shader is missing")]
+    MissingColorTarget(u32),
+    #[error("color target (location={location}) format mismatch, shader: {shader:?}, target: {format:?}")]
+    ColorTargetMismatch { location: u32, shader: ScalarKind, format: wgpu::TextureFormat },
+}
+
+impl EPipelineCheckError {
+    /// 不会导致创建失败，仅提示
+    pub fn is_warning(&self) -> bool {
+        matches!(self, Self::UnusedBinding { .. })
+    }
+}
+
+/// 检查参数
+pub struct PipelineReflectCheck<'a> {
+    pub vs: &'a ShaderReflection,
+    pub vs_entry: &'a str,
+    pub fs: &'a ShaderReflection,
+    pub fs_entry: &'a str,
+    /// 下标为 set
+    pub bind_group_layouts: &'a [Option<KeyBindGroupLayout>],
+    pub vertex_layouts: &'a KeyAttributesLayouts,
+    pub targets: &'a [Option<wgpu::ColorTargetState>],
+}
+
+impl<'a> PipelineReflectCheck<'a> {
+    /// 返回所有检查到的问题，为空表示通过
+    pub fn check(&self) -> Vec<EPipelineCheckError> {
+        let mut errors = vec![];
+        // [set][binding] 是否被使用
+        let mut used: Vec<Vec<bool>> = self.bind_group_layouts.iter().map(|r| vec![false; r.as_ref().map_or(0, |r| r.0.len())]).collect();
+
+        for (reflection, entry, stage) in [
+            (self.vs, self.vs_entry, ShaderStage::Vertex),
+            (self.fs, self.fs_entry, ShaderStage::Fragment),
+        ] {
+            let index = match reflection.module.entry_points.iter().position(|r| r.name == entry && r.stage == stage) {
+                Some(r) => r,
+                None => {
+                    errors.push(EPipelineCheckError::EntryPointNotFound(stage, entry.to_string()));
+                    continue;
+                }
+            };
+            self.check_bindings(reflection, index, stage, &mut used, &mut errors);
+            match stage {
+                ShaderStage::Vertex => self.check_vertex_inputs(reflection, index, &mut errors),
+                _ => self.check_color_targets(reflection, index, &mut errors),
+            }
+        }
+
+        for (set, list) in used.iter().enumerate() {
+            for (binding, used) in list.iter().enumerate() {
+                if !*used {
+                    errors.push(EPipelineCheckError::UnusedBinding { set: set as u32, binding: binding as u32 });
+                }
+            }
+        }
+
+        errors
+    }
+
+    fn check_bindings(
+        &self,
+        reflection: &ShaderReflection,
+        entry_index: usize,
+        stage: ShaderStage,
+        used: &mut [Vec<bool>],
+        errors: &mut Vec<EPipelineCheckError>,
+    ) {
+        let module = &reflection.module;
+        let info = reflection.module_info.get_entry_point(entry_index);
+        for (handle, var) in module.global_variables.iter() {
+            let res = match &var.binding {
+                Some(r) => r,
+                None => continue,
+            };
+            if info[handle].is_empty() {
+                continue;
+            }
+            let (set, binding) = (res.group, res.binding);
+            let layout = match self.bind_group_layouts.get(set as usize).and_then(|r| r.as_ref()).and_then(|r| r.0.get(binding as usize)) {
+                Some(r) => r,
+                None => {
+                    errors.push(EPipelineCheckError::MissingBinding { stage, set, binding });
+                    continue;
+                }
+            };
+            used[set as usize][binding as usize] = true;
+
+            let entry = layout.layout_entry(binding);
+            let visible = match stage {
+                ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+                ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+                ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+            };
+            if !entry.visibility.contains(visible) {
+                errors.push(EPipelineCheckError::BindingNotVisible { stage, set, binding });
+            }
+
+            let (inner, count) = match &module.types[var.ty].inner {
+                TypeInner::BindingArray { base, size } => (&module.types[*base].inner, match size {
+                    naga::ArraySize::Constant(r) => Some(r.get()),
+                    naga::ArraySize::Dynamic => None,
+                }),
+                r => (r, None),
+            };
+            let mismatch = |shader: String| EPipelineCheckError::BindingTypeMismatch { set, binding, shader, layout: format!("{:?}", layout) };
+            match (var.space, inner, layout) {
+                (AddressSpace::Uniform, _, KeyBindLayout::Buffer(val)) => {
+                    let size = inner.size(module.to_ctx());
+                    if val.min_binding_size > 0 && size > val.min_binding_size {
+                        errors.push(EPipelineCheckError::BufferTooSmall { set, binding, shader: size, layout: val.min_binding_size });
+                    }
+                }
+                (AddressSpace::Handle, TypeInner::Image { dim, arrayed, class }, KeyBindLayout::Texture2D(_) | KeyBindLayout::Texture2DArray(_) | KeyBindLayout::TextureCube(_) | KeyBindLayout::Texture3D(_)) => {
+                    let (sample_type, view_dimension, layout_count) = match layout {
+                        KeyBindLayout::Texture2D(val) => (val.texture_sample_type, val.view_dimension, None),
+                        KeyBindLayout::Texture2DArray(val) => (val.texture_sample_type, val.view_dimension, Some(val.count as u32)),
+                        KeyBindLayout::TextureCube(val) => (val.texture_sample_type, val.view_dimension(), None),
+                        KeyBindLayout::Texture3D(val) => (val.texture_sample_type, wgpu::TextureViewDimension::D3, None),
+                        _ => unreachable!(),
+                    };
+                    if view_dimension != image_view_dimension(*dim, *arrayed)
+                        || !image_sample_type_compatible(class, sample_type)
+                        || (layout_count.is_some() && count != layout_count)
+                    {
+                        errors.push(mismatch(format!("{:?}", inner)));
+                    }
+                }
+                (AddressSpace::Handle, TypeInner::Sampler { comparison }, KeyBindLayout::Sampler(val)) => {
+                    if *comparison != (val.binding_type == wgpu::SamplerBindingType::Comparison) {
+                        errors.push(mismatch(format!("{:?}", inner)));
+                    }
+                }
+                _ => errors.push(mismatch(format!("{:?} {:?}", var.space, inner))),
+            }
+        }
+    }
+
+    fn check_vertex_inputs(&self, reflection: &ShaderReflection, entry_index: usize, errors: &mut Vec<EPipelineCheckError>) {
+        let module = &reflection.module;
+        let function = &module.entry_points[entry_index].function;
+        let mut inputs = vec![];
+        for arg in function.arguments.iter() {
+            collect_locations(module, arg.ty, arg.binding.as_ref(), &mut inputs);
+        }
+
+        for (location, kind) in inputs {
+            let attribute = self.vertex_layouts.0.iter().flat_map(|r| r.0.iter()).find(|r| r.shader_location == location);
+            match (attribute, kind) {
+                (None, _) => errors.push(EPipelineCheckError::MissingVertexAttribute(location)),
+                (Some(attribute), Some((kind, components))) => {
+                    // wgpu 只校验标量类型，分量数不同时 多余的分量被丢弃、缺少的分量补默认值
+                    if vertex_format_info(attribute.format).0 != kind {
+                        errors.push(EPipelineCheckError::VertexFormatMismatch { location, shader: kind, components, format: attribute.format });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn check_color_targets(&self, reflection: &ShaderReflection, entry_index: usize, errors: &mut Vec<EPipelineCheckError>) {
+        let module = &reflection.module;
+        let result = match &module.entry_points[entry_index].function.result {
+            Some(r) => r,
+            None => return,
+        };
+        let mut outputs = vec![];
+        collect_locations(module, result.ty, result.binding.as_ref(), &mut outputs);
+
+        for (location, kind) in outputs {
+            match (self.targets.get(location as usize).and_then(|r| r.as_ref()), kind) {
+                (None, _) => errors.push(EPipelineCheckError::MissingColorTarget(location)),
+                (Some(target), Some((kind, _))) => {
+                    if texture_format_kind(target.format) != kind {
+                        errors.push(EPipelineCheckError::ColorTargetMismatch { location, shader: kind, format: target.format });
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+impl<const MAX_BIND_GROUP_COUNT: usize, K: TKeyShaderSetBlock> KeyRenderPipeline<MAX_BIND_GROUP_COUNT, K> {
+    /// 检查 shader 反射信息 与 该 Pipeline 的布局是否一致
+    /// * bind_group_layouts - 与 key_bindgroup_layouts 对应的布局，下标为 set
+    pub fn check_reflect(
+        &self,
+        shader: &Shader<MAX_BIND_GROUP_COUNT, K>,
+        vs: &ShaderReflection,
+        fs: &ShaderReflection,
+        bind_group_layouts: &[Option<KeyBindGroupLayout>],
+    ) -> Vec<EPipelineCheckError> {
+        PipelineReflectCheck {
+            vs,
+            vs_entry: shader.vs_point,
+            fs,
+            fs_entry: shader.fs_point,
+            bind_group_layouts,
+            vertex_layouts: &self.key_vertex_layouts,
+            targets: &self.key_state.target_state(),
+        }
+        .check()
+    }
+}
+
+/// 收集 带 location 的输入输出（标量类型, 分量数），结构体展开其成员
+fn collect_locations(module: &naga::Module, ty: naga::Handle<naga::Type>, binding: Option<&Binding>, result: &mut Vec<(u32, Option<(ScalarKind, u32)>)>) {
+    let inner = &module.types[ty].inner;
+    match binding {
+        Some(Binding::Location { location, .. }) => result.push((*location, scalar_kind(inner))),
+        Some(Binding::BuiltIn(_)) => {}
+        None => {
+            if let TypeInner::Struct { members, .. } = inner {
+                for member in members.iter() {
+                    collect_locations(module, member.ty, member.binding.as_ref(), result);
+                }
+            }
+        }
+    }
+}
+
+fn scalar_kind(inner: &TypeInner) -> Option<(ScalarKind, u32)> {
+    match inner {
+        TypeInner::Scalar(scalar) => Some((scalar.kind, 1)),
+        TypeInner::Vector { scalar, size } => Some((scalar.kind, *size as u32)),
+        _ => None,
+    }
+}
+
+fn image_view_dimension(dim: ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn image_sample_type_compatible(class: &ImageClass, sample_type: wgpu::TextureSampleType) -> bool {
+    match (class, sample_type) {
+        (ImageClass::Sampled { multi: true, .. } | ImageClass::Depth { multi: true }, _) => false,
+        (ImageClass::Sampled { kind: ScalarKind::Float, .. }, wgpu::TextureSampleType::Float { .. }) => true,
+        (ImageClass::Sampled { kind: ScalarKind::Sint, .. }, wgpu::TextureSampleType::Sint) => true,
+        (ImageClass::Sampled { kind: ScalarKind::Uint, .. }, wgpu::TextureSampleType::Uint) => true,
+        // 深度纹理可以用 float 方式采样
+        (ImageClass::Depth { .. }, wgpu::TextureSampleType::Depth) => true,
+        (ImageClass::Sampled { kind: ScalarKind::Float, .. }, wgpu::TextureSampleType::Depth) => true,
+        _ => false,
+    }
+}
+
+/// 顶点格式的 (标量类型, 分量数)
+fn vertex_format_info(format: wgpu::VertexFormat) -> (ScalarKind, u32) {
+    use wgpu::VertexFormat::*;
+    let kind = match format {
+        Uint8x2 | Uint8x4 | Uint16x2 | Uint16x4 | Uint32 | Uint32x2 | Uint32x3 | Uint32x4 => ScalarKind::Uint,
+        Sint8x2 | Sint8x4 | Sint16x2 | Sint16x4 | Sint32 | Sint32x2 | Sint32x3 | Sint32x4 => ScalarKind::Sint,
+        _ => ScalarKind::Float,
+    };
+    let components = match format {
+        Uint8x2 | Sint8x2 | Unorm8x2 | Snorm8x2 | Uint16x2 | Sint16x2 | Unorm16x2 | Snorm16x2 | Float16x2 | Float32x2 | Uint32x2 | Sint32x2 | Float64x2 => 2,
+        Float32x3 | Uint32x3 | Sint32x3 | Float64x3 => 3,
+        Uint8x4 | Sint8x4 | Unorm8x4 | Snorm8x4 | Uint16x4 | Sint16x4 | Unorm16x4 | Snorm16x4 | Float16x4 | Float32x4 | Uint32x4 | Sint32x4 | Float64x4 => 4,
+        _ => 1,
+    };
+    (kind, components)
+}
+
+fn texture_format_kind(format: wgpu::TextureFormat) -> ScalarKind {
+    use wgpu::TextureFormat::*;
+    match format {
+        R8Uint | R16Uint | R32Uint | Rg8Uint | Rg16Uint | Rg32Uint | Rgba8Uint | Rgba16Uint | Rgba32Uint | Rgb10a2Uint => ScalarKind::Uint,
+        R8Sint | R16Sint | R32Sint | Rg8Sint | Rg16Sint | Rg32Sint | Rgba8Sint | Rgba16Sint | Rgba32Sint => ScalarKind::Sint,
+        _ => ScalarKind::Float,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::rhi::shader::ProcessedShader;
+
+    use super::{super::{bind::*, shader_stage::EShaderStage}, *};
+
+    const WGSL: &str = "
+@group(0) @binding(0) var<uniform> u: vec4<f32>;
+@group(0) @binding(1) var tex: texture_2d<f32>;
+@group(0) @binding(2) var samp: sampler;
+@vertex fn vs(@location(0) pos: vec2<f32>) -> @builtin(position) vec4<f32> { return vec4<f32>(pos, 0.0, 1.0) + u; }
+@fragment fn fs() -> @location(0) vec4<f32> { return textureSample(tex, samp, vec2<f32>(0.5)); }
+";
+
+    fn bindings() -> Vec<KeyBindLayout> {
+        vec![
+            KeyBindLayout::Buffer(KeyBindLayoutBuffer { visibility: EShaderStage::VERTEX, dynamic: false, min_binding_size: 16 }),
+            KeyBindLayout::Texture2D(KeyBindLayoutTexture2D {
+                visibility: EShaderStage::FRAGMENT,
+                texture_sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+            }),
+            KeyBindLayout::Sampler(KeyBindLayoutSampler { visibility: EShaderStage::FRAGMENT, binding_type: wgpu::SamplerBindingType::Filtering }),
+        ]
+    }
+
+    fn check(bindings: Vec<KeyBindLayout>, format: wgpu::VertexFormat) -> Vec<EPipelineCheckError> {
+        let reflection = ProcessedShader::Wgsl(WGSL.into()).reflect().unwrap();
+        let vertex_layouts = KeyAttributesLayouts(vec![(
+            vec![wgpu::VertexAttribute { format, offset: 0, shader_location: 0 }],
+            wgpu::VertexStepMode::Vertex,
+            format.size() as u32,
+        )]);
+        let targets = [Some(wgpu::ColorTargetState { format: wgpu::TextureFormat::Rgba8Unorm, blend: None, write_mask: wgpu::ColorWrites::ALL })];
+        PipelineReflectCheck {
+            vs: &reflection,
+            vs_entry: "vs",
+            fs: &reflection,
+            fs_entry: "fs",
+            bind_group_layouts: &[Some(KeyBindGroupLayout(bindings))],
+            vertex_layouts: &vertex_layouts,
+            targets: &targets,
+        }
+        .check()
+    }
+
+    #[test]
+    fn check_ok() {
+        assert_eq!(check(bindings(), wgpu::VertexFormat::Float32x2), vec![]);
+    }
+
+    #[test]
+    fn missing_binding() {
+        let mut layouts = bindings();
+        layouts.pop();
+        assert_eq!(
+            check(layouts, wgpu::VertexFormat::Float32x2),
+            vec![EPipelineCheckError::MissingBinding { stage: ShaderStage::Fragment, set: 0, binding: 2 }]
+        );
+    }
+
+    #[test]
+    fn binding_type_mismatch() {
+        let mut layouts = bindings();
+        layouts[1] = layouts[2].clone();
+        let errors = check(layouts, wgpu::VertexFormat::Float32x2);
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], EPipelineCheckError::BindingTypeMismatch { set: 0, binding: 1, .. }));
+    }
+
+    #[test]
+    fn vertex_format_mismatch() {
+        // 标量类型不一致
+        assert_eq!(
+            check(bindings(), wgpu::VertexFormat::Uint32x2),
+            vec![EPipelineCheckError::VertexFormatMismatch { location: 0, shader: ScalarKind::Float, components: 2, format: wgpu::VertexFormat::Uint32x2 }]
+        );
+        assert_eq!(
+            check(bindings(), wgpu::VertexFormat::Sint32),
+            vec![EPipelineCheckError::VertexFormatMismatch { location: 0, shader: ScalarKind::Float, components: 2, format: wgpu::VertexFormat::Sint32 }]
+        );
+        // 只有分量数不一致时 可以通过 wgpu 的校验
+        assert_eq!(check(bindings(), wgpu::VertexFormat::Float32x3), vec![]);
+        assert_eq!(check(bindings(), wgpu::VertexFormat::Unorm8x4), vec![]);
+    }
+}
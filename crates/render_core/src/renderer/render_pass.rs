@@ -0,0 +1,382 @@
+//! 渲染通道
+//! * 根据附件在依赖图中的使用情况推断 LoadOp、StoreOp
+//!     - 前驱节点写入了内容、或只渲染到图集的部分区域 时 Load，否则 Clear（分块渲染的 GPU 上 Clear 比 Load 开销小）
+//!     - 后继节点使用该附件、或只渲染到图集的部分区域 时 Store，否则 Discard
+//!     - 多重采样的颜色附件只保留解析纹理，多重采样纹理 Discard（部分区域除外，解析会覆盖整张纹理）
+//! * 视口由 DrawList::viewport 应用到可用区域上，裁剪区域为可用区域
+//! * 只渲染到部分区域 又需要清屏时，由 RegionClear 的管线在可用区域内绘制清屏三角形
+
+use std::any::TypeId;
+
+use guillotiere::Rectangle;
+use pi_hash::XHashMap;
+use pi_share::ShareMutex;
+use smallvec::SmallVec;
+
+use crate::{components::view::target_alloc::{rect_viewport, Fbo, TargetView}, depend_graph::node::ParamUsage, rhi::{device::RenderDevice, pipeline::RenderPipeline}};
+
+use super::draw_obj_list::DrawList;
+
+/// 附件在依赖图中的使用情况
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AttachmentUsage {
+    /// 附件内容由前驱节点写入，需要保留
+    pub read_previous: bool,
+    /// 附件内容会被后继节点使用（或直接显示到屏幕）
+    pub used_by_next: bool,
+}
+
+impl AttachmentUsage {
+    pub fn new(read_previous: bool, used_by_next: bool) -> Self {
+        Self { read_previous, used_by_next }
+    }
+    /// 由节点参数的用途得到：输入类型 I 被前驱节点填充、输出类型 O 被后继节点使用
+    pub fn from_param_usage<I: 'static, O: 'static>(usage: &ParamUsage) -> Self {
+        Self {
+            read_previous: usage.is_input_fill(TypeId::of::<I>()),
+            used_by_next: usage.is_output_usage(TypeId::of::<O>()),
+        }
+    }
+}
+
+/// 渲染通道描述
+pub struct RenderPassBuilder<'a> {
+    label: Option<&'a str>,
+    target: &'a Fbo,
+    layer: u32,
+    /// 可用区域，为整个渲染目标时 为 None
+    rect: Option<Rectangle>,
+    clear_color: Option<wgpu::Color>,
+    clear_depth: f32,
+    clear_stencil: u32,
+    color_usage: AttachmentUsage,
+    depth_usage: AttachmentUsage,
+    draws: Option<&'a DrawList>,
+    region_clear: Option<&'a RenderPipeline>,
+}
+
+impl<'a> RenderPassBuilder<'a> {
+    /// 渲染到整个 Fbo
+    pub fn from_fbo(target: &'a Fbo) -> Self {
+        Self {
+            label: None,
+            target,
+            layer: 0,
+            rect: None,
+            clear_color: None,
+            clear_depth: 1.0,
+            clear_stencil: 0,
+            color_usage: AttachmentUsage::default(),
+            depth_usage: AttachmentUsage::default(),
+            draws: None,
+            region_clear: None,
+        }
+    }
+    /// 渲染到分配的区域
+    pub fn from_target_view(view: &'a TargetView) -> Self {
+        let target = &**view.target();
+        let rect = view.rect();
+        let is_whole = rect.min.x == 0 && rect.min.y == 0 && rect.max.x as u32 == target.width && rect.max.y as u32 == target.height;
+        Self {
+            layer: view.layer(),
+            rect: if is_whole { None } else { Some(*rect) },
+            ..Self::from_fbo(target)
+        }
+    }
+    pub fn label(mut self, label: &'a str) -> Self {
+        self.label = Some(label);
+        self
+    }
+    pub fn clear_color(mut self, color: wgpu::Color) -> Self {
+        self.clear_color = Some(color);
+        self
+    }
+    pub fn clear_depth(mut self, depth: f32) -> Self {
+        self.clear_depth = depth;
+        self
+    }
+    pub fn clear_stencil(mut self, stencil: u32) -> Self {
+        self.clear_stencil = stencil;
+        self
+    }
+    pub fn color_usage(mut self, usage: AttachmentUsage) -> Self {
+        self.color_usage = usage;
+        self
+    }
+    pub fn depth_usage(mut self, usage: AttachmentUsage) -> Self {
+        self.depth_usage = usage;
+        self
+    }
+    pub fn draw_list(mut self, draws: &'a DrawList) -> Self {
+        self.draws = Some(draws);
+        self
+    }
+    /// 区域清屏的管线，由 RegionClear::pipeline 获得；needs_region_clear 时必须设置
+    pub fn region_clear(mut self, pipeline: &'a RenderPipeline) -> Self {
+        self.region_clear = Some(pipeline);
+        self
+    }
+
+    /// 是否只渲染到图集的部分区域（其他区域的内容需要保留）
+    pub fn is_partial(&self) -> bool {
+        self.rect.is_some()
+    }
+    /// 需要清屏 但 只渲染到部分区域 无法使用 LoadOp::Clear，需要绘制覆盖可用区域的清屏矩形
+    pub fn needs_region_clear(&self) -> bool {
+        self.is_partial() && self.clear_color.is_some() && !self.color_usage.read_previous
+    }
+
+    /// 多重采样时为多重采样纹理的操作，解析纹理总是被写入
+    pub fn color_ops(&self) -> wgpu::Operations<wgpu::Color> {
+        let store = if self.target.sample_count > 1 && !self.is_partial() {
+            wgpu::StoreOp::Discard
+        } else {
+            store_op(self.color_usage, self.is_partial())
+        };
+        wgpu::Operations {
+            load: load_op(self.color_usage, self.is_partial(), self.clear_color.unwrap_or(wgpu::Color::TRANSPARENT)),
+            store,
+        }
+    }
+    pub fn depth_ops(&self) -> wgpu::Operations<f32> {
+        wgpu::Operations {
+            load: load_op(self.depth_usage, self.is_partial(), self.clear_depth),
+            store: store_op(self.depth_usage, self.is_partial()),
+        }
+    }
+    pub fn stencil_ops(&self) -> wgpu::Operations<u32> {
+        wgpu::Operations {
+            load: load_op(self.depth_usage, self.is_partial(), self.clear_stencil),
+            store: store_op(self.depth_usage, self.is_partial()),
+        }
+    }
+
+    /// 视口 x, y, w, h, min_depth, max_depth；未设置 DrawList 或其视口为空时 为整个可用区域
+    pub fn viewport(&self) -> (f32, f32, f32, f32, f32, f32) {
+        let rect = self.rect_or_whole();
+        match self.draws {
+            Some(draws) if draws.viewport.2 > 0. && draws.viewport.3 > 0. => draws.viewport_in_rect(&rect),
+            _ => {
+                let (x, y, w, h) = rect_viewport(&rect, 0., 0., 1., 1.);
+                (x, y, w, h, 0., 1.)
+            },
+        }
+    }
+
+    /// 开始渲染通道，并设置视口与裁剪区域
+    /// * needs_region_clear 时先在可用区域内清屏，未设置 region_clear 管线则 panic
+    pub fn begin<'e>(&self, encoder: &'e mut wgpu::CommandEncoder) -> wgpu::RenderPass<'e> where 'a: 'e {
+        let color_attachments: SmallVec<[Option<wgpu::RenderPassColorAttachment<'a>>;1]> = self.target.layer_color_attachments(self.layer, self.color_ops());
+        let depth_stencil_attachment = match (self.target.layer_depth_view(self.layer), &self.target.depth) {
+            (Some(view), Some(depth)) => Some(wgpu::RenderPassDepthStencilAttachment {
+                view,
+                depth_ops: if depth.0.format.has_depth_aspect() { Some(self.depth_ops()) } else { None },
+                stencil_ops: if depth.0.format.has_stencil_aspect() { Some(self.stencil_ops()) } else { None },
+            }),
+            _ => None,
+        };
+        let mut renderpass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: self.label,
+            color_attachments: &color_attachments,
+            depth_stencil_attachment,
+            timestamp_writes: None,
+            occlusion_query_set: None,
+        });
+
+        let rect = self.rect_or_whole();
+        renderpass.set_scissor_rect(rect.min.x as u32, rect.min.y as u32, rect.width() as u32, rect.height() as u32);
+        if self.needs_region_clear() {
+            let pipeline = match self.region_clear {
+                Some(r) => r,
+                None => panic!("partial render pass needs a region clear pipeline, label: {:?}, rect: {:?}", self.label, rect),
+            };
+            let (x, y, w, h) = rect_viewport(&rect, 0., 0., 1., 1.);
+            renderpass.set_viewport(x, y, w, h, 0., 1.);
+            renderpass.set_pipeline(pipeline);
+            renderpass.set_blend_constant(self.clear_color.unwrap());
+            renderpass.draw(0..3, 0..1);
+        }
+        let (x, y, w, h, min_depth, max_depth) = self.viewport();
+        renderpass.set_viewport(x, y, w, h, min_depth, max_depth);
+        renderpass
+    }
+
+    /// 开始渲染通道 并绘制 DrawList
+    pub fn render(&self, encoder: &mut wgpu::CommandEncoder) {
+        let mut renderpass = self.begin(encoder);
+        if let Some(draws) = self.draws {
+            let rect = self.rect_or_whole();
+            DrawList::render_with_scissor(&draws.list, &mut renderpass, [rect.min.x as u32, rect.min.y as u32, rect.width() as u32, rect.height() as u32]);
+        }
+    }
+
+    fn rect_or_whole(&self) -> Rectangle {
+        self.rect.unwrap_or(Rectangle::new(
+            guillotiere::Point::new(0, 0),
+            guillotiere::Point::new(self.target.width as i32, self.target.height as i32),
+        ))
+    }
+}
+
+const REGION_CLEAR_VERTEX: &str = r#"
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4<f32> {
+    let uv = vec2<f32>(f32((index << 1u) & 2u), f32(index & 2u));
+    return vec4<f32>(uv * 2.0 - 1.0, 0.0, 1.0);
+}
+"#;
+
+/// 区域清屏
+/// * 绘制覆盖可用区域的三角形，颜色为混合常量（源因子 Constant，目标因子 Zero），不需要绑定组
+/// * 只写颜色，深度、模板不变；附件须可混合，整数格式不支持
+pub struct RegionClear {
+    /// (颜色格式, 深度格式, 采样数) -> 管线
+    pipelines: ShareMutex<XHashMap<(SmallVec<[wgpu::TextureFormat;1]>, Option<wgpu::TextureFormat>, u32), RenderPipeline>>,
+}
+
+impl Default for RegionClear {
+    fn default() -> Self {
+        Self { pipelines: ShareMutex::new(XHashMap::default()) }
+    }
+}
+
+impl RegionClear {
+    /// 与 target 附件格式匹配的清屏管线
+    pub fn pipeline(&self, device: &RenderDevice, target: &Fbo) -> RenderPipeline {
+        let colors: SmallVec<[wgpu::TextureFormat;1]> = target.colors.iter().map(|r| r.0.format).collect();
+        let depth = target.depth.as_ref().map(|r| r.0.format);
+        let key = (colors, depth, target.sample_count);
+        let mut pipelines = self.pipelines.lock();
+        if let Some(pipeline) = pipelines.get(&key) {
+            return pipeline.clone();
+        }
+
+        let (colors, depth, sample_count) = &key;
+        for format in colors.iter() {
+            if !format.guaranteed_format_features(device.features()).flags.contains(wgpu::TextureFormatFeatureFlags::BLENDABLE) {
+                panic!("region clear needs blendable format, found {:?}", format);
+            }
+        }
+        // 每个颜色附件一个输出
+        let outputs = (0..colors.len()).map(|i| format!("@location({}) c{}: vec4<f32>,", i, i)).collect::<Vec<_>>().join("\n    ");
+        let values = (0..colors.len()).map(|_| "vec4<f32>(1.0)").collect::<Vec<_>>().join(", ");
+        let shader = format!("{}\nstruct FragmentOutput {{\n    {}\n}};\n\n@fragment\nfn fs_main() -> FragmentOutput {{\n    return FragmentOutput({});\n}}\n", REGION_CLEAR_VERTEX, outputs, values);
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("region clear"),
+            source: wgpu::ShaderSource::Wgsl(shader.into()),
+        });
+        let blend = wgpu::BlendComponent { src_factor: wgpu::BlendFactor::Constant, dst_factor: wgpu::BlendFactor::Zero, operation: wgpu::BlendOperation::Add };
+        let targets: SmallVec<[Option<wgpu::ColorTargetState>;1]> = colors.iter().map(|format| Some(wgpu::ColorTargetState {
+            format: *format,
+            blend: Some(wgpu::BlendState { color: blend, alpha: blend }),
+            write_mask: wgpu::ColorWrites::ALL,
+        })).collect();
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("region clear"),
+            layout: None,
+            vertex: wgpu::VertexState { module: &shader, entry_point: "vs_main", buffers: &[] },
+            fragment: Some(wgpu::FragmentState { module: &shader, entry_point: "fs_main", targets: &targets }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: depth.map(|format| wgpu::DepthStencilState {
+                format,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState { count: *sample_count, ..Default::default() },
+            multiview: None,
+        });
+        pipelines.insert(key, pipeline.clone());
+        pipeline
+    }
+}
+
+fn load_op<V>(usage: AttachmentUsage, is_partial: bool, clear: V) -> wgpu::LoadOp<V> {
+    if usage.read_previous || is_partial {
+        wgpu::LoadOp::Load
+    } else {
+        wgpu::LoadOp::Clear(clear)
+    }
+}
+
+fn store_op(usage: AttachmentUsage, is_partial: bool) -> wgpu::StoreOp {
+    if usage.used_by_next || is_partial {
+        wgpu::StoreOp::Store
+    } else {
+        wgpu::StoreOp::Discard
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pi_assets::{asset::GarbageEmpty, homogeneous::HomogeneousMgr, mgr::AssetMgr};
+    use pi_share::Share;
+
+    use crate::{components::view::target_alloc::{SafeAtlasAllocator, ShareTargetView, TargetDescriptor, TextureDescriptor}, rhi::test_util::{read_pixel, run}};
+
+    use super::*;
+
+    #[test]
+    fn infer_load_store() {
+        assert_eq!(load_op(AttachmentUsage::new(false, true), false, 1.0), wgpu::LoadOp::Clear(1.0));
+        assert_eq!(load_op(AttachmentUsage::new(true, true), false, 1.0), wgpu::LoadOp::Load);
+        assert_eq!(load_op(AttachmentUsage::default(), true, 1.0), wgpu::LoadOp::Load);
+        assert_eq!(store_op(AttachmentUsage::default(), false), wgpu::StoreOp::Discard);
+        assert_eq!(store_op(AttachmentUsage::default(), true), wgpu::StoreOp::Store);
+        assert_eq!(store_op(AttachmentUsage::new(false, true), false), wgpu::StoreOp::Store);
+    }
+
+    #[test]
+    fn region_clear_partial() {
+        run(|device, queue| {
+            let allocator = SafeAtlasAllocator::new(
+                device.clone(),
+                AssetMgr::new(GarbageEmpty(), false, 64 * 1024 * 1024, 60 * 1000),
+                HomogeneousMgr::new(GarbageEmpty(), 64 * 1024 * 1024, 60 * 1000),
+                Share::new(pi_key_alloter::KeyAlloter::new(0)),
+            );
+            let color = TextureDescriptor {
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: wgpu::TextureFormat::Rgba8Unorm,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+                base_mip_level: 0,
+                base_array_layer: 0,
+                array_layer_count: None,
+                view_dimension: None,
+            };
+            let ty = allocator.get_or_create_type(TargetDescriptor { colors_descriptor: smallvec::smallvec![color], need_depth: true, depth_descriptor: None, default_width: 64, default_height: 64 });
+            let first = allocator.allocate(16, 16, ty, std::iter::empty::<&ShareTargetView>());
+            let second = allocator.allocate(16, 16, ty, std::iter::empty::<&ShareTargetView>());
+            assert!(Share::ptr_eq(first.target(), second.target()));
+
+            let region_clear = RegionClear::default();
+            let pipeline = region_clear.pipeline(&device, first.target());
+            let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+            for (view, color) in [(&first, wgpu::Color::RED), (&second, wgpu::Color::BLUE)] {
+                let builder = RenderPassBuilder::from_target_view(view).clear_color(color).region_clear(&pipeline);
+                assert!(builder.needs_region_clear());
+                assert_eq!(builder.color_ops().load, wgpu::LoadOp::Load);
+                drop(builder.begin(&mut encoder));
+            }
+            queue.submit(Some(encoder.finish()));
+
+            let texture = &first.target().colors[0].1;
+            let (a, b) = (first.rect(), second.rect());
+            assert_eq!(read_pixel(&device, &queue, texture, 0, a.min.x as u32, a.min.y as u32), [255, 0, 0, 255]);
+            assert_eq!(read_pixel(&device, &queue, texture, 0, (a.max.x - 1) as u32, (a.max.y - 1) as u32), [255, 0, 0, 255]);
+            assert_eq!(read_pixel(&device, &queue, texture, 0, b.min.x as u32, b.min.y as u32), [0, 0, 255, 255]);
+            // 可用区域外不变
+            assert_eq!(read_pixel(&device, &queue, texture, 0, 63, 63), [0, 0, 0, 0]);
+
+            // 未设置清屏管线
+            let r = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+                drop(RenderPassBuilder::from_target_view(&first).clear_color(wgpu::Color::RED).begin(&mut encoder));
+            }));
+            assert!(r.is_err());
+        });
+    }
+}
@@ -0,0 +1,283 @@
+//! HDR 图片解码
+//! * Radiance (.hdr) 内部解析，支持 RLE 与非压缩扫描线
+//! * OpenEXR (.exr) 需开启 feature "hdr_exr"
+//! * 统一解码为 RGBA f32，再按需转为 Rgba16Float / Rgba32Float / Rg11b10Float
+
+use pi_atom::Atom;
+use pi_hal::texture::ImageTexture;
+
+use crate::rhi::{device::RenderDevice, RenderQueue};
+
+use super::{f32_to_f16, f32_to_rg11b10};
+
+const RADIANCE_IDENTIFIERS: [&[u8]; 2] = [b"#?RADIANCE", b"#?RGBE"];
+const EXR_IDENTIFIER: [u8; 4] = [0x76, 0x2F, 0x31, 0x01];
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum EHdrError {
+    #[error("not a hdr image")]
+    InvalidIdentifier,
+    #[error("invalid radiance header: {0}")]
+    InvalidHeader(String),
+    #[error("unexpected end of file, offset {0}")]
+    UnexpectedEof(usize),
+    #[error("invalid run length at row {0}")]
+    InvalidRunLength(u32),
+    #[error("exr decode fail: {0}")]
+    Exr(String),
+    #[error("exr needs feature hdr_exr")]
+    ExrDisabled,
+}
+
+/// 上传的目标格式
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum EHdrTargetFormat {
+    Rgba16Float,
+    /// 未开启 FLOAT32_FILTERABLE 时不可线性过滤
+    Rgba32Float,
+    /// 每像素 4 字节，无 Alpha；图片有半透明时回退为 Rgba16Float
+    Rg11b10Float,
+}
+
+/// 解码后的 HDR 图片
+#[derive(Debug, Clone)]
+pub struct HdrImage {
+    pub width: u32,
+    pub height: u32,
+    /// RGBA，逐行自上而下
+    pub pixels: Vec<f32>,
+    /// 源数据是否带 Alpha 通道
+    pub has_alpha: bool,
+}
+
+impl HdrImage {
+    pub fn is_hdr(data: &[u8]) -> bool {
+        RADIANCE_IDENTIFIERS.iter().any(|id| data.starts_with(id)) || data.starts_with(&EXR_IDENTIFIER)
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, EHdrError> {
+        if RADIANCE_IDENTIFIERS.iter().any(|id| data.starts_with(id)) {
+            Self::parse_radiance(data)
+        } else if data.starts_with(&EXR_IDENTIFIER) {
+            Self::parse_exr(data)
+        } else {
+            Err(EHdrError::InvalidIdentifier)
+        }
+    }
+
+    /// 没有 Alpha 通道，或所有像素 Alpha 不小于 1
+    pub fn is_opacity(&self) -> bool {
+        !self.has_alpha || self.pixels.chunks_exact(4).all(|p| p[3] >= 1.)
+    }
+
+    /// 实际使用的格式，Rg11b10Float 不能表达半透明
+    pub fn target_format(&self, target: EHdrTargetFormat) -> wgpu::TextureFormat {
+        match target {
+            EHdrTargetFormat::Rgba16Float => wgpu::TextureFormat::Rgba16Float,
+            EHdrTargetFormat::Rgba32Float => wgpu::TextureFormat::Rgba32Float,
+            EHdrTargetFormat::Rg11b10Float => if self.is_opacity() { wgpu::TextureFormat::Rg11b10Float } else { wgpu::TextureFormat::Rgba16Float },
+        }
+    }
+
+    /// 按格式打包像素
+    pub fn to_bytes(&self, format: wgpu::TextureFormat) -> Vec<u8> {
+        match format {
+            wgpu::TextureFormat::Rgba32Float => bytemuck::cast_slice(&self.pixels).to_vec(),
+            wgpu::TextureFormat::Rg11b10Float => self.pixels.chunks_exact(4).flat_map(|p| f32_to_rg11b10(p[0], p[1], p[2]).to_le_bytes()).collect(),
+            _ => self.pixels.iter().flat_map(|v| f32_to_f16(*v).to_le_bytes()).collect(),
+        }
+    }
+
+    /// 创建单层 2D 纹理；立方体贴图见 ResImageTexture::create_cube_from_equirect
+    pub fn create_texture(&self, device: &RenderDevice, queue: &RenderQueue, key: &Atom, target: EHdrTargetFormat) -> ImageTexture {
+        let format = self.target_format(target);
+        let data = self.to_bytes(format);
+        let texture = (**device).create_texture(&wgpu::TextureDescriptor {
+            label: Some(key.as_str()),
+            size: wgpu::Extent3d { width: self.width, height: self.height, depth_or_array_layers: 1 },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        queue.write_texture(
+            texture.as_image_copy(),
+            &data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(self.width * format.block_copy_size(None).unwrap_or(8)),
+                rows_per_image: None,
+            },
+            wgpu::Extent3d { width: self.width, height: self.height, depth_or_array_layers: 1 },
+        );
+
+        ImageTexture {
+            width: self.width, height: self.height, size: data.len(), texture, format, view_dimension: wgpu::TextureViewDimension::D2, is_opacity: self.is_opacity()
+        }
+    }
+
+    fn parse_radiance(data: &[u8]) -> Result<Self, EHdrError> {
+        let mut offset = 0;
+
+        // 头部以空行结束
+        loop {
+            let header = radiance_line(data, &mut offset)?;
+            if header.is_empty() {
+                break;
+            }
+            if let Some(format) = header.strip_prefix(b"FORMAT=") {
+                if format != b"32-bit_rle_rgbe" {
+                    return Err(EHdrError::InvalidHeader(String::from_utf8_lossy(header).to_string()));
+                }
+            }
+        }
+        let resolution = String::from_utf8_lossy(radiance_line(data, &mut offset)?).to_string();
+        let parts: Vec<&str> = resolution.split_whitespace().collect();
+        let (flip, height, width) = match parts.as_slice() {
+            ["-Y", height, "+X", width] => (false, height, width),
+            ["+Y", height, "+X", width] => (true, height, width),
+            _ => return Err(EHdrError::InvalidHeader(resolution)),
+        };
+        let height: u32 = height.parse().map_err(|_| EHdrError::InvalidHeader(resolution.clone()))?;
+        let width: u32 = width.parse().map_err(|_| EHdrError::InvalidHeader(resolution.clone()))?;
+
+        let mut reader = RadianceReader { data, offset };
+        let mut pixels = vec![0.; (width * height) as usize * 4];
+        let mut scanline = vec![0u8; width as usize * 4];
+        for row in 0..height {
+            reader.scanline(width, row, &mut scanline)?;
+            let y = if flip { height - 1 - row } else { row };
+            let dst = &mut pixels[(y * width) as usize * 4..((y + 1) * width) as usize * 4];
+            for (rgbe, out) in scanline.chunks_exact(4).zip(dst.chunks_exact_mut(4)) {
+                let scale = if rgbe[3] == 0 { 0. } else { 2f32.powi(rgbe[3] as i32 - 136) };
+                out[0] = rgbe[0] as f32 * scale;
+                out[1] = rgbe[1] as f32 * scale;
+                out[2] = rgbe[2] as f32 * scale;
+                out[3] = 1.;
+            }
+        }
+
+        Ok(Self { width, height, pixels, has_alpha: false })
+    }
+
+    #[cfg(feature = "hdr_exr")]
+    fn parse_exr(data: &[u8]) -> Result<Self, EHdrError> {
+        use exr::prelude::*;
+
+        let image = read()
+            .no_deep_data()
+            .largest_resolution_level()
+            .rgba_channels(
+                |resolution, channels: &RgbaChannels| {
+                    (resolution.width(), channels.3.is_some(), vec![0f32; resolution.width() * resolution.height() * 4])
+                },
+                |(width, _, pixels), position, (r, g, b, a): (f32, f32, f32, f32)| {
+                    let index = (position.y() * *width + position.x()) * 4;
+                    pixels[index..index + 4].copy_from_slice(&[r, g, b, a]);
+                },
+            )
+            .first_valid_layer()
+            .all_attributes()
+            .from_buffered(std::io::Cursor::new(data))
+            .map_err(|e| EHdrError::Exr(e.to_string()))?;
+
+        let size = image.layer_data.size;
+        let (_, has_alpha, pixels) = image.layer_data.channel_data.pixels;
+        Ok(Self { width: size.width() as u32, height: size.height() as u32, pixels, has_alpha })
+    }
+
+    #[cfg(not(feature = "hdr_exr"))]
+    fn parse_exr(_data: &[u8]) -> Result<Self, EHdrError> {
+        Err(EHdrError::ExrDisabled)
+    }
+}
+
+fn radiance_line<'a>(data: &'a [u8], offset: &mut usize) -> Result<&'a [u8], EHdrError> {
+    let end = data[*offset..].iter().position(|v| *v == b'\n').ok_or(EHdrError::UnexpectedEof(data.len()))?;
+    let result = &data[*offset..*offset + end];
+    *offset += end + 1;
+    Ok(result)
+}
+
+struct RadianceReader<'a> {
+    data: &'a [u8],
+    offset: usize,
+}
+impl<'a> RadianceReader<'a> {
+    fn u8(&mut self) -> Result<u8, EHdrError> {
+        let result = *self.data.get(self.offset).ok_or(EHdrError::UnexpectedEof(self.offset))?;
+        self.offset += 1;
+        Ok(result)
+    }
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], EHdrError> {
+        let result = self.data.get(self.offset..self.offset + len).ok_or(EHdrError::UnexpectedEof(self.offset))?;
+        self.offset += len;
+        Ok(result)
+    }
+    /// 读取一行 RGBE
+    fn scanline(&mut self, width: u32, row: u32, out: &mut [u8]) -> Result<(), EHdrError> {
+        let width = width as usize;
+        let is_rle = (8..0x8000).contains(&width)
+            && self.data.get(self.offset..self.offset + 4).map_or(false, |head| head[0] == 2 && head[1] == 2 && head[2] & 0x80 == 0);
+        if !is_rle {
+            out.copy_from_slice(self.bytes(width * 4)?);
+            return Ok(());
+        }
+
+        let head = self.bytes(4)?;
+        if ((head[2] as usize) << 8 | head[3] as usize) != width {
+            return Err(EHdrError::InvalidRunLength(row));
+        }
+        // 四个通道分别游程编码
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = self.u8()? as usize;
+                if count > 128 {
+                    let count = count - 128;
+                    if count > width - x {
+                        return Err(EHdrError::InvalidRunLength(row));
+                    }
+                    let value = self.u8()?;
+                    (x..x + count).for_each(|i| out[i * 4 + channel] = value);
+                    x += count;
+                } else {
+                    if count == 0 || count > width - x {
+                        return Err(EHdrError::InvalidRunLength(row));
+                    }
+                    let values = self.bytes(count)?;
+                    values.iter().enumerate().for_each(|(i, value)| out[(x + i) * 4 + channel] = *value);
+                    x += count;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_radiance_rle() {
+        let mut data = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y 1 +X 8\n".to_vec();
+        data.extend_from_slice(&[2, 2, 0, 8]);
+        // R: 8 个 128；G: 8 个 64；B: 4 个 0 + 4 个 32；E: 8 个 129 (2^-7 * 128 = 1.0)
+        data.extend_from_slice(&[128 + 8, 128]);
+        data.extend_from_slice(&[128 + 8, 64]);
+        data.extend_from_slice(&[4, 0, 0, 0, 0, 128 + 4, 32]);
+        data.extend_from_slice(&[128 + 8, 129]);
+
+        let image = HdrImage::parse(&data).unwrap();
+        assert_eq!((image.width, image.height), (8, 1));
+        assert_eq!(&image.pixels[0..4], &[1., 0.5, 0., 1.]);
+        assert_eq!(&image.pixels[28..32], &[1., 0.5, 0.25, 1.]);
+        assert!(image.is_opacity());
+        assert_eq!(image.target_format(EHdrTargetFormat::Rg11b10Float), wgpu::TextureFormat::Rg11b10Float);
+        // 1.0: 指数 15，尾数 0
+        assert_eq!(f32_to_rg11b10(1., 0., 0.), 15 << 6);
+    }
+}
@@ -4,7 +4,7 @@ use pi_atom::Atom;
 use pi_hal::texture::ImageTexture;
 use pi_hash::XHashMap;
 
-use crate::{rhi::{device::RenderDevice, RenderQueue}, asset::TAssetKeyU64, renderer::buildin_data::DefaultTexture};
+use crate::{rhi::{device::RenderDevice, texture::mipmap::{self, MipmapGenerator}, RenderQueue}, asset::TAssetKeyU64, renderer::buildin_data::DefaultTexture};
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct TexturePath {
@@ -64,7 +64,27 @@ impl ResImageTexture {
         format: wgpu::TextureFormat, dimension: wgpu::TextureViewDimension, is_opacity: bool, depth_or_array_layers: u32, aspect: Option<wgpu::TextureAspect>,
         data: Option<&[u8]>, dataoffset: u64
     ) -> Self {
-        let texture = ResImageTexture::create_texture(device, key, width, height, format, dimension.compatible_texture_dimension(), depth_or_array_layers);
+        ResImageTexture::create_data_texture_inner(device, queue, key, width, height, format, dimension, is_opacity, depth_or_array_layers, aspect, data, dataoffset, None)
+    }
+    /// 同 create_data_texture，可生成 mipmap 的格式 创建完整 mip 链，data 写入第0级后在 GPU 上生成其余各级
+    /// * sRGB 由 key.srgb 决定
+    pub fn create_data_texture_with_mipmaps(
+        device: &RenderDevice, queue: &RenderQueue, key: &KeyImageTexture, width: u32, height: u32,
+        format: wgpu::TextureFormat, dimension: wgpu::TextureViewDimension, is_opacity: bool, depth_or_array_layers: u32, aspect: Option<wgpu::TextureAspect>,
+        data: Option<&[u8]>, dataoffset: u64, generator: &MipmapGenerator,
+    ) -> Self {
+        ResImageTexture::create_data_texture_inner(device, queue, key, width, height, format, dimension, is_opacity, depth_or_array_layers, aspect, data, dataoffset, Some(generator))
+    }
+    fn create_data_texture_inner(
+        device: &RenderDevice, queue: &RenderQueue, key: &KeyImageTexture, width: u32, height: u32,
+        format: wgpu::TextureFormat, dimension: wgpu::TextureViewDimension, is_opacity: bool, depth_or_array_layers: u32, aspect: Option<wgpu::TextureAspect>,
+        data: Option<&[u8]>, dataoffset: u64, generator: Option<&MipmapGenerator>,
+    ) -> Self {
+        let mip_level_count = match generator {
+            Some(_) if mipmap::is_mipmap_generatable(format) => mipmap::mip_level_count(width, height),
+            _ => 1,
+        };
+        let texture = ResImageTexture::create_texture_with_mips(device, key, width, height, format, dimension.compatible_texture_dimension(), depth_or_array_layers, mip_level_count);
 
         let (block_width, block_height) = format.block_dimensions();
         let mut extent_width    = width / block_width;
@@ -97,12 +117,15 @@ impl ResImageTexture {
                 },
                 texture_extent,
             );
+            if let (Some(generator), true) = (generator, mip_level_count > 1) {
+                generator.generate(device, queue, &texture, key.srgb);
+            }
         }
 
         // log::error!("{:?}", (key, width, height, format, dimension, depth_or_array_layers, block_width, block_height, extent_width, extent_height, bytes_per_row));
         let size = if let Some(bytes_per_row) = bytes_per_row { extent_height * bytes_per_row } else { extent_width * extent_height * 4 };
         let data: ImageTexture = ImageTexture {
-            width, height, size: size as usize, texture, format, view_dimension: dimension, is_opacity
+            width, height, size: mipmap::mipmap_size(size as usize, mip_level_count), texture, format, view_dimension: dimension, is_opacity
         };
         Self {
             data, extend: vec![]
@@ -115,6 +138,13 @@ impl ResImageTexture {
     pub fn create_texture(
         device: &RenderDevice, key: &KeyImageTexture, width: u32, height: u32,
         format: wgpu::TextureFormat, dimension: wgpu::TextureDimension, depth_or_array_layers: u32
+    ) -> wgpu::Texture {
+        ResImageTexture::create_texture_with_mips(device, key, width, height, format, dimension, depth_or_array_layers, 1)
+    }
+    /// mip_level_count > 1 时，纹理可作为渲染目标，供 MipmapGenerator 使用
+    pub fn create_texture_with_mips(
+        device: &RenderDevice, key: &KeyImageTexture, width: u32, height: u32,
+        format: wgpu::TextureFormat, dimension: wgpu::TextureDimension, depth_or_array_layers: u32, mip_level_count: u32,
     ) -> wgpu::Texture {
         let texture_extent = wgpu::Extent3d {
             width,
@@ -122,14 +152,18 @@ impl ResImageTexture {
             depth_or_array_layers,
         };
 
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC;
+        if mip_level_count > 1 {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
         let texture = (**device).create_texture(&wgpu::TextureDescriptor {
             label: Some(key.url.as_str()),
             size: texture_extent,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension,
             format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            usage,
             view_formats: &[],
         });
 
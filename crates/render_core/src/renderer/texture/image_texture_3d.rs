@@ -0,0 +1,68 @@
+//! 3D 纹理
+//! * 主要用于调色 LUT，可由横向排列的条带图创建
+
+use pi_hal::texture::ImageTexture;
+
+use crate::rhi::{device::RenderDevice, RenderQueue};
+
+use super::{image_texture::{KeyImageTexture, ResImageTexture}, TTextureFormatPixelByte};
+
+/// 条带图 (宽 size * size，高 size，第 z 片位于 x 的 [z * size, (z + 1) * size)) 重排为按 深度、行 排列的体数据
+pub fn lut_strip_to_volume(data: &[u8], size: u32, pixel_bytes: usize) -> Vec<u8> {
+    let size = size as usize;
+    let row = size * pixel_bytes;
+    let mut result = Vec::with_capacity(size * size * row);
+    for z in 0..size {
+        for y in 0..size {
+            let offset = y * size * row + z * row;
+            result.extend_from_slice(&data[offset..offset + row]);
+        }
+    }
+    result
+}
+
+impl ResImageTexture {
+    /// 创建 3D 纹理，data 按 深度、行 紧密排列
+    pub fn create_texture_3d(
+        device: &RenderDevice, queue: &RenderQueue, key: &KeyImageTexture, width: u32, height: u32, depth: u32,
+        format: wgpu::TextureFormat, data: &[u8],
+    ) -> Self {
+        let texture = ResImageTexture::create_texture(device, key, width, height, format, wgpu::TextureDimension::D3, depth);
+        let (block_width, block_height) = format.block_dimensions();
+        let bytes_per_row = format.block_copy_size(None).map(|size| (width + block_width - 1) / block_width * size);
+        queue.write_texture(
+            texture.as_image_copy(),
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row,
+                rows_per_image: Some((height + block_height - 1) / block_height),
+            },
+            wgpu::Extent3d { width, height, depth_or_array_layers: depth },
+        );
+
+        let data = ImageTexture {
+            width, height, size: data.len(), texture, format, view_dimension: wgpu::TextureViewDimension::D3, is_opacity: true
+        };
+        Self::new(data)
+    }
+    /// 由 size 级的 LUT 条带图创建 3D 纹理
+    pub fn create_lut_3d(
+        device: &RenderDevice, queue: &RenderQueue, key: &KeyImageTexture, size: u32, format: wgpu::TextureFormat, strip: &[u8],
+    ) -> Self {
+        let volume = lut_strip_to_volume(strip, size, format.pixel_bytes());
+        ResImageTexture::create_texture_3d(device, queue, key, size, size, size, format, &volume)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lut_strip_reorder() {
+        // 2 级 LUT，每像素 1 字节，条带图为 4x2：像素值 = z * 10 + y * 2 + x
+        let strip = [0, 1, 10, 11, 2, 3, 12, 13];
+        assert_eq!(lut_strip_to_volume(&strip, 2, 1), vec![0, 1, 2, 3, 10, 11, 12, 13]);
+    }
+}
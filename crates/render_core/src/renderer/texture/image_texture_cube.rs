@@ -0,0 +1,136 @@
+//! 立方体纹理
+//! * 由六张同尺寸图片 或 等距柱状投影(equirectangular)的 HDR 数据 创建
+//! * 面的顺序与 wgpu 的 array layer 一致: +X -X +Y -Y +Z -Z
+
+use std::f32::consts::PI;
+
+use pi_hal::texture::ImageTexture;
+
+use crate::rhi::{device::RenderDevice, texture::mipmap::{self, MipmapGenerator}, RenderQueue};
+
+use super::{f32_to_f16, image_texture::{KeyImageTexture, ResImageTexture}};
+
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+pub enum ECubeFace {
+    PositiveX,
+    NegativeX,
+    PositiveY,
+    NegativeY,
+    PositiveZ,
+    NegativeZ,
+}
+impl ECubeFace {
+    pub const ALL: [ECubeFace; 6] = [ECubeFace::PositiveX, ECubeFace::NegativeX, ECubeFace::PositiveY, ECubeFace::NegativeY, ECubeFace::PositiveZ, ECubeFace::NegativeZ];
+
+    /// 面上纹理坐标 (u, v) 对应的方向 (未归一化)，v 向下
+    pub fn direction(&self, u: f32, v: f32) -> [f32; 3] {
+        let s = u * 2. - 1.;
+        let t = v * 2. - 1.;
+        match self {
+            ECubeFace::PositiveX => [1., -t, -s],
+            ECubeFace::NegativeX => [-1., -t, s],
+            ECubeFace::PositiveY => [s, 1., t],
+            ECubeFace::NegativeY => [s, -1., -t],
+            ECubeFace::PositiveZ => [s, -t, 1.],
+            ECubeFace::NegativeZ => [-s, -t, -1.],
+        }
+    }
+}
+
+/// 等距柱状投影的 RGBA f32 数据 转为六个面的 RGBA f32 数据，各面依次紧密排列
+/// * 方向 (x, y, z) 对应 u = atan2(z, x) / 2π + 0.5, v = acos(y) / π，+Y 在图片顶部
+pub fn equirect_to_cube_faces(data: &[f32], width: u32, height: u32, face_size: u32) -> Vec<f32> {
+    let face_pixels = (face_size * face_size) as usize;
+    let mut result = vec![0.; face_pixels * 6 * 4];
+    for (index, face) in ECubeFace::ALL.iter().enumerate() {
+        for y in 0..face_size {
+            for x in 0..face_size {
+                let [dx, dy, dz] = face.direction((x as f32 + 0.5) / face_size as f32, (y as f32 + 0.5) / face_size as f32);
+                let len = (dx * dx + dy * dy + dz * dz).sqrt();
+                let u = dz.atan2(dx) / (2. * PI) + 0.5;
+                let v = (dy / len).clamp(-1., 1.).acos() / PI;
+                let offset = (index * face_pixels + (y * face_size + x) as usize) * 4;
+                sample_bilinear(data, width, height, u, v, &mut result[offset..offset + 4]);
+            }
+        }
+    }
+    result
+}
+
+/// 横向环绕、纵向截断 的双线性采样
+fn sample_bilinear(data: &[f32], width: u32, height: u32, u: f32, v: f32, out: &mut [f32]) {
+    let fx = u * width as f32 - 0.5;
+    let fy = (v * height as f32 - 0.5).clamp(0., (height - 1) as f32);
+    let x0 = fx.floor();
+    let y0 = fy.floor();
+    let (tx, ty) = (fx - x0, fy - y0);
+    let x0 = (x0 as i32).rem_euclid(width as i32) as u32;
+    let x1 = (x0 + 1) % width;
+    let y0 = y0 as u32;
+    let y1 = (y0 + 1).min(height - 1);
+    for (channel, value) in out.iter_mut().enumerate() {
+        let pixel = |x: u32, y: u32| data[((y * width + x) * 4) as usize + channel];
+        let top = pixel(x0, y0) * (1. - tx) + pixel(x1, y0) * tx;
+        let bottom = pixel(x0, y1) * (1. - tx) + pixel(x1, y1) * tx;
+        *value = top * (1. - ty) + bottom * ty;
+    }
+}
+
+impl ResImageTexture {
+    /// 由六张 size x size 的图片创建立方体纹理，faces 顺序同 ECubeFace::ALL
+    /// * generator 不为 None 且格式可生成 mipmap 时 创建完整 mip 链
+    pub fn create_cube(
+        device: &RenderDevice, queue: &RenderQueue, key: &KeyImageTexture, size: u32, format: wgpu::TextureFormat,
+        faces: [&[u8]; 6], generator: Option<&MipmapGenerator>,
+    ) -> Self {
+        let mip_level_count = match generator {
+            Some(_) if mipmap::is_mipmap_generatable(format) => mipmap::mip_level_count(size, size),
+            _ => 1,
+        };
+        let texture = ResImageTexture::create_texture_with_mips(device, key, size, size, format, wgpu::TextureDimension::D2, 6, mip_level_count);
+        for (layer, data) in faces.iter().enumerate() {
+            ResImageTexture::update_sub(&texture, queue, wgpu::Origin3d { x: 0, y: 0, z: layer as u32 }, size, size, 1, None, data, 0);
+        }
+        if let (Some(generator), true) = (generator, mip_level_count > 1) {
+            generator.generate(device, queue, &texture, key.srgb);
+        }
+
+        let data = ImageTexture {
+            width: size, height: size, size: mipmap::mipmap_size(faces[0].len() * 6, mip_level_count), texture, format,
+            view_dimension: wgpu::TextureViewDimension::Cube, is_opacity: true,
+        };
+        Self::new(data)
+    }
+    /// 由等距柱状投影的 RGBA f32 数据 创建 Rgba16Float 的立方体纹理，用于环境光照、天空盒
+    pub fn create_cube_from_equirect(
+        device: &RenderDevice, queue: &RenderQueue, key: &KeyImageTexture, data: &[f32], width: u32, height: u32, face_size: u32,
+        generator: Option<&MipmapGenerator>,
+    ) -> Self {
+        let faces = equirect_to_cube_faces(data, width, height, face_size);
+        let bytes: Vec<u8> = faces.iter().flat_map(|v| f32_to_f16(*v).to_le_bytes()).collect();
+        let face_bytes = (face_size * face_size) as usize * 8;
+        let faces = [0, 1, 2, 3, 4, 5].map(|i| &bytes[i * face_bytes..(i + 1) * face_bytes]);
+        ResImageTexture::create_cube(device, queue, key, face_size, wgpu::TextureFormat::Rgba16Float, faces, generator)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equirect_faces_sample_direction() {
+        // 左右两半分别为 0 与 1：u < 0.5 对应 z < 0
+        let (width, height) = (8, 4);
+        let mut data = vec![0.; width * height * 4];
+        for y in 0..height {
+            for x in width / 2..width {
+                data[(y * width + x) * 4] = 1.;
+            }
+        }
+        let faces = equirect_to_cube_faces(&data, width as u32, height as u32, 2);
+        let face_center = |face: usize| (0..4).map(|i| faces[face * 16 + i * 4]).sum::<f32>() / 4.;
+        assert!(face_center(4) > 0.9);
+        assert!(face_center(5) < 0.1);
+    }
+}
@@ -8,12 +8,12 @@ use pi_atom::Atom;
 use pi_futures::BoxFuture;
 use pi_hal::{image::DynamicImage, texture::ImageTexture};
 use pi_hash::DefaultHasher;
-use pi_share::Share;
+use pi_share::{Share, ShareMutex, ShareWeak};
 use wgpu::TextureView;
 
-use crate::{asset::TAssetKeyU64, renderer::buildin_data::DefaultTexture, rhi::{device::RenderDevice, sampler::SamplerDesc, RenderQueue}};
+use crate::{asset::TAssetKeyU64, renderer::buildin_data::DefaultTexture, rhi::{device::RenderDevice, sampler::SamplerDesc, texture::{alpha::{self, EAlphaMode}, mipmap::{self, MipmapGenerator}}, RenderQueue}};
 
-use super::TextureViewDesc;
+use super::{EHdrError, EHdrTargetFormat, EKtxError, ETextureLoadPath, HdrImage, TextureFormatNegotiator, TextureViewDesc};
 
 #[derive(Debug, Clone, Hash, PartialEq, Eq)]
 pub struct KeyImageTextureFrame {
@@ -40,24 +40,23 @@ impl std::ops::Deref for KeyImageTextureFrame {
         self.url.as_str()
     }
 }
-#[derive(Debug)]
-pub struct TextureFrame {
+/// 帧在图集中的位置，图集重排后改变
+#[derive(Debug, Clone, Copy)]
+pub struct FrameLocation {
     id: AllocId,
-    rect: (u16, u16, u16, u16, u16, u16),
-    depth_or_array_layer: usize,
-    seq: Share<SegQueue<(usize, AllocId)>>,
+    /// 内容区域 (x, y, w, h, 图集宽, 图集高)，不含间隙
+    pub rect: (u16, u16, u16, u16, u16, u16),
+    /// 内容四周的间隙 (横向, 纵向)
+    pub padding: (u16, u16),
+    /// 分配的区域尺寸 (宽, 高)，含间隙，起点与尺寸按块尺寸及 mip 对齐
+    pub slot: (u16, u16),
+    pub depth_or_array_layer: usize,
+    /// 每次被重排移动后加1
+    pub version: u32,
+    /// 帧已释放，等待回收
+    released: bool,
 }
-impl TextureFrame {
-    
-    pub fn tilloff(&self, result: &mut [f32], offset: usize) {
-        result[offset + 0] = self.rect.2 as f32 / self.rect.4 as f32;
-        result[offset + 1] = self.rect.3 as f32 / self.rect.5 as f32;
-        result[offset + 2] = self.rect.0 as f32 / self.rect.4 as f32;
-        result[offset + 3] = self.rect.1 as f32 / self.rect.5 as f32;
-    }
-    pub fn texture_coord(&self) -> usize {
-        self.depth_or_array_layer
-    }
+impl FrameLocation {
     pub fn copy_dst_orign(&self, format: wgpu::TextureFormat) -> wgpu::Origin3d {
         let (blockw, blockh) = format.block_dimensions();
         wgpu::Origin3d {
@@ -66,13 +65,73 @@ impl TextureFrame {
             z: self.depth_or_array_layer as u32,
         }
     }
+    /// 含间隙的区域 (x, y, w, h)
+    pub fn padded_rect(&self) -> (u32, u32, u32, u32) {
+        (
+            (self.rect.0 - self.padding.0) as u32,
+            (self.rect.1 - self.padding.1) as u32,
+            (self.rect.2 + self.padding.0 * 2) as u32,
+            (self.rect.3 + self.padding.1 * 2) as u32,
+        )
+    }
+    /// 分配的区域 (x, y, w, h)
+    pub fn slot_rect(&self) -> (u32, u32, u32, u32) {
+        (
+            (self.rect.0 - self.padding.0) as u32,
+            (self.rect.1 - self.padding.1) as u32,
+            self.slot.0 as u32,
+            self.slot.1 as u32,
+        )
+    }
+}
+
+type SharedFrameLocation = Share<ShareMutex<FrameLocation>>;
+
+pub struct TextureFrame {
+    location: SharedFrameLocation,
+    seq: Share<SegQueue<SharedFrameLocation>>,
+}
+impl std::fmt::Debug for TextureFrame {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TextureFrame").field("location", &self.location()).finish()
+    }
+}
+impl TextureFrame {
+    /// 当前位置，重排后需重新获取
+    pub fn location(&self) -> FrameLocation {
+        *self.location.lock()
+    }
+    pub fn tilloff(&self, result: &mut [f32], offset: usize) {
+        let location = self.location();
+        let rect = location.rect;
+        result[offset + 0] = rect.2 as f32 / rect.4 as f32;
+        result[offset + 1] = rect.3 as f32 / rect.5 as f32;
+        result[offset + 2] = rect.0 as f32 / rect.4 as f32;
+        result[offset + 3] = rect.1 as f32 / rect.5 as f32;
+    }
+    pub fn texture_coord(&self) -> usize {
+        self.location().depth_or_array_layer
+    }
+    pub fn copy_dst_orign(&self, format: wgpu::TextureFormat) -> wgpu::Origin3d {
+        self.location().copy_dst_orign(format)
+    }
+    /// 含间隙的区域 (x, y, w, h)
+    pub fn padded_rect(&self) -> (u32, u32, u32, u32) {
+        self.location().padded_rect()
+    }
+    pub fn version(&self) -> u32 {
+        self.location().version
+    }
 }
 impl Drop for TextureFrame {
     fn drop(&mut self) {
-        self.seq.push((self.depth_or_array_layer, self.id));
+        // 回收时读取最新位置，重排期间释放也能正确回收
+        self.location.lock().released = true;
+        self.seq.push(self.location.clone());
     }
 }
 
+
 pub struct ImageTextureFrame {
     frame: Option<TextureFrame>,
     size: usize,
@@ -87,6 +146,7 @@ impl ImageTextureFrame {
     }
     pub fn tilloff(&self) -> [f32;4] {
         if let Some(frame) = &self.frame {
+            let frame = frame.location();
             [
                 (frame.rect.2 as f32 / frame.rect.4 as f32), // * (u16::MAX as f32)) as u16,
                 (frame.rect.3 as f32 / frame.rect.5 as f32), // * (u16::MAX as f32)) as u16,
@@ -102,16 +162,41 @@ impl ImageTextureFrame {
     }
     pub fn update_texture(&self, queue: &RenderQueue, data: &[u8]) {
         if let Some(frame) = &self.frame {
+            let frame = frame.location();
             let format = self.tex.texture.format();
             let (blockw, blockh) = self.tex.texture.format().block_dimensions();
             let width = ((frame.rect.2 as u32 + blockw - 1) / blockw) * blockw;
             let height = ((frame.rect.3 as u32 + blockh - 1) / blockh) * blockh;
             let origin = frame.copy_dst_orign(format);
             // log::error!("{:?}", (&origin, width, height, (frame.rect.4 as u32 + blockw - 1) / blockw, (frame.rect.5 as u32 + blockh - 1) / blockh));
-            ImageTextureFrame::update_sub(&self.tex.texture, queue, origin,
-            width, height,
-                1, None, data, 0
-            );
+            if frame.padding == (0, 0) {
+                ImageTextureFrame::update_sub(&self.tex.texture, queue, origin,
+                width, height,
+                    1, None, data, 0
+                );
+            } else {
+                // 边缘像素（压缩格式为边缘块）向间隙延伸
+                let (padx, pady) = (frame.padding.0 as u32, frame.padding.1 as u32);
+                let blocksize = format.block_copy_size(None).unwrap_or(4) as usize;
+                let extruded = extrude_edges(data, (width / blockw) as usize, (height / blockh) as usize, blocksize, (padx / blockw) as usize, (pady / blockh) as usize);
+                let origin = wgpu::Origin3d { x: origin.x - padx, y: origin.y - pady, z: origin.z };
+                ImageTextureFrame::update_sub(&self.tex.texture, queue, origin,
+                width + padx * 2, height + pady * 2,
+                    1, None, &extruded, 0
+                );
+            }
+        }
+    }
+    /// 生成 mip 链（仅包含在图集中的帧有效），采样不越过帧的边界
+    /// * srgb - 纹理为非 sRGB 格式，但存放的是 sRGB 数据
+    pub fn generate_mipmaps(&self, generator: &MipmapGenerator, device: &RenderDevice, queue: &RenderQueue, srgb: bool) {
+        match &self.frame {
+            Some(frame) => {
+                let frame = frame.location();
+                let region = frame.padded_rect();
+                generator.generate_regions(device, queue, &self.tex.texture, frame.depth_or_array_layer as u32, &[region], srgb);
+            },
+            None => generator.generate(device, queue, &self.tex.texture, srgb),
         }
     }
     pub fn create_image(
@@ -119,6 +204,41 @@ impl ImageTextureFrame {
         dimension: wgpu::TextureViewDimension,
         data: DynamicImage,
     ) -> Option<ImageTexture> {
+        ImageTextureFrame::create_image_inner(device, queue, key, dimension, data, None, false).map(|(image, _)| image)
+    }
+    /// 同 create_image，可生成 mipmap 的格式 创建完整 mip 链 并在 GPU 上生成
+    /// * srgb - 图片数据为 sRGB 编码
+    pub fn create_image_with_mipmaps(
+        device: &RenderDevice, queue: &RenderQueue, key: &Atom,
+        dimension: wgpu::TextureViewDimension,
+        data: DynamicImage,
+        generator: &MipmapGenerator, srgb: bool,
+    ) -> Option<ImageTexture> {
+        ImageTextureFrame::create_image_inner(device, queue, key, dimension, data, Some((generator, srgb)), false).map(|(image, _)| image)
+    }
+    /// 同 create_image，同时返回透明类型
+    /// * premultiply - 有透明像素时 颜色预乘 Alpha
+    /// * generator 不为 None 时同 create_image_with_mipmaps
+    pub fn create_image_with_alpha(
+        device: &RenderDevice, queue: &RenderQueue, key: &Atom,
+        dimension: wgpu::TextureViewDimension,
+        data: DynamicImage,
+        generator: Option<&MipmapGenerator>, srgb: bool, premultiply: bool,
+    ) -> Option<(ImageTexture, EAlphaMode)> {
+        ImageTextureFrame::create_image_inner(device, queue, key, dimension, data, generator.map(|r| (r, srgb)), premultiply)
+    }
+    fn create_image_inner(
+        device: &RenderDevice, queue: &RenderQueue, key: &Atom,
+        dimension: wgpu::TextureViewDimension,
+        mut data: DynamicImage,
+        mipmap: Option<(&MipmapGenerator, bool)>,
+        premultiply: bool,
+    ) -> Option<(ImageTexture, EAlphaMode)> {
+        // 无 Alpha 通道的格式不扫描
+        let alpha = alpha::image_alpha_mode(&data);
+        if premultiply && alpha != EAlphaMode::Opaque {
+            alpha::premultiply_alpha(&mut data);
+        }
         let width = data.width();
         let height = data.height();
         let depth_or_array_layers = 1;
@@ -161,7 +281,11 @@ impl ImageTextureFrame {
             },
             _ => return None,
         };
-        let texture = ImageTextureFrame::create_texture(device, key, width, height, format, dimension.compatible_texture_dimension(), depth_or_array_layers);
+        let mip_level_count = match mipmap {
+            Some(_) if mipmap::is_mipmap_generatable(format) => mipmap::mip_level_count(width, height),
+            _ => 1,
+        };
+        let texture = ImageTextureFrame::create_texture_with_mips(device, key, width, height, format, dimension.compatible_texture_dimension(), depth_or_array_layers, mip_level_count);
 
         let (block_width, block_height) = format.block_dimensions();
         let extent_width    = (width  + block_width  - 1) / block_width;
@@ -187,12 +311,25 @@ impl ImageTextureFrame {
             texture_extent,
         );
 
+        if let (Some((generator, srgb)), true) = (mipmap, mip_level_count > 1) {
+            generator.generate(device, queue, &texture, srgb);
+        }
+
         // log::error!("{:?}", (key, width, height, format, dimension, depth_or_array_layers, block_width, block_height, extent_width, extent_height, bytes_per_row));
         let size = if let Some(bytes_per_row) = bytes_per_row { extent_height * bytes_per_row } else { extent_width * extent_height * 4 };
-        Some(ImageTexture {
-            width, height, size: size as usize, texture, format, view_dimension: dimension, is_opacity: true
-        })
+        Some((ImageTexture {
+            width, height, size: mipmap::mipmap_size(size as usize, mip_level_count), texture, format, view_dimension: dimension, is_opacity: alpha.is_opacity()
+        }, alpha))
     }
+    /// 由 .hdr / .exr 数据创建 2D 纹理，is_opacity 按 Alpha 通道检测
+    pub fn create_hdr(
+        device: &RenderDevice, queue: &RenderQueue, key: &Atom,
+        data: &[u8], target: EHdrTargetFormat,
+    ) -> Result<ImageTexture, EHdrError> {
+        let image = HdrImage::parse(data)?;
+        Ok(image.create_texture(device, queue, key, target))
+    }
+    /// 仅支持单个面、单层、无 mip 的 KTX1；完整支持见 create_ktx_data
     pub fn create_ktx(
         device: &RenderDevice, queue: &RenderQueue, key: &Atom,
         dimension: wgpu::TextureViewDimension,
@@ -212,15 +349,43 @@ impl ImageTextureFrame {
         }
         None
     }
+    /// 从 KTX1 / KTX2 文件数据创建纹理，包含完整 mip 链、立方体贴图、数组
+    /// * TextureViewDimension 由文件内容决定
+    /// * 设备不支持的压缩格式 在 CPU 上解码为 RGBA8，返回实际走的路径
+    pub fn create_ktx_data(
+        device: &RenderDevice, queue: &RenderQueue, key: &Atom,
+        data: &[u8],
+    ) -> Result<(ImageTexture, ETextureLoadPath), EKtxError> {
+        ImageTextureFrame::create_ktx_negotiated(device, queue, key, data, &TextureFormatNegotiator::new(device.features()))
+    }
+    /// 同 create_ktx_data，使用指定的协商器（如带 Basis 转码器）
+    pub fn create_ktx_negotiated(
+        device: &RenderDevice, queue: &RenderQueue, key: &Atom,
+        data: &[u8], negotiator: &TextureFormatNegotiator,
+    ) -> Result<(ImageTexture, ETextureLoadPath), EKtxError> {
+        let (ktx, path) = negotiator.load_ktx(data)?;
+        if let ETextureLoadPath::Native(_) = path {} else {
+            log::info!("ktx {:?} load path: {:?}", key.as_str(), path);
+        }
+        Ok((ktx.create_texture(device, queue, key), path))
+    }
     pub fn create_data_texture(
         device: &RenderDevice, queue: &RenderQueue, key: &Atom, width: u32, height: u32,
         format: wgpu::TextureFormat, dimension: wgpu::TextureViewDimension, is_opacity: bool, depth_or_array_layers: u32, aspect: Option<wgpu::TextureAspect>,
         data: Option<&[u8]>, dataoffset: u64
+    ) -> ImageTexture {
+        ImageTextureFrame::create_data_texture_with_mips(device, queue, key, width, height, format, dimension, is_opacity, depth_or_array_layers, 1, aspect, data, dataoffset)
+    }
+    /// 同 create_data_texture，data 只写入第0级，其余各级由 MipmapGenerator 生成
+    pub fn create_data_texture_with_mips(
+        device: &RenderDevice, queue: &RenderQueue, key: &Atom, width: u32, height: u32,
+        format: wgpu::TextureFormat, dimension: wgpu::TextureViewDimension, is_opacity: bool, depth_or_array_layers: u32, mip_level_count: u32, aspect: Option<wgpu::TextureAspect>,
+        data: Option<&[u8]>, dataoffset: u64
     ) -> ImageTexture {
         let (block_width, block_height) = format.block_dimensions();
         let mut extent_width    = (width + block_width - 1) / block_width;
         let mut extent_height   = (height + block_height - 1) / block_height;
-        let texture = ImageTextureFrame::create_texture(device, key, extent_width * block_width, extent_height * block_height, format, dimension.compatible_texture_dimension(), depth_or_array_layers);
+        let texture = ImageTextureFrame::create_texture_with_mips(device, key, extent_width * block_width, extent_height * block_height, format, dimension.compatible_texture_dimension(), depth_or_array_layers, mip_level_count);
 
         let bytes_per_row = if let Some(pre_pixel_size) = format.block_copy_size(aspect) {
             Some(extent_width * pre_pixel_size)
@@ -256,12 +421,19 @@ impl ImageTextureFrame {
         // log::error!("{:?}", (key, width, height, format, dimension, depth_or_array_layers, block_width, block_height, extent_width, extent_height, bytes_per_row));
         let size = if let Some(bytes_per_row) = bytes_per_row { extent_height * bytes_per_row } else { extent_width * extent_height * 4 };
         ImageTexture {
-            width, height, size: size as usize, texture, format, view_dimension: dimension, is_opacity
+            width, height, size: mipmap::mipmap_size(size as usize, mip_level_count), texture, format, view_dimension: dimension, is_opacity
         }
     }
     pub fn create_texture(
         device: &RenderDevice, key: &Atom, width: u32, height: u32,
         format: wgpu::TextureFormat, dimension: wgpu::TextureDimension, depth_or_array_layers: u32
+    ) -> wgpu::Texture {
+        ImageTextureFrame::create_texture_with_mips(device, key, width, height, format, dimension, depth_or_array_layers, 1)
+    }
+    /// mip_level_count > 1 时，纹理可作为渲染目标，供 MipmapGenerator 使用
+    pub fn create_texture_with_mips(
+        device: &RenderDevice, key: &Atom, width: u32, height: u32,
+        format: wgpu::TextureFormat, dimension: wgpu::TextureDimension, depth_or_array_layers: u32, mip_level_count: u32,
     ) -> wgpu::Texture {
         let texture_extent = wgpu::Extent3d {
             width,
@@ -269,14 +441,18 @@ impl ImageTextureFrame {
             depth_or_array_layers,
         };
 
+        let mut usage = wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC;
+        if mip_level_count > 1 {
+            usage |= wgpu::TextureUsages::RENDER_ATTACHMENT;
+        }
         let texture = (**device).create_texture(&wgpu::TextureDescriptor {
             label: Some(key.as_str()),
             size: texture_extent,
-            mip_level_count: 1,
+            mip_level_count,
             sample_count: 1,
             dimension,
             format,
-            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            usage,
             view_formats: &[],
         });
 
@@ -314,7 +490,15 @@ impl ImageTextureFrame {
     }
     pub fn coord(&self) -> u8 {
         if let Some(frame) = &self.frame {
-            frame.depth_or_array_layer as u8
+            frame.texture_coord() as u8
+        } else {
+            0
+        }
+    }
+    /// 在图集中被重排移动后加1，变化时应重新获取 tilloff、coord
+    pub fn version(&self) -> u32 {
+        if let Some(frame) = &self.frame {
+            frame.version()
         } else {
             0
         }
@@ -337,32 +521,37 @@ pub struct Atlas {
     format: wgpu::TextureFormat,
     texture: Arc<ImageTexture>,
     key_image_texture_2d_array: Option<u64>,
-    recycle: Share<SegQueue<(usize, AllocId)>>,
+    recycle: Share<SegQueue<SharedFrameLocation>>,
+    padding: u32,
+    /// 已分配的帧，重排时使用
+    frames: Vec<ShareWeak<ShareMutex<FrameLocation>>>,
 }
 impl Atlas {
     ///
     /// maxcount 可容纳图块的数目最大值, 根据 bindbuffer
     pub fn new(key: u64, maxwidth: u32, maxheight: u32, depth_or_array_layers: u32, format: wgpu::TextureFormat,
         device: &RenderDevice, queue: &RenderQueue,
+    ) -> Self {
+        Atlas::new_with_mips(key, maxwidth, maxheight, depth_or_array_layers, format, 1, device, queue)
+    }
+    /// mip_level_count > 1 时，分配的帧按 2^(mip_level_count - 1) 对齐，各级中帧之间互不重叠
+    pub fn new_with_mips(key: u64, maxwidth: u32, maxheight: u32, depth_or_array_layers: u32, format: wgpu::TextureFormat, mip_level_count: u32,
+        device: &RenderDevice, queue: &RenderQueue,
     ) -> Self {
         let dimension = wgpu::TextureViewDimension::D2Array;
         let aspect = None;
         let data = None;
         let dataoffset = 0;
         let akey = Atom::from(key.to_string());
-        let texture = ImageTextureFrame::create_data_texture(device, queue, &akey, maxwidth, maxheight, format, dimension, true, depth_or_array_layers, aspect, data, dataoffset);
+        let mip_level_count = if mipmap::is_mipmap_generatable(format) { mip_level_count.clamp(1, mipmap::mip_level_count(maxwidth, maxheight)) } else { 1 };
+        let texture = ImageTextureFrame::create_data_texture_with_mips(device, queue, &akey, maxwidth, maxheight, format, dimension, true, depth_or_array_layers, mip_level_count, aspect, data, dataoffset);
         let (blockw, blockh) = format.block_dimensions();
+        let mip_align = 1 << (mip_level_count - 1);
+        let (blockw, blockh) = (blockw.max(mip_align), blockh.max(mip_align));
 
         let mut allocator = Vec::with_capacity(depth_or_array_layers as usize);
         for _ in 0..depth_or_array_layers {
-            allocator.push(AtlasAllocator::with_options(
-                guillotiere::Size { width: maxwidth as i32, height: maxheight as i32, ..Default::default() },
-                &AllocatorOptions {
-                    alignment: guillotiere::Size { width: blockw as i32, height: blockh as i32, ..Default::default() },
-                    small_size_threshold: maxwidth as i32 / 16,
-                    large_size_threshold: maxwidth as i32 /  4,
-                }
-            ));
+            allocator.push(Atlas::create_allocator(maxwidth, maxheight, blockw, blockh));
         }
         let recycle = Share::new(SegQueue::default());
         
@@ -378,25 +567,134 @@ impl Atlas {
             key_image_texture_2d_array: Some(temp.asset_u64()),
             texture: Arc::new(texture),
             recycle,
+            padding: 0,
+            frames: vec![],
         }
     }
-    pub fn allocate(&mut self, mut width: u32, mut height: u32) -> Option<ImageTextureFrame> {
-        let mut result = None;
-        while let Some((idx, id))  = self.recycle.pop() {
-            if let Some(allocator) = self.allocator.get_mut(idx) {
-                allocator.deallocate(id);
+    fn create_allocator(maxwidth: u32, maxheight: u32, blockw: u32, blockh: u32) -> AtlasAllocator {
+        AtlasAllocator::with_options(
+            guillotiere::Size { width: maxwidth as i32, height: maxheight as i32, ..Default::default() },
+            &AllocatorOptions {
+                alignment: guillotiere::Size { width: blockw as i32, height: blockh as i32, ..Default::default() },
+                small_size_threshold: maxwidth as i32 / 16,
+                large_size_threshold: maxwidth as i32 /  4,
+            }
+        )
+    }
+    fn recycle(&mut self) {
+        let mut recycled = false;
+        while let Some(location) = self.recycle.pop() {
+            let location = location.lock();
+            if let Some(allocator) = self.allocator.get_mut(location.depth_or_array_layer) {
+                allocator.deallocate(location.id);
+            }
+            recycled = true;
+        }
+        // 回收后 帧的位置不再被引用，移除失效的弱引用
+        if recycled {
+            self.frames.retain(|r| r.upgrade().is_some());
+        }
+    }
+    /// 存活帧的数目
+    pub fn frame_count(&self) -> usize {
+        self.frames.iter().filter(|r| r.upgrade().map_or(false, |r| !r.lock().released)).count()
+    }
+    /// 重排：存活的帧按尺寸从大到小重新分配，像素经中间纹理在 GPU 上拷贝到新位置
+    /// * 位置改变的帧版本号加1，持有者应重新获取 tilloff、coord
+    /// * 放不下时不做任何修改，返回 None；否则返回移动的帧数
+    pub fn repack(&mut self, device: &RenderDevice, queue: &RenderQueue) -> Option<usize> {
+        self.recycle();
+        let mut lives: Vec<SharedFrameLocation> = self.frames.iter().filter_map(|r| r.upgrade()).filter(|r| !r.lock().released).collect();
+        self.frames = lives.iter().map(Share::downgrade).collect();
+        lives.sort_by_key(|r| {
+            let (_, _, w, h) = r.lock().slot_rect();
+            std::cmp::Reverse((h, w))
+        });
+
+        let texture = &self.texture.texture;
+        let (blockw, blockh) = texture.format().block_dimensions();
+        let mip_align = 1 << (texture.mip_level_count() - 1);
+        let mut allocators: Vec<AtlasAllocator> = (0..self.allocator.len()).map(|_| Atlas::create_allocator(self.maxwidth, self.maxheight, blockw.max(mip_align), blockh.max(mip_align))).collect();
+        let mut moves = Vec::with_capacity(lives.len());
+        for live in lives.iter() {
+            let old = *live.lock();
+            let (_, _, w, h) = old.slot_rect();
+            let (layer, alloc) = allocators.iter_mut().enumerate().find_map(|(layer, allocator)| {
+                allocator.allocate(guillotiere::Size { width: w as i32, height: h as i32, ..Default::default() }).map(|r| (layer, r))
+            })?;
+            let mut new = old;
+            new.id = alloc.id;
+            new.rect.0 = alloc.rectangle.min.x as u16 + old.padding.0;
+            new.rect.1 = alloc.rectangle.min.y as u16 + old.padding.1;
+            new.depth_or_array_layer = layer;
+            if (new.rect.0, new.rect.1, new.depth_or_array_layer) != (old.rect.0, old.rect.1, old.depth_or_array_layer) {
+                new.version += 1;
+            }
+            moves.push((old, new));
+        }
+        // 位置未变的帧不需要拷贝，移动的帧不会覆盖它们
+        let moved: Vec<&(FrameLocation, FrameLocation)> = moves.iter().filter(|(old, new)| old.version != new.version).collect();
+
+        if !moved.is_empty() {
+            // 同一纹理内的区域可能重叠，先拷贝到中间纹理，再拷贝回来
+            let temp = (**device).create_texture(&wgpu::TextureDescriptor {
+                label: Some("AtlasRepack"),
+                size: texture.size(),
+                mip_level_count: texture.mip_level_count(),
+                sample_count: 1,
+                dimension: texture.dimension(),
+                format: texture.format(),
+                usage: wgpu::TextureUsages::COPY_SRC | wgpu::TextureUsages::COPY_DST,
+                view_formats: &[],
+            });
+            let mut encoder = (**device).create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("AtlasRepack") });
+            for level in 0..texture.mip_level_count() {
+                for (old, new) in moved.iter() {
+                    copy_frame_region(&mut encoder, texture, &temp, level, old, new);
+                }
+                for (_, new) in moved.iter() {
+                    copy_frame_region(&mut encoder, &temp, texture, level, new, new);
+                }
             }
+            queue.submit(Some(encoder.finish()));
+        }
+        let moved = moved.len();
+
+        // 重排期间释放的帧已在回收队列中，回收时按新位置释放
+        for (live, (_, new)) in lives.iter().zip(moves.iter()) {
+            let mut location = live.lock();
+            let released = location.released;
+            *location = *new;
+            location.released = released;
         }
+        self.allocator = allocators;
+        Some(moved)
+    }
+    /// 之后分配的帧 四周留出的间隙，向上对齐到块尺寸
+    pub fn set_padding(&mut self, padding: u32) {
+        self.padding = padding;
+    }
+    pub fn allocate(&mut self, mut width: u32, mut height: u32) -> Option<ImageTextureFrame> {
+        let mut result = None;
+        self.recycle();
         let mut idx = 0;
         let format = self.texture.texture.format();
         let (blockw, blockh) = format.block_dimensions();
+        let mip_align = 1 << (self.texture.texture.mip_level_count() - 1);
+        // 内容与间隙只按块尺寸对齐，tilloff 与 update_texture 使用内容的实际尺寸
         width  = (width  + blockw - 1) / blockw * blockw;
         height = (height + blockh - 1) / blockh * blockh;
+        let padx = (self.padding + blockw - 1) / blockw * blockw;
+        let pady = (self.padding + blockh - 1) / blockh * blockh;
+        // 分配的区域再按 mip 对齐（块尺寸与 mip 对齐均为2的幂，取大者即可），各级 mip 中帧之间互不重叠
+        let (alignw, alignh) = (blockw.max(mip_align), blockh.max(mip_align));
+        let slotw = (width  + padx * 2 + alignw - 1) / alignw * alignw;
+        let sloth = (height + pady * 2 + alignh - 1) / alignh * alignh;
         for allocator in self.allocator.iter_mut() {
-            if let Some(rect) = allocator.allocate(guillotiere::Size { width: width as i32, height: height as i32, ..Default::default() }) {
+            if let Some(rect) = allocator.allocate(guillotiere::Size { width: slotw as i32, height: sloth as i32, ..Default::default() }) {
                 // log::error!("Alloc: {:?}", (rect.rectangle.min.x, rect.rectangle.min.y, idx, width, height, ));
-                let ox = rect.rectangle.min.x as u16;
-                let oy = rect.rectangle.min.y as u16;
+                let ox = (rect.rectangle.min.x as u32 + padx) as u16;
+                let oy = (rect.rectangle.min.y as u32 + pady) as u16;
                 let sx = width  as u16;
                 let sy = height as u16;
                 let w = self.maxwidth  as u16;
@@ -405,15 +703,23 @@ impl Atlas {
                     size
                 } else { 1 };
 
+                let location = Share::new(ShareMutex::new(FrameLocation {
+                    id: rect.id,
+                    rect: (ox, oy, sx, sy, w, h),
+                    padding: (padx as u16, pady as u16),
+                    slot: (slotw as u16, sloth as u16),
+                    depth_or_array_layer: idx,
+                    version: 0,
+                    released: false,
+                }));
+                self.frames.push(Share::downgrade(&location));
                 result = Some(ImageTextureFrame {
                     frame: Some(TextureFrame {
-                        depth_or_array_layer: idx,
-                        id: rect.id,
+                        location,
                         seq: self.recycle.clone(),
-                        rect: (ox, oy, sx, sy, w, h),
                     }),
                     tex: self.texture.clone(),
-                    size: (blocksize * width / blockw * height / blockh) as usize,
+                    size: (blocksize * slotw / blockw * sloth / blockh) as usize,
                     extend: vec![],
                     atlashash: self.key_image_texture_2d_array
                 });
@@ -427,12 +733,55 @@ impl Atlas {
     }
 }
 
+/// 拷贝 from 的分配区域 到 to 的位置，尺寸按 mip 级缩小
+fn copy_frame_region(encoder: &mut wgpu::CommandEncoder, src: &wgpu::Texture, dst: &wgpu::Texture, level: u32, from: &FrameLocation, to: &FrameLocation) {
+    let (fx, fy, w, h) = from.slot_rect();
+    let (tx, ty, _, _) = to.slot_rect();
+    encoder.copy_texture_to_texture(
+        wgpu::ImageCopyTexture {
+            texture: src,
+            mip_level: level,
+            origin: wgpu::Origin3d { x: fx >> level, y: fy >> level, z: from.depth_or_array_layer as u32 },
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::ImageCopyTexture {
+            texture: dst,
+            mip_level: level,
+            origin: wgpu::Origin3d { x: tx >> level, y: ty >> level, z: to.depth_or_array_layer as u32 },
+            aspect: wgpu::TextureAspect::All,
+        },
+        wgpu::Extent3d { width: (w >> level).max(1), height: (h >> level).max(1), depth_or_array_layers: 1 },
+    );
+}
+
+/// 将 width * height 个单元（像素或压缩块）的数据 四周各延伸 padx、pady 个单元，边缘单元重复
+/// * unit - 每个单元的字节数
+fn extrude_edges(data: &[u8], width: usize, height: usize, unit: usize, padx: usize, pady: usize) -> Vec<u8> {
+    let row = width * unit;
+    let padded_width = width + padx * 2;
+    let mut result = Vec::with_capacity(padded_width * (height + pady * 2) * unit);
+    for y in 0..height + pady * 2 {
+        let src = &data[y.saturating_sub(pady).min(height - 1) * row..][..row];
+        for _ in 0..padx {
+            result.extend_from_slice(&src[..unit]);
+        }
+        result.extend_from_slice(src);
+        for _ in 0..padx {
+            result.extend_from_slice(&src[row - unit..]);
+        }
+    }
+    result
+}
+
 pub struct CombineAtlas2DMgr {
     atlasarr: Vec<Atlas>,
     format: wgpu::TextureFormat,
     maxcount: usize,
     maxlayer: u32,
     maxsize: u32,
+    mip_level_count: u32,
+    padding: u32,
+    version: u32,
 }
 impl CombineAtlas2DMgr {
     pub fn new(
@@ -445,7 +794,44 @@ impl CombineAtlas2DMgr {
         let limit = device.limits();
         let maxsize  = maxsize.min(limit.max_texture_dimension_2d);
         let maxlayer = maxlayer.min(limit.max_texture_array_layers);
-        Self { atlasarr: vec![], format, maxcount, maxlayer, maxsize }
+        Self { atlasarr: vec![], format, maxcount, maxlayer, maxsize, mip_level_count: 1, padding: 0, version: 0 }
+    }
+    /// 之后创建的图集 带 mip 链，帧分配后需调用 ImageTextureFrame::generate_mipmaps
+    pub fn new_with_mips(
+        device: &RenderDevice, 
+        format: wgpu::TextureFormat,
+        maxlayer: u32,
+        maxsize: u32,
+        maxcount: usize,
+        mip_level_count: u32,
+    ) -> Self {
+        let mut result = Self::new(device, format, maxlayer, maxsize, maxcount);
+        result.mip_level_count = mip_level_count.max(1);
+        result
+    }
+    /// 帧四周的间隙，update_texture 时边缘像素延伸到间隙中，避免双线性与 mipmap 采样到相邻的帧
+    /// * 只影响之后分配的帧
+    pub fn set_padding(&mut self, padding: u32) {
+        self.padding = padding;
+        self.atlasarr.iter_mut().for_each(|atlas| atlas.set_padding(padding));
+    }
+    /// 每次重排移动了帧后加1，变化时 ImageTextureFrame 的持有者应重新获取 tilloff
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+    /// 重排所有图集，返回移动的帧数；放不下的图集保持原样
+    pub fn repack(&mut self, device: &RenderDevice, queue: &RenderQueue) -> usize {
+        let mut count = 0;
+        for atlas in self.atlasarr.iter_mut() {
+            match atlas.repack(device, queue) {
+                Some(moved) => count += moved,
+                None => log::warn!("atlas repack fail, frames: {}", atlas.frame_count()),
+            }
+        }
+        if count > 0 {
+            self.version += 1;
+        }
+        count
     }
     pub fn combine(&mut self,
         format: wgpu::TextureFormat,
@@ -467,10 +853,15 @@ impl CombineAtlas2DMgr {
                 self.format.hash(&mut hasher);
                 idx.hash(&mut hasher);
                 let key = hasher.finish();
-                let mut atlas = Atlas::new(key, self.maxsize, self.maxsize, self.maxlayer, self.format, device, queue);
+                let mut atlas = Atlas::new_with_mips(key, self.maxsize, self.maxsize, self.maxlayer, self.format, self.mip_level_count, device, queue);
+                atlas.set_padding(self.padding);
                 frame = atlas.allocate(width, height);
                 self.atlasarr.push(atlas);
             }
+            // 图集数目已达上限，重排整理碎片后再尝试
+            if frame.is_none() && idx >= self.maxcount && self.repack(device, queue) > 0 {
+                frame = self.atlasarr.iter_mut().find_map(|atlas| atlas.allocate(width, height));
+            }
             frame
         } else {
             // log::error!("Combin Faile A");
@@ -574,3 +965,112 @@ pub type EImageTextureViewUsage = Handle<ImageTextureViewFrame>;
 //     Handle(Handle<ImageTextureViewFrame>),
 //     Arc(Arc<ImageTextureViewFrame>),
 // }
+
+#[cfg(test)]
+mod tests {
+    use crate::rhi::test_util::run;
+
+    use super::*;
+
+    #[test]
+    fn extrude_edges_padding() {
+        // 2x2 像素，每像素 1 字节，四周延伸 1
+        let data = [1, 2, 3, 4];
+        assert_eq!(extrude_edges(&data, 2, 2, 1, 1, 1), vec![
+            1, 1, 2, 2,
+            1, 1, 2, 2,
+            3, 3, 4, 4,
+            3, 3, 4, 4,
+        ]);
+        // 无间隙时原样返回
+        assert_eq!(extrude_edges(&data, 2, 2, 1, 0, 0), data.to_vec());
+        // 横纵间隙不同
+        assert_eq!(extrude_edges(&data, 2, 2, 1, 2, 0), vec![1, 1, 1, 2, 2, 2, 3, 3, 3, 4, 4, 4]);
+    }
+
+    #[test]
+    fn extrude_edges_block_unit() {
+        // 2x1 个压缩块，每块 8 字节，边缘整块重复
+        let (a, b) = ([1u8; 8], [2u8; 8]);
+        let data = [a, b].concat();
+        let result = extrude_edges(&data, 2, 1, 8, 1, 1);
+        let row = [a, a, b, b].concat();
+        assert_eq!(result, [row.clone(), row.clone(), row].concat());
+    }
+
+    #[test]
+    fn extrude_edges_single_pixel() {
+        let data = [9, 8, 7, 6];
+        assert_eq!(extrude_edges(&data, 1, 1, 4, 1, 2), data.repeat(3 * 5));
+    }
+
+    #[test]
+    fn allocate_unaligned_with_mips() {
+        run(|device, queue| {
+            // 4 级 mip，分配区域按 8 对齐
+            let mut atlas = Atlas::new_with_mips(0, 256, 256, 1, wgpu::TextureFormat::Rgba8Unorm, 4, &device, &queue);
+            atlas.set_padding(2);
+            let frames = [atlas.allocate(13, 5).unwrap(), atlas.allocate(13, 5).unwrap()];
+            let [a, b] = frames.each_ref().map(|r| r.frame.as_ref().unwrap().location());
+            for location in [a, b] {
+                // 内容为请求的尺寸，不受 mip 对齐影响
+                assert_eq!((location.rect.2, location.rect.3), (13, 5));
+                assert_eq!(location.padding, (2, 2));
+                assert_eq!(location.slot, (24, 16));
+                let (x, y, _, _) = location.slot_rect();
+                assert_eq!((x % 8, y % 8), (0, 0));
+            }
+            let (ax, ay, aw, ah) = a.slot_rect();
+            let (bx, by, _, _) = b.slot_rect();
+            assert!(bx >= ax + aw || by >= ay + ah);
+        });
+    }
+
+    fn location(frame: &ImageTextureFrame) -> FrameLocation {
+        frame.frame.as_ref().unwrap().location()
+    }
+
+    #[test]
+    fn atlas_prune_and_repack() {
+        run(|device, queue| {
+            let mut atlas = Atlas::new(1, 64, 64, 1, wgpu::TextureFormat::Rgba8Unorm, &device, &queue);
+            let big = atlas.allocate(64, 32).unwrap();
+            let small = atlas.allocate(16, 16).unwrap();
+            let temp = atlas.allocate(8, 8).unwrap();
+            assert_eq!(atlas.frames.len(), 3);
+
+            // 释放的帧回收后 移除弱引用
+            drop(temp);
+            let _other = atlas.allocate(8, 8).unwrap();
+            assert_eq!(atlas.frames.len(), 3);
+            assert_eq!(atlas.frame_count(), 3);
+
+            // 位置都不变时 版本号不变
+            let old = location(&small);
+            assert_eq!(atlas.repack(&device, &queue), Some(0));
+            assert_eq!((location(&small).rect, location(&small).version), (old.rect, 0));
+
+            // 释放大帧后重排，移动的帧版本号加1
+            drop(big);
+            assert_eq!(atlas.repack(&device, &queue).map(|r| r > 0), Some(true));
+            assert_ne!(location(&small).rect, old.rect);
+            assert_eq!(location(&small).version, 1);
+            assert_eq!(atlas.frames.len(), 2);
+        });
+    }
+
+    #[test]
+    fn combine_repack_at_maxcount() {
+        run(|device, queue| {
+            let mut mgr = CombineAtlas2DMgr::new(&device, wgpu::TextureFormat::Rgba8Unorm, 1, 64, 1);
+            let mut frames: Vec<ImageTextureFrame> = (0..4).map(|_| mgr.combine(wgpu::TextureFormat::Rgba8Unorm, 64, 16, &device, &queue).unwrap()).collect();
+            // 释放不相邻的两行，剩余空间足够但不连续
+            frames.retain(|r| location(r).rect.1 % 32 != 0);
+            assert_eq!(frames.len(), 2);
+            let frame = mgr.combine(wgpu::TextureFormat::Rgba8Unorm, 64, 32, &device, &queue);
+            assert!(frame.is_some());
+            assert_eq!(mgr.version(), 1);
+            assert_eq!(mgr.atlasarr.len(), 1);
+        });
+    }
+}
@@ -0,0 +1,695 @@
+//! KTX1 / KTX2 容器解析
+//! * 支持完整 mip 链、立方体贴图、纹理数组、3D 纹理
+//! * KTX2 的 zstd 超压缩需开启 feature "ktx2_zstd"，BasisLZ / zlib 不支持
+//! * 根据 面数、层数、深度 自动选择 TextureViewDimension
+//! * 透明类型由格式与 KTX2 的 DFD 推断，无法确定时按格式是否有 Alpha 通道
+
+use pi_atom::Atom;
+use pi_hal::texture::ImageTexture;
+use wgpu::{AstcBlock, AstcChannel};
+
+use crate::rhi::{device::RenderDevice, texture::alpha::EAlphaMode, RenderQueue};
+
+const KTX1_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x31, 0x31, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX2_IDENTIFIER: [u8; 12] = [0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A];
+const KTX1_ENDIANNESS: u32 = 0x04030201;
+
+const ASTC_BLOCKS: [AstcBlock; 14] = [
+    AstcBlock::B4x4, AstcBlock::B5x4, AstcBlock::B5x5, AstcBlock::B6x5, AstcBlock::B6x6, AstcBlock::B8x5, AstcBlock::B8x6,
+    AstcBlock::B8x8, AstcBlock::B10x5, AstcBlock::B10x6, AstcBlock::B10x8, AstcBlock::B10x10, AstcBlock::B12x10, AstcBlock::B12x12,
+];
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum EKtxError {
+    #[error("not a ktx file")]
+    InvalidIdentifier,
+    #[error("unexpected end of file, offset {0}")]
+    UnexpectedEof(usize),
+    #[error("unsupported gl internal format 0x{0:X}")]
+    UnsupportedGlFormat(u32),
+    #[error("unsupported vk format {0}")]
+    UnsupportedVkFormat(u32),
+    #[error("unsupported supercompression scheme {0}")]
+    UnsupportedSupercompression(u32),
+    #[error("invalid texture shape: {0}")]
+    InvalidShape(String),
+    #[error("level {level} size mismatch, expect {expect} bytes, found {found}")]
+    LevelSizeMismatch { level: usize, expect: usize, found: usize },
+    #[error("zstd decode fail: {0}")]
+    Zstd(String),
+    #[error("decode fail: {0}")]
+    Decode(String),
+    #[error("basis texture needs a transcoder")]
+    TranscoderMissing,
+}
+
+/// 解析后的 KTX 纹理
+#[derive(Debug, Clone)]
+pub struct KtxTexture {
+    pub width: u32,
+    pub height: u32,
+    /// 3D 纹理深度，非 3D 为 1
+    pub depth: u32,
+    /// 数组元素数目，非数组为 0
+    pub layers: u32,
+    /// 1 或 6
+    pub faces: u32,
+    pub format: wgpu::TextureFormat,
+    /// 每级数据紧密排列，顺序为 层、面、深度切片（与 wgpu 的 array layer 顺序一致）
+    pub levels: Vec<Vec<u8>>,
+    /// 透明类型，解析时只区分 有无 Alpha，CPU 解码后按像素分析
+    pub alpha: EAlphaMode,
+}
+
+impl KtxTexture {
+    pub fn is_ktx(data: &[u8]) -> bool {
+        data.starts_with(&KTX1_IDENTIFIER) || data.starts_with(&KTX2_IDENTIFIER)
+    }
+
+    /// KTX2 且 vkFormat 为 VK_FORMAT_UNDEFINED，即 BasisLZ / UASTC，需转码
+    pub fn is_basis(data: &[u8]) -> bool {
+        data.starts_with(&KTX2_IDENTIFIER) && data.get(12..16) == Some(&[0, 0, 0, 0])
+    }
+
+    pub fn parse(data: &[u8]) -> Result<Self, EKtxError> {
+        if data.starts_with(&KTX1_IDENTIFIER) {
+            Self::parse_ktx1(data)
+        } else if data.starts_with(&KTX2_IDENTIFIER) {
+            Self::parse_ktx2(data)
+        } else {
+            Err(EKtxError::InvalidIdentifier)
+        }
+    }
+
+    pub fn view_dimension(&self) -> wgpu::TextureViewDimension {
+        if self.depth > 1 {
+            wgpu::TextureViewDimension::D3
+        } else if self.faces == 6 {
+            if self.layers > 0 { wgpu::TextureViewDimension::CubeArray } else { wgpu::TextureViewDimension::Cube }
+        } else if self.layers > 0 {
+            wgpu::TextureViewDimension::D2Array
+        } else {
+            wgpu::TextureViewDimension::D2
+        }
+    }
+
+    pub fn depth_or_array_layers(&self) -> u32 {
+        if self.depth > 1 { self.depth } else { self.layers.max(1) * self.faces }
+    }
+
+    pub fn mip_level_count(&self) -> u32 {
+        self.levels.len() as u32
+    }
+
+    /// 数据总字节数
+    pub fn size(&self) -> usize {
+        self.levels.iter().map(|r| r.len()).sum()
+    }
+
+    /// 创建纹理并写入所有 mip 级
+    pub fn create_texture(&self, device: &RenderDevice, queue: &RenderQueue, key: &Atom) -> ImageTexture {
+        let (block_width, block_height) = self.format.block_dimensions();
+        let layers = self.depth_or_array_layers();
+        let view_dimension = self.view_dimension();
+
+        // 压缩格式的纹理尺寸须为块的整数倍
+        let texture = (**device).create_texture(&wgpu::TextureDescriptor {
+            label: Some(key.as_str()),
+            size: wgpu::Extent3d {
+                width: self.width.div_ceil(block_width) * block_width,
+                height: self.height.div_ceil(block_height) * block_height,
+                depth_or_array_layers: layers,
+            },
+            mip_level_count: self.mip_level_count(),
+            sample_count: 1,
+            dimension: view_dimension.compatible_texture_dimension(),
+            format: self.format,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        for level in 0..self.mip_level_count() {
+            self.write_level(queue, &texture, level, level);
+        }
+
+        ImageTexture {
+            width: self.width, height: self.height, size: self.size(), texture, format: self.format, view_dimension, is_opacity: self.alpha.is_opacity()
+        }
+    }
+
+    /// 将第 level 级数据写入 texture 的第 mip_level 级
+    pub(crate) fn write_level(&self, queue: &RenderQueue, texture: &wgpu::Texture, level: u32, mip_level: u32) {
+        let block_size = self.format.block_copy_size(None).unwrap_or(4);
+        let (blocks_x, blocks_y, _) = self.level_blocks(level);
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                texture,
+                mip_level,
+                origin: wgpu::Origin3d::ZERO,
+                aspect: wgpu::TextureAspect::All,
+            },
+            &self.levels[level as usize],
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(blocks_x * block_size),
+                rows_per_image: Some(blocks_y),
+            },
+            self.level_extent(level),
+        );
+    }
+
+    /// 第 level 级 按块对齐后的拷贝尺寸
+    pub(crate) fn level_extent(&self, level: u32) -> wgpu::Extent3d {
+        let (block_width, block_height) = self.format.block_dimensions();
+        let (blocks_x, blocks_y, depth) = self.level_blocks(level);
+        wgpu::Extent3d {
+            width: blocks_x * block_width,
+            height: blocks_y * block_height,
+            depth_or_array_layers: if self.depth > 1 { depth } else { self.depth_or_array_layers() },
+        }
+    }
+
+    /// 第 level 级 横向块数、纵向块数、深度
+    fn level_blocks(&self, level: u32) -> (u32, u32, u32) {
+        let (block_width, block_height) = self.format.block_dimensions();
+        let width = (self.width >> level).max(1);
+        let height = (self.height >> level).max(1);
+        let depth = (self.depth >> level).max(1);
+        (width.div_ceil(block_width), height.div_ceil(block_height), depth)
+    }
+
+    /// 第 level 级 单个面（或单个深度切片）的字节数，溢出时返回错误
+    fn face_size(&self, level: u32) -> Result<usize, EKtxError> {
+        let (blocks_x, blocks_y, _) = self.level_blocks(level);
+        (blocks_x as usize)
+            .checked_mul(blocks_y as usize)
+            .and_then(|r| r.checked_mul(self.format.block_copy_size(None).unwrap_or(4) as usize))
+            .ok_or_else(|| EKtxError::InvalidShape(format!("level {} size overflow", level)))
+    }
+
+    /// 第 level 级 全部数据的字节数，溢出时返回错误
+    fn level_size(&self, level: u32) -> Result<usize, EKtxError> {
+        let (_, _, depth) = self.level_blocks(level);
+        [depth, self.layers.max(1), self.faces]
+            .iter()
+            .try_fold(self.face_size(level)?, |size, r| size.checked_mul(*r as usize))
+            .ok_or_else(|| EKtxError::InvalidShape(format!("level {} size overflow", level)))
+    }
+
+    fn check_shape(&self) -> Result<(), EKtxError> {
+        if self.width == 0 {
+            return Err(EKtxError::InvalidShape("width is 0".to_string()));
+        }
+        if self.faces != 1 && self.faces != 6 {
+            return Err(EKtxError::InvalidShape(format!("faces {}", self.faces)));
+        }
+        if self.faces == 6 && (self.width != self.height || self.depth > 1) {
+            return Err(EKtxError::InvalidShape(format!("cubemap {}x{}x{}", self.width, self.height, self.depth)));
+        }
+        if self.depth > 1 && self.layers > 0 {
+            return Err(EKtxError::InvalidShape("3d texture array".to_string()));
+        }
+        Ok(())
+    }
+
+    /// 级数不能超过完整 mip 链的级数 floor(log2(max(w, h, d))) + 1
+    fn check_level_count(&self, level_count: u32) -> Result<(), EKtxError> {
+        let max_count = u32::BITS - self.width.max(self.height).max(self.depth).leading_zeros();
+        if level_count > max_count {
+            return Err(EKtxError::InvalidShape(format!("level count {} exceeds {} for {}x{}x{}", level_count, max_count, self.width, self.height, self.depth)));
+        }
+        Ok(())
+    }
+
+    fn parse_ktx1(data: &[u8]) -> Result<Self, EKtxError> {
+        let mut reader = Reader { data, offset: 12, swap: false };
+        let endianness = reader.u32()?;
+        reader.swap = endianness != KTX1_ENDIANNESS;
+        let _gl_type = reader.u32()?;
+        let gl_type_size = reader.u32()?;
+        let _gl_format = reader.u32()?;
+        let gl_internal_format = reader.u32()?;
+        let _gl_base_internal_format = reader.u32()?;
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let depth = reader.u32()?;
+        let layers = reader.u32()?;
+        let faces = reader.u32()?;
+        let level_count = reader.u32()?.max(1);
+        let key_value_bytes = reader.u32()?;
+        reader.skip(key_value_bytes as usize)?;
+
+        let format = gl_internal_format_to_wgpu(gl_internal_format).ok_or(EKtxError::UnsupportedGlFormat(gl_internal_format))?;
+        // GL_COMPRESSED_RGB_S3TC_DXT1 / GL_COMPRESSED_SRGB_S3TC_DXT1 无 Alpha
+        let alpha = if matches!(gl_internal_format, 0x83F0 | 0x8C4C) { EAlphaMode::Opaque } else { format_alpha_mode(format) };
+        let mut result = KtxTexture { width, height: height.max(1), depth: depth.max(1), layers, faces, format, levels: Vec::new(), alpha };
+        result.check_shape()?;
+        result.check_level_count(level_count)?;
+        result.levels.reserve_exact(level_count as usize);
+
+        // 非数组的立方体贴图，imageSize 为单个面的大小，每个面按4字节对齐
+        let cube_faces = if faces == 6 && layers == 0 { 6 } else { 1 };
+        let block_size = format.block_copy_size(None).unwrap_or(4) as usize;
+        let is_compressed = format.block_dimensions() != (1, 1);
+        for level in 0..level_count {
+            let image_size = reader.u32()? as usize;
+            let (blocks_x, blocks_y, _) = result.level_blocks(level);
+            let row = blocks_x as usize * block_size;
+            let padded_row = if is_compressed { row } else { (row + 3) & !3 };
+            let expect = result.level_size(level)?;
+            // 数据不会少于解析结果，先检查剩余长度 再分配
+            if expect > reader.remaining() {
+                return Err(EKtxError::UnexpectedEof(reader.offset));
+            }
+            let mut level_data = Vec::with_capacity(expect);
+            for _ in 0..cube_faces {
+                let bytes = reader.bytes(image_size)?;
+                if padded_row == row {
+                    level_data.extend_from_slice(bytes);
+                } else {
+                    // GL_UNPACK_ALIGNMENT 为4，去掉行尾填充
+                    for chunk in bytes.chunks(padded_row) {
+                        level_data.extend_from_slice(&chunk[..row.min(chunk.len())]);
+                    }
+                }
+                reader.align(4);
+            }
+            if reader.swap && (gl_type_size == 2 || gl_type_size == 4) {
+                swap_bytes(&mut level_data, gl_type_size as usize);
+            }
+            if level_data.len() != expect {
+                return Err(EKtxError::LevelSizeMismatch { level: level as usize, expect, found: level_data.len() });
+            }
+            result.levels.push(level_data);
+        }
+        Ok(result)
+    }
+
+    fn parse_ktx2(data: &[u8]) -> Result<Self, EKtxError> {
+        let mut reader = Reader { data, offset: 12, swap: false };
+        let vk_format = reader.u32()?;
+        let _type_size = reader.u32()?;
+        let width = reader.u32()?;
+        let height = reader.u32()?;
+        let depth = reader.u32()?;
+        let layers = reader.u32()?;
+        let faces = reader.u32()?;
+        let level_count = reader.u32()?.max(1);
+        let supercompression = reader.u32()?;
+        // dfd、kvd 偏移与长度，sgd 偏移与长度
+        reader.skip(4 * 4 + 8 * 2)?;
+
+        let format = vk_format_to_wgpu(vk_format).ok_or(EKtxError::UnsupportedVkFormat(vk_format))?;
+        // VK_FORMAT_BC1_RGB_UNORM_BLOCK / VK_FORMAT_BC1_RGB_SRGB_BLOCK 无 Alpha
+        let alpha = KtxTexture::ktx2_dfd_alpha(data).unwrap_or_else(|| {
+            if matches!(vk_format, 131 | 132) { EAlphaMode::Opaque } else { format_alpha_mode(format) }
+        });
+        match supercompression {
+            0 => {},
+            #[cfg(feature = "ktx2_zstd")]
+            2 => {},
+            _ => return Err(EKtxError::UnsupportedSupercompression(supercompression)),
+        }
+        let mut result = KtxTexture { width, height: height.max(1), depth: depth.max(1), layers, faces, format, levels: Vec::new(), alpha };
+        result.check_shape()?;
+        result.check_level_count(level_count)?;
+        result.levels.reserve_exact(level_count as usize);
+
+        for level in 0..level_count {
+            let offset = reader.u64()? as usize;
+            let length = reader.u64()? as usize;
+            let _uncompressed_length = reader.u64()?;
+            let bytes = data.get(offset..offset.saturating_add(length)).ok_or(EKtxError::UnexpectedEof(offset))?;
+            let level_data = match supercompression {
+                #[cfg(feature = "ktx2_zstd")]
+                2 => zstd_decode(bytes)?,
+                _ => bytes.to_vec(),
+            };
+            let expect = result.level_size(level)?;
+            if level_data.len() != expect {
+                return Err(EKtxError::LevelSizeMismatch { level: level as usize, expect, found: level_data.len() });
+            }
+            result.levels.push(level_data);
+        }
+        Ok(result)
+    }
+
+    /// 由 KTX2 的 DFD 判断是否有 Alpha 通道
+    /// * DFD 缺失、或颜色模型不区分通道（如 ASTC）时返回 None
+    pub fn ktx2_dfd_alpha(data: &[u8]) -> Option<EAlphaMode> {
+        if !data.starts_with(&KTX2_IDENTIFIER) {
+            return None;
+        }
+        let mut reader = Reader { data, offset: 48, swap: false };
+        let offset = reader.u32().ok()? as usize;
+        let length = reader.u32().ok()? as usize;
+        let dfd = data.get(offset..offset.saturating_add(length))?;
+
+        // dfdTotalSize 后为 basic descriptor block，块头 24 字节，之后每个 sample 16 字节
+        let model = *dfd.get(12)?;
+        let block_size = u16::from_le_bytes([*dfd.get(10)?, *dfd.get(11)?]) as usize;
+        let mut channels = dfd.get(28..4 + block_size)?.chunks_exact(16).map(|sample| sample[3] & 0x0F);
+        let has_alpha = match model {
+            // RGBSDA、BC2、BC3、ETC2、ETC1S 的 Alpha 通道为 15
+            1 | 129 | 130 | 161 | 163 => channels.any(|r| r == 15),
+            // BC1A
+            128 => channels.any(|r| r == 1),
+            // UASTC 的 RGBA、RRRG
+            166 => channels.any(|r| r == 3 || r == 5),
+            _ => return None,
+        };
+        Some(if has_alpha { EAlphaMode::Translucent } else { EAlphaMode::Opaque })
+    }
+}
+
+/// 按格式是否有 Alpha 通道得到的透明类型，有 Alpha 时视为半透明
+pub fn format_alpha_mode(format: wgpu::TextureFormat) -> EAlphaMode {
+    use wgpu::TextureFormat as F;
+    match format {
+        F::Rgba8Unorm | F::Rgba8UnormSrgb | F::Rgba8Snorm | F::Rgba8Uint | F::Rgba8Sint
+        | F::Bgra8Unorm | F::Bgra8UnormSrgb | F::Rgb10a2Unorm
+        | F::Rgba16Unorm | F::Rgba16Snorm | F::Rgba16Uint | F::Rgba16Sint | F::Rgba16Float
+        | F::Rgba32Uint | F::Rgba32Sint | F::Rgba32Float
+        | F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb | F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb
+        | F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb | F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb
+        | F::Etc2Rgb8A1Unorm | F::Etc2Rgb8A1UnormSrgb | F::Etc2Rgba8Unorm | F::Etc2Rgba8UnormSrgb
+        | F::Astc { .. } => EAlphaMode::Translucent,
+        _ => EAlphaMode::Opaque,
+    }
+}
+
+#[cfg(feature = "ktx2_zstd")]
+fn zstd_decode(bytes: &[u8]) -> Result<Vec<u8>, EKtxError> {
+    use std::io::Read;
+    let mut decoder = ruzstd::streaming_decoder::StreamingDecoder::new(bytes).map_err(|e| EKtxError::Zstd(e.to_string()))?;
+    let mut result = Vec::new();
+    decoder.read_to_end(&mut result).map_err(|e| EKtxError::Zstd(e.to_string()))?;
+    Ok(result)
+}
+
+fn swap_bytes(data: &mut [u8], size: usize) {
+    for chunk in data.chunks_exact_mut(size) {
+        chunk.reverse();
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    offset: usize,
+    swap: bool,
+}
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], EKtxError> {
+        let result = self.data.get(self.offset..self.offset.saturating_add(len)).ok_or(EKtxError::UnexpectedEof(self.offset))?;
+        self.offset += len;
+        Ok(result)
+    }
+    fn remaining(&self) -> usize {
+        self.data.len().saturating_sub(self.offset)
+    }
+    fn skip(&mut self, len: usize) -> Result<(), EKtxError> {
+        self.bytes(len).map(|_| ())
+    }
+    fn align(&mut self, align: usize) {
+        self.offset = (self.offset + align - 1) / align * align;
+    }
+    fn u32(&mut self) -> Result<u32, EKtxError> {
+        let bytes: [u8; 4] = self.bytes(4)?.try_into().unwrap();
+        Ok(if self.swap { u32::from_be_bytes(bytes) } else { u32::from_le_bytes(bytes) })
+    }
+    fn u64(&mut self) -> Result<u64, EKtxError> {
+        let bytes: [u8; 8] = self.bytes(8)?.try_into().unwrap();
+        Ok(u64::from_le_bytes(bytes))
+    }
+}
+
+/// KTX1 的 glInternalFormat
+pub fn gl_internal_format_to_wgpu(format: u32) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+    Some(match format {
+        0x8229 => F::R8Unorm,
+        0x8F94 => F::R8Snorm,
+        0x8232 => F::R8Uint,
+        0x8231 => F::R8Sint,
+        0x822B => F::Rg8Unorm,
+        0x8F95 => F::Rg8Snorm,
+        0x8238 => F::Rg8Uint,
+        0x8237 => F::Rg8Sint,
+        0x8058 => F::Rgba8Unorm,
+        0x8C43 => F::Rgba8UnormSrgb,
+        0x8F97 => F::Rgba8Snorm,
+        0x8D7C => F::Rgba8Uint,
+        0x8D8E => F::Rgba8Sint,
+        0x8059 => F::Rgb10a2Unorm,
+        0x822D => F::R16Float,
+        0x822F => F::Rg16Float,
+        0x881A => F::Rgba16Float,
+        0x822E => F::R32Float,
+        0x8230 => F::Rg32Float,
+        0x8814 => F::Rgba32Float,
+        0x8C3A => F::Rg11b10Float,
+        0x8C3D => F::Rgb9e5Ufloat,
+        // S3TC / RGTC / BPTC
+        0x83F0 | 0x83F1 => F::Bc1RgbaUnorm,
+        0x8C4C | 0x8C4D => F::Bc1RgbaUnormSrgb,
+        0x83F2 => F::Bc2RgbaUnorm,
+        0x8C4E => F::Bc2RgbaUnormSrgb,
+        0x83F3 => F::Bc3RgbaUnorm,
+        0x8C4F => F::Bc3RgbaUnormSrgb,
+        0x8DBB => F::Bc4RUnorm,
+        0x8DBC => F::Bc4RSnorm,
+        0x8DBD => F::Bc5RgUnorm,
+        0x8DBE => F::Bc5RgSnorm,
+        0x8E8F => F::Bc6hRgbUfloat,
+        0x8E8E => F::Bc6hRgbFloat,
+        0x8E8C => F::Bc7RgbaUnorm,
+        0x8E8D => F::Bc7RgbaUnormSrgb,
+        // ETC2 / EAC
+        0x9274 => F::Etc2Rgb8Unorm,
+        0x9275 => F::Etc2Rgb8UnormSrgb,
+        0x9276 => F::Etc2Rgb8A1Unorm,
+        0x9277 => F::Etc2Rgb8A1UnormSrgb,
+        0x9278 => F::Etc2Rgba8Unorm,
+        0x9279 => F::Etc2Rgba8UnormSrgb,
+        0x9270 => F::EacR11Unorm,
+        0x9271 => F::EacR11Snorm,
+        0x9272 => F::EacRg11Unorm,
+        0x9273 => F::EacRg11Snorm,
+        // ASTC
+        0x93B0..=0x93BD => F::Astc { block: ASTC_BLOCKS[(format - 0x93B0) as usize], channel: AstcChannel::Unorm },
+        0x93D0..=0x93DD => F::Astc { block: ASTC_BLOCKS[(format - 0x93D0) as usize], channel: AstcChannel::UnormSrgb },
+        _ => return None,
+    })
+}
+
+/// KTX2 的 vkFormat
+pub fn vk_format_to_wgpu(format: u32) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+    Some(match format {
+        9 => F::R8Unorm,
+        10 => F::R8Snorm,
+        13 => F::R8Uint,
+        14 => F::R8Sint,
+        16 => F::Rg8Unorm,
+        17 => F::Rg8Snorm,
+        20 => F::Rg8Uint,
+        21 => F::Rg8Sint,
+        37 => F::Rgba8Unorm,
+        38 => F::Rgba8Snorm,
+        41 => F::Rgba8Uint,
+        42 => F::Rgba8Sint,
+        43 => F::Rgba8UnormSrgb,
+        44 => F::Bgra8Unorm,
+        50 => F::Bgra8UnormSrgb,
+        64 => F::Rgb10a2Unorm,
+        70 => F::R16Unorm,
+        71 => F::R16Snorm,
+        74 => F::R16Uint,
+        75 => F::R16Sint,
+        76 => F::R16Float,
+        77 => F::Rg16Unorm,
+        78 => F::Rg16Snorm,
+        81 => F::Rg16Uint,
+        82 => F::Rg16Sint,
+        83 => F::Rg16Float,
+        91 => F::Rgba16Unorm,
+        92 => F::Rgba16Snorm,
+        95 => F::Rgba16Uint,
+        96 => F::Rgba16Sint,
+        97 => F::Rgba16Float,
+        98 => F::R32Uint,
+        99 => F::R32Sint,
+        100 => F::R32Float,
+        101 => F::Rg32Uint,
+        102 => F::Rg32Sint,
+        103 => F::Rg32Float,
+        107 => F::Rgba32Uint,
+        108 => F::Rgba32Sint,
+        109 => F::Rgba32Float,
+        122 => F::Rg11b10Float,
+        123 => F::Rgb9e5Ufloat,
+        131 | 133 => F::Bc1RgbaUnorm,
+        132 | 134 => F::Bc1RgbaUnormSrgb,
+        135 => F::Bc2RgbaUnorm,
+        136 => F::Bc2RgbaUnormSrgb,
+        137 => F::Bc3RgbaUnorm,
+        138 => F::Bc3RgbaUnormSrgb,
+        139 => F::Bc4RUnorm,
+        140 => F::Bc4RSnorm,
+        141 => F::Bc5RgUnorm,
+        142 => F::Bc5RgSnorm,
+        143 => F::Bc6hRgbUfloat,
+        144 => F::Bc6hRgbFloat,
+        145 => F::Bc7RgbaUnorm,
+        146 => F::Bc7RgbaUnormSrgb,
+        147 => F::Etc2Rgb8Unorm,
+        148 => F::Etc2Rgb8UnormSrgb,
+        149 => F::Etc2Rgb8A1Unorm,
+        150 => F::Etc2Rgb8A1UnormSrgb,
+        151 => F::Etc2Rgba8Unorm,
+        152 => F::Etc2Rgba8UnormSrgb,
+        153 => F::EacR11Unorm,
+        154 => F::EacR11Snorm,
+        155 => F::EacRg11Unorm,
+        156 => F::EacRg11Snorm,
+        // ASTC 的 UNORM、SRGB 交替排列
+        157..=184 => {
+            let index = format - 157;
+            let channel = if index % 2 == 0 { AstcChannel::Unorm } else { AstcChannel::UnormSrgb };
+            F::Astc { block: ASTC_BLOCKS[(index / 2) as usize], channel }
+        },
+        1000066000..=1000066013 => F::Astc { block: ASTC_BLOCKS[(format - 1000066000) as usize], channel: AstcChannel::Hdr },
+        _ => return None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ktx1_header(internal_format: u32, width: u32, height: u32, layers: u32, faces: u32, levels: u32) -> Vec<u8> {
+        let mut data = KTX1_IDENTIFIER.to_vec();
+        for v in [KTX1_ENDIANNESS, 0x1401, 1, 0x1908, internal_format, 0x1908, width, height, 0, layers, faces, levels, 0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data
+    }
+
+    #[test]
+    fn parse_ktx1_cubemap_mips() {
+        // RGBA8 4x4 立方体贴图，3级 mip
+        let mut data = ktx1_header(0x8058, 4, 4, 0, 6, 3);
+        for (level, size) in [4u32, 2, 1].iter().enumerate() {
+            let face = size * size * 4;
+            data.extend_from_slice(&face.to_le_bytes());
+            for f in 0..6u8 {
+                data.extend(std::iter::repeat(level as u8 * 10 + f).take(face as usize));
+            }
+        }
+        let ktx = KtxTexture::parse(&data).unwrap();
+        assert_eq!(ktx.view_dimension(), wgpu::TextureViewDimension::Cube);
+        assert_eq!(ktx.depth_or_array_layers(), 6);
+        assert_eq!(ktx.levels.iter().map(|r| r.len()).collect::<Vec<_>>(), vec![384, 96, 24]);
+        assert_eq!(ktx.levels[1][16], 11);
+        assert_eq!(ktx.levels[2][23], 25);
+        assert_eq!(ktx.alpha, EAlphaMode::Translucent);
+    }
+
+    #[test]
+    fn parse_ktx1_row_padding() {
+        // R8 3x2，行按4字节对齐
+        let mut data = ktx1_header(0x8229, 3, 2, 0, 1, 1);
+        data.extend_from_slice(&8u32.to_le_bytes());
+        data.extend_from_slice(&[1, 2, 3, 0, 4, 5, 6, 0]);
+        let ktx = KtxTexture::parse(&data).unwrap();
+        assert_eq!(ktx.levels[0], vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(ktx.view_dimension(), wgpu::TextureViewDimension::D2);
+        assert_eq!(ktx.alpha, EAlphaMode::Opaque);
+    }
+
+    #[test]
+    fn parse_ktx1_invalid_header() {
+        // 级数超过完整 mip 链
+        let data = ktx1_header(0x8058, 4, 4, 0, 1, u32::MAX);
+        assert!(matches!(KtxTexture::parse(&data), Err(EKtxError::InvalidShape(_))));
+        let data = ktx1_header(0x8058, 4, 4, 0, 1, 4);
+        assert!(matches!(KtxTexture::parse(&data), Err(EKtxError::InvalidShape(_))));
+        // 尺寸溢出
+        let mut data = ktx1_header(0x8058, u32::MAX, u32::MAX, u32::MAX, 1, 1);
+        data.extend_from_slice(&16u32.to_le_bytes());
+        assert!(matches!(KtxTexture::parse(&data), Err(EKtxError::InvalidShape(_))));
+        // 数据不足，不按头部尺寸分配
+        let mut data = ktx1_header(0x8058, 4096, 4096, 0, 1, 1);
+        data.extend_from_slice(&(4096u32 * 4096 * 4).to_le_bytes());
+        data.extend_from_slice(&[0u8; 16]);
+        assert_eq!(KtxTexture::parse(&data).unwrap_err(), EKtxError::UnexpectedEof(data.len() - 16));
+    }
+
+    #[test]
+    fn parse_ktx2_array() {
+        // ASTC 4x4 8x8，2层数组，2级 mip
+        let mut data = KTX2_IDENTIFIER.to_vec();
+        for v in [157u32, 1, 8, 8, 0, 2, 1, 2, 0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&[0u8; 4 * 4 + 8 * 2]);
+        let index = data.len();
+        let level0 = 4 * 16 * 2;
+        let level1 = 16 * 2;
+        let start = (index + 24 * 2) as u64;
+        for (offset, len) in [(start + level1 as u64, level0 as u64), (start, level1 as u64)] {
+            for v in [offset, len, len] {
+                data.extend_from_slice(&v.to_le_bytes());
+            }
+        }
+        data.extend(std::iter::repeat(1u8).take(level1));
+        data.extend(std::iter::repeat(0u8).take(level0));
+        let ktx = KtxTexture::parse(&data).unwrap();
+        assert_eq!(ktx.format, wgpu::TextureFormat::Astc { block: AstcBlock::B4x4, channel: AstcChannel::Unorm });
+        assert_eq!(ktx.view_dimension(), wgpu::TextureViewDimension::D2Array);
+        assert_eq!(ktx.levels[0].len(), level0);
+        assert_eq!(ktx.levels[1], vec![1u8; level1]);
+        // 没有 DFD，按格式判断
+        assert_eq!(ktx.alpha, EAlphaMode::Translucent);
+
+        data[12 + 4 * 8] = 1;
+        assert_eq!(KtxTexture::parse(&data).unwrap_err(), EKtxError::UnsupportedSupercompression(1));
+    }
+
+    #[test]
+    fn parse_ktx2_dfd_alpha() {
+        // RGBA8 1x1，DFD 只描述 RGB 三个通道
+        let mut data = KTX2_IDENTIFIER.to_vec();
+        for v in [37u32, 1, 1, 1, 0, 0, 1, 1, 0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        let dfd_offset = 48 + 4 * 4 + 8 * 2 + 24;
+        let dfd_length = 4 + 24 + 16 * 3;
+        for v in [dfd_offset as u32, dfd_length as u32, 0, 0] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&[0u8; 8 * 2]);
+        for v in [(dfd_offset + dfd_length) as u64, 4, 4] {
+            data.extend_from_slice(&v.to_le_bytes());
+        }
+        data.extend_from_slice(&(dfd_length as u32).to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&2u16.to_le_bytes());
+        data.extend_from_slice(&(dfd_length as u16 - 4).to_le_bytes());
+        data.extend_from_slice(&[1, 1, 1, 0, 0, 0, 0, 0, 4, 0, 0, 0, 0, 0, 0, 0]);
+        for channel in 0..3u8 {
+            data.extend_from_slice(&[channel * 8, 0, 7, channel]);
+            data.extend_from_slice(&[0u8; 12]);
+        }
+        data.extend_from_slice(&[255, 0, 0, 255]);
+
+        let ktx = KtxTexture::parse(&data).unwrap();
+        assert_eq!(ktx.format, wgpu::TextureFormat::Rgba8Unorm);
+        assert_eq!(ktx.alpha, EAlphaMode::Opaque);
+
+        // 第三个通道改为 Alpha
+        let channel = dfd_offset + 28 + 16 * 2 + 3;
+        data[channel] = 15;
+        assert_eq!(KtxTexture::parse(&data).unwrap().alpha, EAlphaMode::Translucent);
+    }
+}
@@ -6,6 +6,12 @@ mod render_target;
 pub mod texture_view;
 mod texture_view_array;
 mod image_texture_frame;
+mod ktx_texture;
+mod texture_compress;
+mod texture_streaming;
+mod image_texture_cube;
+mod image_texture_3d;
+mod hdr_texture;
 
 pub use bind_texture::*;
 pub use texture_format::*;
@@ -15,6 +21,12 @@ pub use render_target::*;
 pub use texture_view::*;
 pub use texture_view_array::*;
 pub use image_texture_frame::*;
+pub use ktx_texture::*;
+pub use texture_compress::*;
+pub use texture_streaming::*;
+pub use image_texture_cube::*;
+pub use image_texture_3d::*;
+pub use hdr_texture::*;
 
 
 #[derive(Debug, Clone, Default, Hash, PartialEq, Eq)]
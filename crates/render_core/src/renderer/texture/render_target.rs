@@ -2,7 +2,7 @@ use std::{sync::Arc, hash::Hash};
 
 use pi_assets::asset::{Handle, Asset, Size};
 
-use crate::{rhi::{device::RenderDevice, texture::{Texture, TextureView}}, renderer::texture::texture_format::TTextureFormatPixelByte, asset::{ASSET_SIZE_FOR_UNKOWN, TAssetKeyU64}};
+use crate::{components::view::target_alloc::supported_sample_count, rhi::{device::RenderDevice, texture::{mipmap::{self, MipmapGenerator}, Texture, TextureView}, RenderQueue}, renderer::texture::texture_format::TTextureFormatPixelByte, asset::{ASSET_SIZE_FOR_UNKOWN, TAssetKeyU64}};
 
 use super::TextureViewDesc;
 
@@ -59,6 +59,9 @@ pub struct RenderTexture {
     pub(crate) texture: Texture,
 	#[allow(dead_code)]
     pub(crate) format: wgpu::TextureFormat,
+    /// 多重采样时的解析纹理
+    pub(crate) resolve: Option<Texture>,
+    pub(crate) sample_count: u32,
     size: usize,
 }
 impl RenderTexture {
@@ -68,10 +71,73 @@ impl RenderTexture {
             height,
             texture,
             format,
+            resolve: None,
+            sample_count: 1,
             size: (width * height) as usize * format.pixel_bytes(),
         }
     }
+    pub fn sample_count(&self) -> u32 {
+        self.sample_count
+    }
+    /// 多重采样颜色纹理的解析纹理，用于采样、拷贝
+    pub fn resolve_texture(&self) -> Option<&Texture> {
+        self.resolve.as_ref()
+    }
+    /// 渲染到该纹理的管线应使用的多重采样状态
+    pub fn multisample_state(&self) -> wgpu::MultisampleState {
+        wgpu::MultisampleState { count: self.sample_count, mask: !0, alpha_to_coverage_enabled: false }
+    }
+    /// 多重采样的颜色纹理，另带一张同尺寸的解析纹理
+    /// * sample_count 按设备支持向下取值，为1时等同 color
+    pub fn color_msaa(device: &RenderDevice, width: u32, height: u32, format: wgpu::TextureFormat, sample_count: u32) -> RenderTexture {
+        let sample_count = supported_sample_count(device, format, sample_count);
+        if sample_count == 1 {
+            return RenderTexture::color(device, width, height, format);
+        }
+        let texture = RenderTexture::create_msaa(device, width, height, format, sample_count);
+        let resolve = RenderTexture::color(device, width, height, format);
+        let mut result = RenderTexture::new(width, height, format, texture);
+        result.size = result.size * sample_count as usize + resolve.size;
+        result.resolve = Some(resolve.texture);
+        result.sample_count = sample_count;
+        result
+    }
+    /// 多重采样的深度纹理，与 color_msaa 配合使用，采样数须一致
+    pub fn depth_msaa(device: &RenderDevice, width: u32, height: u32, format: EDepthStencilFormat, sample_count: u32) -> RenderTexture {
+        let sample_count = supported_sample_count(device, format.format(), sample_count);
+        if sample_count == 1 {
+            return RenderTexture::depth(device, width, height, format);
+        }
+        let format = format.format();
+        let texture = RenderTexture::create_msaa(device, width, height, format, sample_count);
+        let mut result = RenderTexture::new(width, height, format, texture);
+        result.size *= sample_count as usize;
+        result.sample_count = sample_count;
+        result
+    }
+    fn create_msaa(device: &RenderDevice, width: u32, height: u32, format: wgpu::TextureFormat, sample_count: u32) -> Texture {
+        device.create_texture(
+            &wgpu::TextureDescriptor {
+                size: wgpu::Extent3d { width, height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count,
+                dimension: wgpu::TextureDimension::D2,
+                format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+                label: None,
+				view_formats: &[],
+            }
+        )
+    }
     pub fn color(device: &RenderDevice, width: u32, height: u32, format: wgpu::TextureFormat) -> RenderTexture {
+        RenderTexture::color_with_mips(device, width, height, format, 1)
+    }
+    /// 带完整 mip 链的颜色纹理（格式不支持生成 mipmap 时只有1级），渲染后调用 generate_mipmaps
+    pub fn color_with_mipmaps(device: &RenderDevice, width: u32, height: u32, format: wgpu::TextureFormat) -> RenderTexture {
+        let mip_level_count = if mipmap::is_mipmap_generatable(format) { mipmap::mip_level_count(width, height) } else { 1 };
+        RenderTexture::color_with_mips(device, width, height, format, mip_level_count)
+    }
+    fn color_with_mips(device: &RenderDevice, width: u32, height: u32, format: wgpu::TextureFormat, mip_level_count: u32) -> RenderTexture {
         let size = wgpu::Extent3d {
             width,
             height,
@@ -83,7 +149,7 @@ impl RenderTexture {
                 // All textures are stored as 3D, we represent our 2D texture
                 // by setting depth to 1.
                 size,
-                mip_level_count: 1, // We'll talk about this a little later
+                mip_level_count,
                 sample_count: 1,
                 dimension: wgpu::TextureDimension::D2,
                 // Most images are stored using sRGB so we need to reflect that here.
@@ -96,7 +162,13 @@ impl RenderTexture {
             }
         );
 
-        RenderTexture::new(width, height, format, texture)
+        let mut result = RenderTexture::new(width, height, format, texture);
+        result.size = mipmap::mipmap_size(result.size, mip_level_count);
+        result
+    }
+    /// 以第0级为源 生成其余各级
+    pub fn generate_mipmaps(&self, generator: &MipmapGenerator, device: &RenderDevice, queue: &RenderQueue) {
+        generator.generate(device, queue, &self.texture, false);
     }
     pub fn depth(device: &RenderDevice, width: u32, height: u32, format: EDepthStencilFormat) -> RenderTexture {
         let format = format.format();
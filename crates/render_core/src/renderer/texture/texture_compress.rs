@@ -0,0 +1,336 @@
+//! 压缩纹理格式协商
+//! * 根据设备 Features 判断压缩格式（BC / ETC2 / ASTC）是否可用
+//! * 不可用时在 CPU 上解码为 RGBA8
+//! * Basis 类数据（KTX2 BasisLZ / UASTC）由外部提供的转码器转为设备支持的压缩格式
+//!
+//! 同一份资源可同时用于桌面 GL、移动端、WebGL2
+
+use pi_share::Share;
+
+use crate::rhi::texture::alpha::{self, EAlphaMode};
+
+use super::{EKtxError, KtxTexture};
+
+/// 纹理上传所走的路径
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ETextureLoadPath {
+    /// 设备支持，直接上传
+    Native(wgpu::TextureFormat),
+    /// 设备不支持，CPU 解码后上传
+    Decoded { from: wgpu::TextureFormat, to: wgpu::TextureFormat },
+    /// Basis 类数据，转码后上传
+    Transcoded(wgpu::TextureFormat),
+}
+
+/// Basis 类数据转码器
+pub trait TTextureTranscoder: Send + Sync {
+    /// 将 KTX2 文件数据转码
+    /// * targets - 设备支持的目标格式，按优先级排序，均为线性格式，是否使用 sRGB 由文件的 DFD 决定
+    fn transcode(&self, data: &[u8], targets: &[wgpu::TextureFormat]) -> Result<KtxTexture, String>;
+}
+
+pub struct TextureFormatNegotiator {
+    features: wgpu::Features,
+    transcoder: Option<Share<dyn TTextureTranscoder>>,
+}
+
+impl TextureFormatNegotiator {
+    pub fn new(features: wgpu::Features) -> Self {
+        Self { features, transcoder: None }
+    }
+
+    pub fn with_transcoder(mut self, transcoder: Share<dyn TTextureTranscoder>) -> Self {
+        self.transcoder = Some(transcoder);
+        self
+    }
+
+    pub fn is_supported(&self, format: wgpu::TextureFormat) -> bool {
+        self.features.contains(format.required_features())
+    }
+
+    /// 转码目标，依次为 ASTC 4x4、BC7、BC3、ETC2 RGBA8，最后为 RGBA8
+    pub fn transcode_targets(&self) -> Vec<wgpu::TextureFormat> {
+        [
+            wgpu::TextureFormat::Astc { block: wgpu::AstcBlock::B4x4, channel: wgpu::AstcChannel::Unorm },
+            wgpu::TextureFormat::Bc7RgbaUnorm,
+            wgpu::TextureFormat::Bc3RgbaUnorm,
+            wgpu::TextureFormat::Etc2Rgba8Unorm,
+            wgpu::TextureFormat::Rgba8Unorm,
+        ].into_iter().filter(|r| self.is_supported(*r)).collect()
+    }
+
+    /// 解析 KTX1 / KTX2 数据，并转为设备可用的格式
+    pub fn load_ktx(&self, data: &[u8]) -> Result<(KtxTexture, ETextureLoadPath), EKtxError> {
+        if KtxTexture::is_basis(data) {
+            let transcoder = self.transcoder.as_ref().ok_or(EKtxError::TranscoderMissing)?;
+            let mut ktx = transcoder.transcode(data, &self.transcode_targets()).map_err(EKtxError::Decode)?;
+            // 转码目标格式总带 Alpha，以源文件的 DFD 为准
+            if let Some(alpha) = KtxTexture::ktx2_dfd_alpha(data) {
+                ktx.alpha = alpha;
+            }
+            let format = ktx.format;
+            return match self.negotiate(ktx)? {
+                (ktx, ETextureLoadPath::Native(_)) => Ok((ktx, ETextureLoadPath::Transcoded(format))),
+                (ktx, path) => Ok((ktx, path)),
+            };
+        }
+        self.negotiate(KtxTexture::parse(data)?)
+    }
+
+    /// 设备不支持时，逐级、逐面解码为 RGBA8
+    /// * 源格式有 Alpha 时，按第 0 级解码结果重新分析透明类型
+    pub fn negotiate(&self, ktx: KtxTexture) -> Result<(KtxTexture, ETextureLoadPath), EKtxError> {
+        let from = ktx.format;
+        if self.is_supported(from) {
+            return Ok((ktx, ETextureLoadPath::Native(from)));
+        }
+        let to = decoded_format(from).ok_or_else(|| EKtxError::Decode(format!("cpu decode unsupported for {:?}", from)))?;
+        let mut levels = Vec::with_capacity(ktx.levels.len());
+        for (level, data) in ktx.levels.iter().enumerate() {
+            let width = (ktx.width >> level).max(1);
+            let height = (ktx.height >> level).max(1);
+            // 3D 纹理每级深度减半，数组与立方体每级图像数不变
+            let images = if ktx.depth > 1 { (ktx.depth >> level).max(1) } else { ktx.layers.max(1) * ktx.faces } as usize;
+            let face_size = data.len() / images;
+            if face_size == 0 {
+                return Err(EKtxError::Decode(format!("level {} data too small, {} bytes for {} images", level, data.len(), images)));
+            }
+            let mut decoded = Vec::with_capacity(width as usize * height as usize * 4 * images);
+            for image in data.chunks(face_size) {
+                decoded.extend_from_slice(&decode_to_rgba8(from, width, height, image).map_err(EKtxError::Decode)?);
+            }
+            levels.push(decoded);
+        }
+        let alpha = match ktx.alpha {
+            EAlphaMode::Opaque => EAlphaMode::Opaque,
+            _ => alpha::alpha_mode_u8(&levels[0], 4),
+        };
+        Ok((KtxTexture { format: to, levels, alpha, ..ktx }, ETextureLoadPath::Decoded { from, to }))
+    }
+}
+
+/// CPU 解码的目标格式：sRGB 源为 Rgba8UnormSrgb，有符号源为 Rgba8Snorm，其余为 Rgba8Unorm
+pub fn decoded_format(format: wgpu::TextureFormat) -> Option<wgpu::TextureFormat> {
+    use wgpu::TextureFormat as F;
+    match format {
+        F::Bc1RgbaUnormSrgb | F::Bc2RgbaUnormSrgb | F::Bc3RgbaUnormSrgb | F::Bc7RgbaUnormSrgb
+        | F::Etc2Rgb8UnormSrgb | F::Etc2Rgb8A1UnormSrgb | F::Etc2Rgba8UnormSrgb
+        | F::Astc { channel: wgpu::AstcChannel::UnormSrgb, .. } => Some(F::Rgba8UnormSrgb),
+        F::Bc4RSnorm | F::Bc5RgSnorm => Some(F::Rgba8Snorm),
+        F::Bc1RgbaUnorm | F::Bc2RgbaUnorm | F::Bc3RgbaUnorm | F::Bc4RUnorm | F::Bc5RgUnorm | F::Bc7RgbaUnorm
+        | F::Etc2Rgb8Unorm | F::Etc2Rgb8A1Unorm | F::Etc2Rgba8Unorm | F::EacR11Unorm | F::EacRg11Unorm
+        | F::Astc { channel: wgpu::AstcChannel::Unorm, .. } => Some(F::Rgba8Unorm),
+        _ => None,
+    }
+}
+
+/// 解码单个图像（一个面或一层）为 RGBA8，结果紧密排列，width * height * 4 字节
+pub fn decode_to_rgba8(format: wgpu::TextureFormat, width: u32, height: u32, data: &[u8]) -> Result<Vec<u8>, String> {
+    use wgpu::TextureFormat as F;
+    let (w, h) = (width as usize, height as usize);
+    match format {
+        F::Bc1RgbaUnorm | F::Bc1RgbaUnormSrgb => decode_blocks(data, w, h, 8, |block, out| bc1_block(block, false, out)),
+        F::Bc2RgbaUnorm | F::Bc2RgbaUnormSrgb => decode_blocks(data, w, h, 16, bc2_block),
+        F::Bc3RgbaUnorm | F::Bc3RgbaUnormSrgb => decode_blocks(data, w, h, 16, bc3_block),
+        F::Bc4RUnorm => decode_blocks(data, w, h, 8, |block, out| bc4_block(block, false, [0, 0, 255], out)),
+        F::Bc4RSnorm => decode_blocks(data, w, h, 8, |block, out| bc4_block(block, true, [0, 0, 127], out)),
+        F::Bc5RgUnorm => decode_blocks(data, w, h, 16, |block, out| bc5_block(block, false, out)),
+        F::Bc5RgSnorm => decode_blocks(data, w, h, 16, |block, out| bc5_block(block, true, out)),
+        _ => {
+            let mut image = vec![0u32; w * h];
+            let r = match format {
+                F::Bc7RgbaUnorm | F::Bc7RgbaUnormSrgb => texture2ddecoder::decode_bc7(data, w, h, &mut image),
+                F::Etc2Rgb8Unorm | F::Etc2Rgb8UnormSrgb => texture2ddecoder::decode_etc2_rgb(data, w, h, &mut image),
+                F::Etc2Rgb8A1Unorm | F::Etc2Rgb8A1UnormSrgb => texture2ddecoder::decode_etc2_rgba1(data, w, h, &mut image),
+                F::Etc2Rgba8Unorm | F::Etc2Rgba8UnormSrgb => texture2ddecoder::decode_etc2_rgba8(data, w, h, &mut image),
+                F::EacR11Unorm => texture2ddecoder::decode_eacr(data, w, h, &mut image),
+                F::EacRg11Unorm => texture2ddecoder::decode_eacrg(data, w, h, &mut image),
+                F::Astc { channel: wgpu::AstcChannel::Unorm | wgpu::AstcChannel::UnormSrgb, .. } => {
+                    let (bw, bh) = format.block_dimensions();
+                    texture2ddecoder::decode_astc(data, w, h, bw as usize, bh as usize, &mut image)
+                },
+                _ => return Err(format!("cpu decode unsupported for {:?}", format)),
+            };
+            r.map_err(|e| e.to_string())?;
+            // 解码结果为 BGRA
+            let mut result = Vec::with_capacity(w * h * 4);
+            for pixel in image {
+                let [b, g, r, a] = pixel.to_le_bytes();
+                result.extend_from_slice(&[r, g, b, a]);
+            }
+            Ok(result)
+        }
+    }
+}
+
+/// 逐块解码 4x4 块格式，块的输出为 16 个 RGBA 像素
+fn decode_blocks(data: &[u8], width: usize, height: usize, block_size: usize, decode: impl Fn(&[u8], &mut [[u8; 4]; 16])) -> Result<Vec<u8>, String> {
+    let blocks_x = (width + 3) / 4;
+    let blocks_y = (height + 3) / 4;
+    if data.len() < blocks_x * blocks_y * block_size {
+        return Err(format!("data too short, expect {} bytes, found {}", blocks_x * blocks_y * block_size, data.len()));
+    }
+    let mut result = vec![0u8; width * height * 4];
+    let mut pixels = [[0u8; 4]; 16];
+    for by in 0..blocks_y {
+        for bx in 0..blocks_x {
+            let offset = (by * blocks_x + bx) * block_size;
+            decode(&data[offset..offset + block_size], &mut pixels);
+            for (i, pixel) in pixels.iter().enumerate() {
+                let (x, y) = (bx * 4 + i % 4, by * 4 + i / 4);
+                if x < width && y < height {
+                    let index = (y * width + x) * 4;
+                    result[index..index + 4].copy_from_slice(pixel);
+                }
+            }
+        }
+    }
+    Ok(result)
+}
+
+fn rgb565(c: u16) -> [u8; 3] {
+    let (r, g, b) = ((c >> 11) & 31, (c >> 5) & 63, c & 31);
+    [((r << 3) | (r >> 2)) as u8, ((g << 2) | (g >> 4)) as u8, ((b << 3) | (b >> 2)) as u8]
+}
+
+/// * opaque - BC2 / BC3 的颜色块总是 4 色模式
+fn bc1_block(block: &[u8], opaque: bool, out: &mut [[u8; 4]; 16]) {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let (a, b) = (rgb565(c0), rgb565(c1));
+    let mix = |wa: u16, wb: u16, d: u16| -> [u8; 4] {
+        let f = |i: usize| ((a[i] as u16 * wa + b[i] as u16 * wb) / d) as u8;
+        [f(0), f(1), f(2), 255]
+    };
+    let colors = if c0 > c1 || opaque {
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], mix(2, 1, 3), mix(1, 2, 3)]
+    } else {
+        [[a[0], a[1], a[2], 255], [b[0], b[1], b[2], 255], mix(1, 1, 2), [0, 0, 0, 0]]
+    };
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    for (i, pixel) in out.iter_mut().enumerate() {
+        *pixel = colors[((indices >> (2 * i)) & 3) as usize];
+    }
+}
+
+fn bc2_block(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    bc1_block(&block[8..16], true, out);
+    let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+    for (i, pixel) in out.iter_mut().enumerate() {
+        pixel[3] = ((alpha >> (4 * i)) & 15) as u8 * 17;
+    }
+}
+
+fn bc3_block(block: &[u8], out: &mut [[u8; 4]; 16]) {
+    bc1_block(&block[8..16], true, out);
+    let alpha = interpolate_channel(&block[0..8], false);
+    for (pixel, a) in out.iter_mut().zip(alpha.iter()) {
+        pixel[3] = *a;
+    }
+}
+
+/// * rest - 其余三个通道 (G, B, A) 的值
+fn bc4_block(block: &[u8], signed: bool, rest: [u8; 3], out: &mut [[u8; 4]; 16]) {
+    let red = interpolate_channel(block, signed);
+    for (pixel, r) in out.iter_mut().zip(red.iter()) {
+        *pixel = [*r, rest[0], rest[1], rest[2]];
+    }
+}
+
+fn bc5_block(block: &[u8], signed: bool, out: &mut [[u8; 4]; 16]) {
+    let red = interpolate_channel(&block[0..8], signed);
+    let green = interpolate_channel(&block[8..16], signed);
+    let alpha = if signed { 127 } else { 255 };
+    for (i, pixel) in out.iter_mut().enumerate() {
+        *pixel = [red[i], green[i], 0, alpha];
+    }
+}
+
+/// BC3 的 alpha 块、BC4 / BC5 的通道块，有符号时结果按 i8 的位模式存储
+fn interpolate_channel(block: &[u8], signed: bool) -> [u8; 16] {
+    let value = |v: u8| -> i32 { if signed { (v as i8).max(-127) as i32 } else { v as i32 } };
+    let (e0, e1) = (value(block[0]), value(block[1]));
+    let (min, max) = if signed { (-127, 127) } else { (0, 255) };
+    let mut palette = [e0, e1, 0, 0, 0, 0, 0, 0];
+    if e0 > e1 {
+        for i in 1..7 {
+            palette[i + 1] = (e0 * (7 - i as i32) + e1 * i as i32) / 7;
+        }
+    } else {
+        for i in 1..5 {
+            palette[i + 1] = (e0 * (5 - i as i32) + e1 * i as i32) / 5;
+        }
+        palette[6] = min;
+        palette[7] = max;
+    }
+    let mut bits = 0u64;
+    for (i, b) in block[2..8].iter().enumerate() {
+        bits |= (*b as u64) << (8 * i);
+    }
+    let mut result = [0u8; 16];
+    for (i, r) in result.iter_mut().enumerate() {
+        *r = palette[((bits >> (3 * i)) & 7) as usize] as u8;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_bc1_fallback() {
+        // 红、蓝两色，索引依次 0 1 2 3
+        let block = [0x00, 0xF8, 0x1F, 0x00, 0b11100100, 0b11100100, 0b11100100, 0b11100100];
+        let ktx = KtxTexture { width: 4, height: 4, depth: 1, layers: 0, faces: 1, format: wgpu::TextureFormat::Bc1RgbaUnorm, levels: vec![block.to_vec()], alpha: EAlphaMode::Translucent };
+
+        let negotiator = TextureFormatNegotiator::new(wgpu::Features::TEXTURE_COMPRESSION_BC);
+        let (_, path) = negotiator.negotiate(ktx.clone()).unwrap();
+        assert_eq!(path, ETextureLoadPath::Native(wgpu::TextureFormat::Bc1RgbaUnorm));
+
+        let negotiator = TextureFormatNegotiator::new(wgpu::Features::empty());
+        let (ktx, path) = negotiator.negotiate(ktx).unwrap();
+        assert_eq!(path, ETextureLoadPath::Decoded { from: wgpu::TextureFormat::Bc1RgbaUnorm, to: wgpu::TextureFormat::Rgba8Unorm });
+        assert_eq!(ktx.levels[0].len(), 64);
+        assert_eq!(&ktx.levels[0][0..16], &[255, 0, 0, 255, 0, 0, 255, 255, 170, 0, 85, 255, 85, 0, 170, 255]);
+        // 4 色模式没有透明像素
+        assert_eq!(ktx.alpha, EAlphaMode::Opaque);
+        assert_eq!(negotiator.load_ktx(&[0u8; 4]).unwrap_err(), EKtxError::InvalidIdentifier);
+    }
+
+    #[test]
+    fn decode_bc1_3d_levels() {
+        // 4x4x2 两级：第0级 2 个切片，第1级 2x2x1 只有 1 个切片
+        let red = [0x00, 0xF8, 0x00, 0xF8, 0, 0, 0, 0];
+        let blue = [0x1F, 0x00, 0x1F, 0x00, 0, 0, 0, 0];
+        let ktx = KtxTexture {
+            width: 4, height: 4, depth: 2, layers: 0, faces: 1,
+            format: wgpu::TextureFormat::Bc1RgbaUnorm,
+            levels: vec![[red, blue].concat(), blue.to_vec()],
+            alpha: EAlphaMode::Translucent,
+        };
+
+        let negotiator = TextureFormatNegotiator::new(wgpu::Features::empty());
+        let (ktx, _) = negotiator.negotiate(ktx).unwrap();
+        assert_eq!(ktx.levels[0].len(), 4 * 4 * 4 * 2);
+        assert_eq!(&ktx.levels[0][0..4], &[255, 0, 0, 255]);
+        assert_eq!(&ktx.levels[0][64..68], &[0, 0, 255, 255]);
+        assert_eq!(ktx.levels[1].len(), 2 * 2 * 4);
+        assert_eq!(&ktx.levels[1][0..4], &[0, 0, 255, 255]);
+    }
+
+    #[test]
+    fn decode_bc1_alpha() {
+        // c0 <= c1 为 3 色模式，索引 3 为透明
+        let block = [0x1F, 0x00, 0x00, 0xF8, 0b11111100, 0, 0, 0];
+        let ktx = KtxTexture { width: 4, height: 4, depth: 1, layers: 0, faces: 1, format: wgpu::TextureFormat::Bc1RgbaUnorm, levels: vec![block.to_vec()], alpha: EAlphaMode::Translucent };
+        let negotiator = TextureFormatNegotiator::new(wgpu::Features::empty());
+        let (decoded, _) = negotiator.negotiate(ktx.clone()).unwrap();
+        assert_eq!(&decoded.levels[0][12..16], &[0, 0, 0, 0]);
+        assert_eq!(decoded.alpha, EAlphaMode::Mask);
+
+        // 源格式无 Alpha 时不分析
+        let (decoded, _) = negotiator.negotiate(KtxTexture { alpha: EAlphaMode::Opaque, ..ktx }).unwrap();
+        assert_eq!(decoded.alpha, EAlphaMode::Opaque);
+    }
+}
@@ -86,4 +86,40 @@ impl TTextureFormatPixelByte for wgpu::TextureFormat {
             wgpu::TextureFormat::NV12 => 03,
         }
     }
-}
\ No newline at end of file
+}
+
+/// f32 转为半精度浮点的位表示，用于写入 Rgba16Float 等格式，舍入到最近
+pub fn f32_to_f16(value: f32) -> u16 {
+    let bits = value.to_bits();
+    let sign = ((bits >> 16) & 0x8000) as u16;
+    let exponent = ((bits >> 23) & 0xFF) as i32;
+    let mantissa = bits & 0x7F_FFFF;
+    if exponent == 0xFF {
+        // Inf / NaN
+        return sign | 0x7C00 | if mantissa != 0 { 0x200 } else { 0 };
+    }
+    let exponent = exponent - 127 + 15;
+    if exponent >= 0x1F {
+        sign | 0x7C00
+    } else if exponent <= 0 {
+        // 非规格化数
+        if exponent < -10 {
+            return sign;
+        }
+        let mantissa = mantissa | 0x80_0000;
+        let shift = (14 - exponent) as u32;
+        sign | ((mantissa >> shift) + ((mantissa >> (shift - 1)) & 1)) as u16
+    } else {
+        // 舍入进位到指数部分时结果仍正确
+        sign | ((((exponent as u32) << 10) | (mantissa >> 13)) + ((mantissa >> 12) & 1)) as u16
+    }
+}
+
+/// 三个 f32 打包为 Rg11b10Float，负数截断为 0
+pub fn f32_to_rg11b10(r: f32, g: f32, b: f32) -> u32 {
+    // 半精度去掉符号位后，截去低位尾数即为 11 / 10 位无符号浮点
+    let r = (f32_to_f16(r.max(0.)) >> 4) as u32 & 0x7FF;
+    let g = (f32_to_f16(g.max(0.)) >> 4) as u32 & 0x7FF;
+    let b = (f32_to_f16(b.max(0.)) >> 5) as u32 & 0x3FF;
+    r | (g << 11) | (b << 22)
+}
@@ -0,0 +1,362 @@
+//! 纹理流式加载
+//! * 先常驻最粗糙的若干级 mip，再按屏幕上的尺寸逐级升级到更精细的 mip
+//! * 所有流式纹理的常驻显存受 TextureStreamer 的预算约束，预算不足时 最久未可见的纹理 先降回粗糙级别
+//! * 纹理本身的缓存、超时释放 仍由 pi_assets 的 AssetMgr 管理
+//! * 升降级会重建 wgpu 纹理，使用方需根据 version 重建绑定组
+
+use pi_assets::{asset::{Asset, GarbageEmpty, Handle, Size}, mgr::AssetMgr};
+use pi_hal::texture::ImageTexture;
+use pi_share::{Share, ShareMutex, ShareWeak};
+
+use crate::rhi::{device::RenderDevice, RenderQueue};
+
+use super::{image_texture::{KeyImageTexture, ResImageTexture}, KtxTexture};
+
+/// 流式纹理创建时常驻的 mip 级数
+pub const STREAMING_RESIDENT_LEVELS: u32 = 4;
+
+struct StreamingState {
+    texture: ResImageTexture,
+    view: Share<wgpu::TextureView>,
+    /// 常驻的最精细一级
+    base_level: u32,
+    /// 最近可见帧需要的最精细一级
+    required_level: u32,
+    /// 最近一次可见的帧
+    last_visible: u64,
+    version: u32,
+}
+
+struct StreamingInner {
+    key: KeyImageTexture,
+    source: KtxTexture,
+    /// resident_sizes[i] 为 第 i 级及更粗糙各级 的总字节数
+    resident_sizes: Vec<usize>,
+    /// 允许的最粗糙常驻级别
+    coarsest_level: u32,
+    state: ShareMutex<StreamingState>,
+}
+
+/// 流式纹理
+/// * source 须为设备支持的格式，不支持的压缩格式先经 TextureFormatNegotiator 处理
+pub struct StreamingTexture {
+    inner: Share<StreamingInner>,
+    /// 源数据 与 初始常驻部分 的字节数，供 AssetMgr 计算容量
+    size: usize,
+}
+
+impl Asset for StreamingTexture {
+    type Key = KeyImageTexture;
+}
+impl Size for StreamingTexture {
+    fn size(&self) -> usize {
+        self.size
+    }
+}
+
+impl StreamingTexture {
+    pub fn new(device: &RenderDevice, queue: &RenderQueue, key: KeyImageTexture, source: KtxTexture, resident_levels: u32) -> Self {
+        let level_count = source.mip_level_count();
+        let mut resident_sizes = vec![0; level_count as usize + 1];
+        for level in (0..level_count as usize).rev() {
+            resident_sizes[level] = resident_sizes[level + 1] + source.levels[level].len();
+        }
+        resident_sizes.pop();
+
+        // 压缩格式的纹理尺寸须为块的整数倍，更粗糙的级别不能作为纹理的第0级
+        let (block_width, block_height) = source.format.block_dimensions();
+        let mut coarsest_level = 0;
+        while coarsest_level + 1 < level_count
+            && (source.width >> (coarsest_level + 1)) % block_width == 0
+            && (source.height >> (coarsest_level + 1)) % block_height == 0
+            && (source.width >> (coarsest_level + 1)) > 0
+            && (source.height >> (coarsest_level + 1)) > 0
+        {
+            coarsest_level += 1;
+        }
+        let base_level = level_count.saturating_sub(resident_levels.max(1)).min(coarsest_level);
+
+        let (texture, view) = create_resident(device, queue, &key, &source, &resident_sizes, base_level, None);
+        let size = source.size() + resident_sizes[base_level as usize];
+        let inner = StreamingInner {
+            key,
+            source,
+            resident_sizes,
+            coarsest_level,
+            state: ShareMutex::new(StreamingState { texture, view, base_level, required_level: coarsest_level, last_visible: 0, version: 0 }),
+        };
+
+        Self { inner: Share::new(inner), size }
+    }
+    pub fn key(&self) -> &KeyImageTexture {
+        &self.inner.key
+    }
+    /// 完整分辨率的宽
+    pub fn width(&self) -> u32 {
+        self.inner.source.width
+    }
+    /// 完整分辨率的高
+    pub fn height(&self) -> u32 {
+        self.inner.source.height
+    }
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.inner.source.format
+    }
+    /// 当前常驻纹理的视图
+    pub fn view(&self) -> Share<wgpu::TextureView> {
+        self.inner.state.lock().view.clone()
+    }
+    /// 常驻纹理每次重建后递增
+    pub fn version(&self) -> u32 {
+        self.inner.state.lock().version
+    }
+    /// 常驻的最精细 mip 级别
+    pub fn base_level(&self) -> u32 {
+        self.inner.state.lock().base_level
+    }
+    /// 常驻的显存字节数
+    pub fn resident_size(&self) -> usize {
+        let base_level = self.inner.state.lock().base_level;
+        self.inner.resident_sizes[base_level as usize]
+    }
+    /// 屏幕上最大跨度为 screen_extent 像素时 需要的最精细 mip 级别
+    pub fn required_level(&self, screen_extent: f32) -> u32 {
+        required_level(self.inner.source.width.max(self.inner.source.height), screen_extent, self.inner.source.mip_level_count())
+    }
+}
+
+/// 创建以 base_level 为第0级的纹理，已常驻的级别从旧纹理拷贝，其余从源数据写入
+fn create_resident(
+    device: &RenderDevice, queue: &RenderQueue, key: &KeyImageTexture, source: &KtxTexture, resident_sizes: &[usize],
+    base_level: u32, old: Option<&StreamingState>,
+) -> (ResImageTexture, Share<wgpu::TextureView>) {
+    let level_count = source.mip_level_count();
+    let view_dimension = source.view_dimension();
+    let width = (source.width >> base_level).max(1);
+    let height = (source.height >> base_level).max(1);
+    let texture = (**device).create_texture(&wgpu::TextureDescriptor {
+        label: Some(key.url.as_str()),
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: if source.depth > 1 { (source.depth >> base_level).max(1) } else { source.depth_or_array_layers() },
+        },
+        mip_level_count: level_count - base_level,
+        sample_count: 1,
+        dimension: view_dimension.compatible_texture_dimension(),
+        format: source.format,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+
+    let mut encoder = None;
+    for level in base_level..level_count {
+        match old {
+            Some(old) if level >= old.base_level => {
+                let encoder = encoder.get_or_insert_with(|| (**device).create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("texture streaming") }));
+                encoder.copy_texture_to_texture(
+                    wgpu::ImageCopyTexture { texture: old.texture.texture(), mip_level: level - old.base_level, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+                    wgpu::ImageCopyTexture { texture: &texture, mip_level: level - base_level, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+                    source.level_extent(level),
+                );
+            },
+            _ => source.write_level(queue, &texture, level, level - base_level),
+        }
+    }
+    if let Some(encoder) = encoder {
+        queue.submit(Some(encoder.finish()));
+    }
+
+    let view = texture.create_view(&wgpu::TextureViewDescriptor {
+        label: Some(key.url.as_str()),
+        dimension: Some(view_dimension),
+        ..Default::default()
+    });
+    let image = ImageTexture {
+        width, height, size: resident_sizes[base_level as usize], texture, format: source.format, view_dimension, is_opacity: source.alpha.is_opacity()
+    };
+    (ResImageTexture::new(image), Share::new(view))
+}
+
+/// 最大边长为 size 的纹理 显示为 screen_extent 像素时 需要的 mip 级别
+pub fn required_level(size: u32, screen_extent: f32, level_count: u32) -> u32 {
+    if screen_extent <= 0. {
+        return level_count.saturating_sub(1);
+    }
+    let ratio = size as f32 / screen_extent;
+    if ratio <= 1. {
+        0
+    } else {
+        (ratio.log2().floor() as u32).min(level_count.saturating_sub(1))
+    }
+}
+
+/// 一次 update 的统计
+#[derive(Debug, Default, Clone, Copy)]
+pub struct StreamingReport {
+    pub textures: usize,
+    /// 更新后的常驻显存字节数
+    pub resident_size: usize,
+    pub upgraded: usize,
+    pub downgraded: usize,
+}
+
+/// 流式纹理管理
+/// * 每帧先对可见纹理调用 request，再调用 update 统一升降级
+pub struct TextureStreamer {
+    asset_mgr: Share<AssetMgr<StreamingTexture>>,
+    textures: Vec<ShareWeak<StreamingInner>>,
+    budget: usize,
+    resident_levels: u32,
+    frame: u64,
+}
+
+impl TextureStreamer {
+    /// * budget: 流式纹理常驻显存预算
+    /// * capacity, timeout: 同 AssetMgr
+    pub fn new(budget: usize, capacity: usize, timeout: usize) -> Self {
+        Self {
+            asset_mgr: AssetMgr::<StreamingTexture>::new(GarbageEmpty(), false, capacity, timeout),
+            textures: vec![],
+            budget,
+            resident_levels: STREAMING_RESIDENT_LEVELS,
+            frame: 1,
+        }
+    }
+    pub fn budget(&self) -> usize {
+        self.budget
+    }
+    pub fn set_budget(&mut self, budget: usize) {
+        self.budget = budget;
+    }
+    /// 新纹理创建时常驻的 mip 级数
+    pub fn set_resident_levels(&mut self, levels: u32) {
+        self.resident_levels = levels.max(1);
+    }
+    pub fn frame(&self) -> u64 {
+        self.frame
+    }
+    pub fn asset_mgr(&self) -> &Share<AssetMgr<StreamingTexture>> {
+        &self.asset_mgr
+    }
+    pub fn get(&self, key: &KeyImageTexture) -> Option<Handle<StreamingTexture>> {
+        self.asset_mgr.get(key)
+    }
+    /// 创建流式纹理，只上传最粗糙的 resident_levels 级
+    pub fn insert(&mut self, device: &RenderDevice, queue: &RenderQueue, key: KeyImageTexture, source: KtxTexture) -> Option<Handle<StreamingTexture>> {
+        if let Some(texture) = self.asset_mgr.get(&key) {
+            return Some(texture);
+        }
+        let texture = StreamingTexture::new(device, queue, key.clone(), source, self.resident_levels);
+        let weak = Share::downgrade(&texture.inner);
+        match self.asset_mgr.insert(key, texture) {
+            Ok(texture) => {
+                self.textures.push(weak);
+                Some(texture)
+            },
+            Err(_) => None,
+        }
+    }
+    /// 标记纹理本帧可见，screen_extent 为纹理在屏幕上的最大像素跨度
+    /// * 同一帧多次调用取最精细的需求
+    pub fn request(&self, texture: &StreamingTexture, screen_extent: f32) {
+        let level = texture.required_level(screen_extent);
+        let mut state = texture.inner.state.lock();
+        if state.last_visible != self.frame {
+            state.required_level = level;
+            state.last_visible = self.frame;
+        } else {
+            state.required_level = state.required_level.min(level);
+        }
+    }
+    /// 按预算计算各纹理的常驻级别并升降级，之后进入下一帧
+    pub fn update(&mut self, device: &RenderDevice, queue: &RenderQueue) -> StreamingReport {
+        let mut list = Vec::with_capacity(self.textures.len());
+        self.textures.retain(|weak| {
+            if let Some(inner) = weak.upgrade() {
+                list.push(inner);
+                true
+            } else { false }
+        });
+
+        let candidates: Vec<StreamingCandidate> = list.iter().map(|inner| {
+            let state = inner.state.lock();
+            let target = if state.last_visible == self.frame { state.required_level } else { state.base_level };
+            StreamingCandidate { last_visible: state.last_visible, target, coarsest: inner.coarsest_level, sizes: &inner.resident_sizes }
+        }).collect();
+        let levels = assign_levels(&candidates, self.budget);
+
+        let mut report = StreamingReport { textures: list.len(), ..Default::default() };
+        // 先降级释放显存，再升级
+        for upgrade in [false, true] {
+            for (inner, level) in list.iter().zip(levels.iter()) {
+                let mut state = inner.state.lock();
+                if (upgrade && *level < state.base_level) || (!upgrade && *level > state.base_level) {
+                    let (texture, view) = create_resident(device, queue, &inner.key, &inner.source, &inner.resident_sizes, *level, Some(&state));
+                    state.texture = texture;
+                    state.view = view;
+                    state.base_level = *level;
+                    state.version += 1;
+                    if upgrade { report.upgraded += 1; } else { report.downgraded += 1; }
+                }
+            }
+        }
+        report.resident_size = list.iter().zip(levels.iter()).map(|(inner, level)| inner.resident_sizes[*level as usize]).sum();
+
+        self.frame += 1;
+        report
+    }
+}
+
+struct StreamingCandidate<'a> {
+    last_visible: u64,
+    target: u32,
+    coarsest: u32,
+    sizes: &'a [usize],
+}
+
+/// 所有纹理先保留最粗糙级别，剩余预算按最近可见的顺序分配，分配不足的纹理取放得下的最精细级别
+fn assign_levels(candidates: &[StreamingCandidate], budget: usize) -> Vec<u32> {
+    let minimum: usize = candidates.iter().map(|r| r.sizes[r.coarsest as usize]).sum();
+    let mut remain = budget.saturating_sub(minimum);
+
+    let mut order: Vec<usize> = (0..candidates.len()).collect();
+    order.sort_by(|a, b| candidates[*b].last_visible.cmp(&candidates[*a].last_visible));
+
+    let mut result: Vec<u32> = candidates.iter().map(|r| r.coarsest).collect();
+    for index in order {
+        let item = &candidates[index];
+        let base = item.sizes[item.coarsest as usize];
+        let mut level = item.target.min(item.coarsest);
+        while level < item.coarsest && item.sizes[level as usize] - base > remain {
+            level += 1;
+        }
+        remain -= item.sizes[level as usize] - base;
+        result[index] = level;
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn assign_levels_in_budget() {
+        // 4 级 mip：64 16 4 1 字节
+        let sizes = [85, 21, 5, 1];
+        let candidates = [
+            StreamingCandidate { last_visible: 3, target: 0, coarsest: 3, sizes: &sizes },
+            StreamingCandidate { last_visible: 2, target: 0, coarsest: 3, sizes: &sizes },
+            StreamingCandidate { last_visible: 1, target: 1, coarsest: 3, sizes: &sizes },
+        ];
+        // 最近可见的取到 0 级，其次只放得下 1 级，最久未可见的回到最粗糙级别
+        assert_eq!(assign_levels(&candidates, 110), vec![0, 1, 3]);
+        assert_eq!(assign_levels(&candidates, 1000), vec![0, 0, 1]);
+        assert_eq!(assign_levels(&candidates, 0), vec![3, 3, 3]);
+
+        assert_eq!(required_level(1024, 256., 11), 2);
+        assert_eq!(required_level(1024, 2048., 11), 0);
+        assert_eq!(required_level(1024, 0., 11), 10);
+    }
+}
@@ -120,8 +120,8 @@ impl ETextureViewUsage {
             // ETextureViewUsage::Render(val) => val.view(),
             ETextureViewUsage::Tex(val) => &val.texture_view,
             ETextureViewUsage::TexWithId(val) => &val.texture_view,
-            ETextureViewUsage::SRT(val) => &val.target().colors[0].0,
-            ETextureViewUsage::FBORect(val, _, _, _) => &val.colors[0].0,
+            ETextureViewUsage::SRT(val) => val.target().sampled_color(0),
+            ETextureViewUsage::FBORect(val, _, _, _) => val.sampled_color(0),
             ETextureViewUsage::ImageFrame(val) => &val.view,
             // ETextureViewUsage::Temp(val, _) => val,
         }
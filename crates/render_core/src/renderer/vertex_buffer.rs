@@ -13,6 +13,7 @@ use super::{
     vertex_buffer_desc::VertexBufferDesc,
     vertex_format::TVertexFormatByteSize,
     buffer::{FixedSizeBufferPool, AssetRWBuffer, RWBufferRange},
+    indirect::{check_first_instance, DrawIndirectArgs, DrawIndexedIndirectArgs, EIndirectError},
 };
 
 pub type IDAssetVertexBuffer = u64;
@@ -195,6 +196,7 @@ pub struct VertexBufferAllocator {
     asset_mgr_2: Share<AssetMgr<NotUpdatableBuffer>>,
     unupdatables: Vec<FixedSizeBufferPoolNotUpdatable>,
     unupdatables_for_index: Vec<FixedSizeBufferPoolNotUpdatable>,
+    unupdatables_for_indirect: Vec<FixedSizeBufferPoolNotUpdatable>,
     // buffer 是否共用，更新时部分更新
     buffer_sub_update: bool,
 }
@@ -233,6 +235,9 @@ impl VertexBufferAllocator {
         self.unupdatables_for_index.iter().for_each(|item| {
             result += item.total_buffer_count();
         });
+        self.unupdatables_for_indirect.iter().for_each(|item| {
+            result += item.total_buffer_count();
+        });
         result
     }
 
@@ -246,6 +251,9 @@ impl VertexBufferAllocator {
         self.unupdatables_for_index.iter().for_each(|item| {
             result += item.total_buffer_size();
         });
+        self.unupdatables_for_indirect.iter().for_each(|item| {
+            result += item.total_buffer_size();
+        });
         // log::warn!("VertexBuffer: {:?}", result - temp);
         result
     }
@@ -313,6 +321,7 @@ impl VertexBufferAllocator {
             asset_mgr_2,
             unupdatables: vec![],
             unupdatables_for_index: vec![],
+            unupdatables_for_indirect: vec![],
             buffer_sub_update
         }
     }
@@ -484,6 +493,57 @@ impl VertexBufferAllocator {
             }
         }
     }
+
+    /// 创建 draw_indirect 参数 Buffer，用于 DrawIndirect
+    /// * 设备不支持 INDIRECT_FIRST_INSTANCE 时 first_instance 必须为 0
+    pub fn create_indirect_buffer(&mut self, device: &RenderDevice, queue: &RenderQueue, args: &[DrawIndirectArgs]) -> Result<EVertexBufferRange, EIndirectError> {
+        check_first_instance(device.features(), args.iter().map(|v| v.first_instance))?;
+        self.create_not_updatable_buffer_for_indirect(device, queue, bytemuck::cast_slice(args))
+    }
+
+    /// 创建 draw_indexed_indirect 参数 Buffer，用于 DrawIndirect
+    /// * 设备不支持 INDIRECT_FIRST_INSTANCE 时 first_instance 必须为 0
+    pub fn create_indexed_indirect_buffer(&mut self, device: &RenderDevice, queue: &RenderQueue, args: &[DrawIndexedIndirectArgs]) -> Result<EVertexBufferRange, EIndirectError> {
+        check_first_instance(device.features(), args.iter().map(|v| v.first_instance))?;
+        self.create_not_updatable_buffer_for_indirect(device, queue, bytemuck::cast_slice(args))
+    }
+
+    /// * 参数 Buffer 可作为 STORAGE 由计算着色器写入（GPU 剔除），webgl 下没有 STORAGE 用途
+    fn create_not_updatable_buffer_for_indirect(&mut self, device: &RenderDevice, queue: &RenderQueue, data: &[u8]) -> Result<EVertexBufferRange, EIndirectError> {
+        let size = data.len() as u32;
+        if size == 0 {
+            return Err(EIndirectError::AllocateFail(size));
+        }
+        let mut level = 0;
+        let mut level_size = self.base_size;
+        while level_size < size {
+            level_size *= 2;
+            level += 1;
+            if level > 25 {
+                return Err(EIndirectError::AllocateFail(size));
+            }
+        }
+
+        let old_count = self.unupdatables_for_indirect.len();
+        let new_count = level + 1;
+        if old_count < new_count {
+            let usage = if cfg!(feature = "webgl") {
+                wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::INDIRECT
+            } else {
+                wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::INDIRECT | wgpu::BufferUsages::STORAGE
+            };
+            for level in old_count..new_count {
+                self.unupdatables_for_indirect.push(
+                    FixedSizeBufferPoolNotUpdatable::new(self.base_size * 2_i32.pow(level as u32) as u32, usage)
+                );
+            }
+        }
+
+        // 参数均为 u32，长度已 4 字节对齐
+        self.unupdatables_for_indirect.get_mut(level).unwrap().allocate(device, queue, data).map(|range| {
+            EVertexBufferRange::NotUpdatable(Arc::new(range), 0, size)
+        }).ok_or(EIndirectError::AllocateFail(size))
+    }
 }
 
 pub struct FixedSizeBufferPoolNotUpdatable {
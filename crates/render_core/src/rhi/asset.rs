@@ -10,7 +10,7 @@ use pi_key_alloter::KeyData;
 use pi_share::Share;
 use wgpu::{TextureView, AstcBlock, AstcChannel, util::{DeviceExt, TextureDataOrder}};
 
-use super::{device::RenderDevice, RenderQueue, texture::PiRenderDefault};
+use super::{device::RenderDevice, RenderQueue, texture::{PiRenderDefault, alpha::EAlphaMode}};
 
 #[derive(Debug, Deref)]
 pub struct RenderRes<T> {
@@ -56,13 +56,24 @@ pub struct TextureRes {
 	#[deref]
 	pub texture_view: TextureView,
 	pub is_opacity: bool,
+	/// 透明类型，仅有 is_opacity 时为近似值
+	pub alpha: EAlphaMode,
+	/// 颜色是否已预乘 Alpha
+	pub premultiplied: bool,
 	pub format: wgpu::TextureFormat,
 	size: usize,
 }
 
 impl TextureRes {
 	pub fn new(width: u32, height: u32, size: usize, texture_view: TextureView, is_opacity: bool, format: wgpu::TextureFormat) -> Self {
-		Self { width, height, size, texture_view, is_opacity, format }
+		Self { width, height, size, texture_view, is_opacity, alpha: EAlphaMode::from_opacity(is_opacity), premultiplied: false, format }
+	}
+	/// 设置加载时分析得到的透明类型，is_opacity 随之更新
+	pub fn with_alpha(mut self, alpha: EAlphaMode, premultiplied: bool) -> Self {
+		self.is_opacity = alpha.is_opacity();
+		self.alpha = alpha;
+		self.premultiplied = premultiplied;
+		self
 	}
 }
 
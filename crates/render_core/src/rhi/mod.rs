@@ -12,6 +12,11 @@ pub mod dyn_uniform_buffer;
 pub mod options;
 pub mod pipeline;
 pub mod shader;
+pub mod shader_expr;
+pub mod surface;
+pub mod surface_set;
+#[cfg(feature = "hot_reload")]
+pub mod shader_hot_reload;
 pub mod texture;
 pub mod uniform_vec;
 pub mod draw_obj;
@@ -19,6 +24,8 @@ pub mod sampler;
 pub mod small_struct_allocator;
 pub mod buffer_alloc;
 pub mod id_alloter;
+#[cfg(test)]
+pub(crate) mod test_util;
 
 use self::device::RenderDevice;
 use pi_share::Share;
@@ -20,6 +20,8 @@ use uuid::Uuid;
 use wgpu::util::make_spirv;
 use wgpu::{ShaderModuleDescriptor, Device};
 
+use super::shader_expr;
+
 pub trait Input {
     fn location() -> u32;
 }
@@ -121,45 +123,93 @@ pub struct ShaderMeta {
     pub vs: BlockCodeAtom,
     /// 像素代码片段
     pub fs: BlockCodeAtom,
+	/// 构建期翻译的wgsl代码
+	pub wgsl: ShaderWgsl,
 
 	pub name: String,
 }
 
 impl ShaderMeta {
     pub fn to_code(&self, defines: &XHashSet<Atom>, visibility: wgpu::ShaderStages) -> String {
-        let mut code = String::new();
-        if visibility & wgpu::ShaderStages::VERTEX == wgpu::ShaderStages::VERTEX {
-			self.vs.to_define_code(&mut code, defines);
-            self.ins.to_code(&mut code, defines);
-            self.varyings.to_code(&mut code, defines, "out");
-			self.bindings.to_code(&mut code, defines, visibility);
-            self.vs.to_running_code(&mut code, defines);
-        } else {
-			self.fs.to_define_code(&mut code, defines);
-            self.varyings.to_code(&mut code, defines, "in");
-            self.outs.to_code(&mut code, defines);
-			self.bindings.to_code(&mut code, defines, visibility);
-            self.fs.to_running_code(&mut code, defines);
-        }
-
-        code
+        self.to_code_with_source_map(defines, visibility).0
     }
+
+	/// 同 to_code，同时返回 代码每一行 到 代码段的映射
+	/// 代码段名称形如 "{name}:vs:define"，行号为该代码段内的行号
+	pub fn to_code_with_source_map(&self, defines: &XHashSet<Atom>, visibility: wgpu::ShaderStages) -> (String, SourceMap) {
+		let mut code = String::new();
+		let mut source_map = SourceMap::default();
+		let mut section = |code: &mut String, name: &str, f: &dyn Fn(&mut String)| {
+			let start = code.len();
+			f(code);
+			source_map.push_section(&format!("{}:{}", self.name, name), &code[start..]);
+		};
+		if visibility & wgpu::ShaderStages::VERTEX == wgpu::ShaderStages::VERTEX {
+			section(&mut code, "vs:define", &|code: &mut String| self.vs.to_define_code(code, defines));
+			section(&mut code, "vs:input", &|code: &mut String| self.ins.to_code(code, defines));
+			section(&mut code, "vs:varying", &|code: &mut String| self.varyings.to_code(code, defines, "out"));
+			section(&mut code, "vs:binding", &|code: &mut String| self.bindings.to_code(code, defines, visibility));
+			section(&mut code, "vs:main", &|code: &mut String| self.vs.to_running_code(code, defines));
+		} else {
+			section(&mut code, "fs:define", &|code: &mut String| self.fs.to_define_code(code, defines));
+			section(&mut code, "fs:varying", &|code: &mut String| self.varyings.to_code(code, defines, "in"));
+			section(&mut code, "fs:output", &|code: &mut String| self.outs.to_code(code, defines));
+			section(&mut code, "fs:binding", &|code: &mut String| self.bindings.to_code(code, defines, visibility));
+			section(&mut code, "fs:main", &|code: &mut String| self.fs.to_running_code(code, defines));
+		}
+
+		(code, source_map)
+	}
+
+	/// 生成wgsl代码
+	/// 先生成glsl代码，再通过naga翻译为wgsl
+	pub fn to_wgsl(&self, defines: &XHashSet<Atom>, visibility: wgpu::ShaderStages) -> Result<String, ShaderReflectError> {
+		let stage = if visibility & wgpu::ShaderStages::VERTEX == wgpu::ShaderStages::VERTEX {
+			naga::ShaderStage::Vertex
+		} else {
+			naga::ShaderStage::Fragment
+		};
+		let (code, source_map) = self.to_code_with_source_map(defines, visibility);
+		ProcessedShader::Glsl(Cow::Owned(code), stage)
+			.reflect_with_source_map(&source_map)?
+			.get_wgsl()
+			.map_err(ShaderReflectError::WgslConversion)
+	}
+
+	/// 创建ShaderModule
+	/// * 开启wgsl特性时，defines对应的组合未在构建期翻译 会panic，需要处理错误时使用 try_create_shader_module
 	pub fn create_shader_module(&self, device: &Device, defines: &XHashSet<Atom>, stage: naga::ShaderStage) -> wgpu::ShaderModule {
+		self.try_create_shader_module(device, defines, stage).unwrap_or_else(|e| panic!("create shader module fail, name={:?}, stage={stage:?}, err={e}", &self.name))
+	}
+
+	/// 创建ShaderModule
+	/// * 开启wgsl特性时，使用构建期翻译的wgsl，defines对应的组合未翻译时返回错误
+	pub fn try_create_shader_module(&self, device: &Device, defines: &XHashSet<Atom>, stage: naga::ShaderStage) -> Result<wgpu::ShaderModule, ShaderReflectError> {
 		let s = match stage {
 			naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
 			naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
 			naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
 		};
+
+		// WebGPU目标，只使用构建期翻译的wgsl，运行时不解析glsl
+		#[cfg(feature = "wgsl")]
+		let code = self.wgsl.get(defines, s).ok_or_else(|| ShaderReflectError::WgslNotBuilt(self.name.clone(), defines.iter().cloned().collect()))?;
+		#[cfg(not(feature = "wgsl"))]
 		let code = self.to_code(defines, s);
 		log::debug!("shader_code====,\nname={:?},\nstage={stage:?}\ndefines={defines:?}\ncode=\n{code}", &self.name);
-		return device.create_shader_module(wgpu::ShaderModuleDescriptor {
-            label: Some(&self.name),
-            source: wgpu::ShaderSource::Glsl {
-                shader: Cow::Borrowed(code.as_str()),
-                stage,
-                defines: naga::FastHashMap::default(),
-            },
-        });
+
+		#[cfg(feature = "wgsl")]
+		let source = wgpu::ShaderSource::Wgsl(Cow::Borrowed(code));
+		#[cfg(not(feature = "wgsl"))]
+		let source = wgpu::ShaderSource::Glsl {
+			shader: Cow::Borrowed(code.as_str()),
+			stage,
+			defines: naga::FastHashMap::default(),
+		};
+		Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+			label: Some(&self.name),
+			source,
+		}))
 	}
 
     pub fn add_binding_entry(
@@ -558,6 +608,38 @@ impl BlockCodeAtom {
     }
 }
 
+/// 构建期由render_compile翻译的wgsl代码，每种宏组合一份
+#[derive(Debug, Clone, Default, Hash)]
+pub struct ShaderWgsl {
+	/// 着色器中使用的所有宏
+	pub defines: Vec<Atom>,
+	pub variants: Vec<WgslVariant>,
+}
+
+impl ShaderWgsl {
+	/// 取defines对应组合的代码，与着色器无关的宏被忽略
+	pub fn get(&self, defines: &XHashSet<Atom>, visibility: wgpu::ShaderStages) -> Option<&str> {
+		let count = self.defines.iter().filter(|r| defines.contains(*r)).count();
+		let variant = self.variants.iter().find(|r| r.defines.len() == count && r.defines.iter().all(|d| defines.contains(d)))?;
+		if visibility & wgpu::ShaderStages::VERTEX == wgpu::ShaderStages::VERTEX {
+			Some(&variant.vs)
+		} else if visibility & wgpu::ShaderStages::FRAGMENT == wgpu::ShaderStages::FRAGMENT {
+			Some(&variant.fs)
+		} else {
+			None
+		}
+	}
+}
+
+/// 一种宏组合的wgsl代码
+#[derive(Debug, Clone, Hash)]
+pub struct WgslVariant {
+	/// 开启的宏
+	pub defines: Vec<Atom>,
+	pub vs: Atom,
+	pub fs: Atom,
+}
+
 /// 代码片段
 #[derive(Debug, Clone, Hash)]
 pub struct CodeSlice {
@@ -619,8 +701,11 @@ impl ShaderId {
 /// Shader 解析 错误
 #[derive(Error, Debug)]
 pub enum ShaderReflectError {
-    // #[error(transparent)]
-    // WgslParse(#[from] naga::front::wgsl::ParseError),
+    #[error(transparent)]
+    WgslParse(#[from] naga::front::wgsl::ParseError),
+
+    #[error(transparent)]
+    WgslConversion(#[from] naga::back::wgsl::Error),
 
     #[error("GLSL Parse Error: {0:?}")]
     GlslParse(Vec<naga::front::glsl::Error>),
@@ -631,6 +716,122 @@ pub enum ShaderReflectError {
 
     #[error(transparent)]
     Validation(#[from] naga::WithSpan<naga::valid::ValidationError>),
+
+    /// 已映射到原始文件位置的错误
+    #[error("{}", .0.iter().map(|r| r.to_string()).collect::<Vec<_>>().join("\n"))]
+    Mapped(Vec<ShaderErrorLocation>, Box<ShaderReflectError>),
+
+    /// 构建期未翻译该宏组合的wgsl
+    #[error("wgsl is not built, shader: {0:?}, defines: {1:?}")]
+    WgslNotBuilt(String, Vec<Atom>),
+}
+
+/// 错误在原始代码中的位置
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ShaderErrorLocation {
+    /// 文件路径；无路径时为 ShaderId 或 ShaderMeta 的代码段名称
+    pub source: String,
+    /// 行号，从1开始
+    pub line: usize,
+    /// 列号，从1开始
+    pub column: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for ShaderErrorLocation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}:{}: {}", self.source, self.line, self.column, self.message)
+    }
+}
+
+/// 源码映射：处理后代码（#import 展开、ShaderMeta 拼接）的每一行 对应的 原始位置
+#[derive(Debug, Clone, Default)]
+pub struct SourceMap {
+    /// 来源名称
+    sources: Vec<String>,
+    /// 处理后的每一行：(sources 索引, 原始行号)
+    lines: Vec<(usize, usize)>,
+}
+
+impl SourceMap {
+    /// 追加一行
+    pub fn push_line(&mut self, source: &str, line: usize) {
+        let index = match self.sources.iter().rposition(|r| r == source) {
+            Some(r) => r,
+            None => {
+                self.sources.push(source.to_string());
+                self.sources.len() - 1
+            }
+        };
+        self.lines.push((index, line));
+    }
+
+    /// 追加一段代码的所有行，行号从1开始
+    pub fn push_section(&mut self, source: &str, code: &str) {
+        for line in 1..=code.lines().count() {
+            self.push_line(source, line);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    /// 处理后代码的行号（从1开始） -> (来源名称, 原始行号)
+    pub fn locate(&self, line: usize) -> Option<(&str, usize)> {
+        let (source, line) = self.lines.get(line.checked_sub(1)?)?;
+        Some((self.sources[*source].as_str(), *line))
+    }
+
+    /// 将 naga 的错误 映射到原始位置
+    /// code 为产生错误的 处理后代码；无法映射的错误原样返回
+    pub fn map_error(&self, code: &str, err: ShaderReflectError) -> ShaderReflectError {
+        let mut locations = Vec::new();
+        match &err {
+            ShaderReflectError::GlslParse(errors) => {
+                for e in errors.iter() {
+                    self.push_location(code, e.meta, e.kind.to_string(), &mut locations);
+                }
+            }
+            ShaderReflectError::Validation(e) => {
+                let message = e.as_inner().to_string();
+                for (span, label) in e.spans() {
+                    let message = if label.is_empty() { message.clone() } else { format!("{}: {}", message, label) };
+                    self.push_location(code, *span, message, &mut locations);
+                }
+            }
+            ShaderReflectError::WgslParse(e) => {
+                if let Some(location) = e.location(code) {
+                    locations.push(self.to_location(location, e.to_string()));
+                }
+            }
+            _ => {}
+        }
+        if locations.is_empty() {
+            err
+        } else {
+            ShaderReflectError::Mapped(locations, Box::new(err))
+        }
+    }
+
+    fn push_location(&self, code: &str, span: naga::Span, message: String, locations: &mut Vec<ShaderErrorLocation>) {
+        if span.is_defined() {
+            locations.push(self.to_location(span.location(code), message));
+        }
+    }
+
+    fn to_location(&self, location: naga::SourceLocation, message: String) -> ShaderErrorLocation {
+        let line = location.line_number as usize;
+        let (source, line) = match self.locate(line) {
+            Some((source, line)) => (source.to_string(), line),
+            None => ("<processed>".to_string(), line),
+        };
+        ShaderErrorLocation { source, line, column: location.line_position as usize, message }
+    }
 }
 
 /// Shader: [`ShaderSource`] and [`ShaderStage`](naga::ShaderStage)
@@ -715,6 +916,25 @@ impl Shader {
     pub fn imports(&self) -> impl ExactSizeIterator<Item = &ShaderImport> {
         self.imports.iter()
     }
+
+    /// 替换源码（id不变），重新提取 #import，返回旧的源码
+    /// 热重载时使用，使依赖该id的其他shader无需改动
+    pub fn replace_source(&mut self, source: Source) -> Source {
+        let ShaderImports {
+            imports,
+            import_path,
+        } = match &source {
+            Source::Wgsl(code) => SHADER_IMPORT_PROCESSOR.get_imports_from_str(code),
+            Source::Glsl(code, _stage) => SHADER_IMPORT_PROCESSOR.get_imports_from_str(code),
+			#[cfg(feature="wgpu/spirv")]
+            Source::SpirV(_) => ShaderImports::default(),
+        };
+        self.imports = imports;
+        if import_path.is_some() {
+            self.import = import_path;
+        }
+        std::mem::replace(&mut self.source, source)
+    }
 }
 
 /// Shader 源码
@@ -758,7 +978,7 @@ impl ProcessedShader {
     /// 反射
     pub fn reflect(&self) -> Result<ShaderReflection, ShaderReflectError> {
         let module = match &self {
-            ProcessedShader::Wgsl(_source) => {todo!()}//naga::front::wgsl::parse_str(source)?,
+            ProcessedShader::Wgsl(source) => naga::front::wgsl::parse_str(source)?,
             ProcessedShader::Glsl(source, shader_stage) => {
                 let mut parser = naga::front::glsl::Frontend::default();
                 parser
@@ -788,28 +1008,34 @@ impl ProcessedShader {
         })
     }
 
+    /// 反射，错误位置映射到原始文件
+    pub fn reflect_with_source_map(&self, source_map: &SourceMap) -> Result<ShaderReflection, ShaderReflectError> {
+        self.reflect().map_err(|e| match self {
+            ProcessedShader::Wgsl(source) | ProcessedShader::Glsl(source, _) => source_map.map_error(source, e),
+            #[cfg(feature="wgpu/spirv")]
+            ProcessedShader::SpirV(_) => e,
+        })
+    }
+
     /// 取对应的 描述符
     pub fn get_module_descriptor(&self) -> Result<ShaderModuleDescriptor, AsModuleDescriptorError> {
-		#[allow(unreachable_code)]
         Ok(ShaderModuleDescriptor {
             label: None,
             source: match self {
-                ProcessedShader::Wgsl(_source) => {
+                ProcessedShader::Wgsl(source) => {
                     // This isn't neccessary, but catches errors early during hot reloading of invalid wgsl shaders.
                     // Eventually, wgpu will have features that will make this unneccessary like compilation info or error scopes, but until then parsing the shader twice during development the easiest solution.
                     #[cfg(debug_assertions)]
                     let _ = self.reflect()?;
 
-					todo!();
-                    // ShaderSource::Wgsl(source.clone())
+                    wgpu::ShaderSource::Wgsl(source.clone())
                 }
                 ProcessedShader::Glsl(_source, _stage) => {
-                    let _reflection = self.reflect()?;
+                    let reflection = self.reflect()?;
 
                     // 通过 反射信息 转换成 wgsl
-                    // let wgsl = reflection.get_wgsl()?;
-					todo!()
-                    // ShaderSource::Wgsl(wgsl.into())
+                    let wgsl = reflection.get_wgsl().map_err(ShaderReflectError::from)?;
+                    wgpu::ShaderSource::Wgsl(wgsl.into())
                 }
 				#[cfg(feature="wgpu/spirv")]
                 ProcessedShader::SpirV(source) => make_spirv(source),
@@ -850,10 +1076,14 @@ impl ShaderReflection {
     //     )
     // }
 
-    // /// 转 wgsl
-    // pub fn get_wgsl(&self) -> Result<String, naga::back::wgsl::Error> {
-    //     naga::back::wgsl::write_string(&self.module, &self.module_info, WriterFlags::EXPLICIT_TYPES)
-    // }
+    /// 转 wgsl
+    pub fn get_wgsl(&self) -> Result<String, naga::back::wgsl::Error> {
+        naga::back::wgsl::write_string(
+            &self.module,
+            &self.module_info,
+            naga::back::wgsl::WriterFlags::EXPLICIT_TYPES,
+        )
+    }
 }
 
 /// 加载 Shader
@@ -890,13 +1120,19 @@ pub fn load_shader(
 /// 预处理 Shader 遇到的 错误
 #[derive(Error, Debug, PartialEq, Eq)]
 pub enum ProcessShaderError {
-    #[error("Too many '# endif' lines. Each endif should be preceded by an if statement.")]
-    TooManyEndIfs,
+    #[error("Too many '# endif' lines (line {0}). Each endif should be preceded by an if statement.")]
+    TooManyEndIfs(usize),
 
     #[error(
-        "Not enough '# endif' lines. Each if statement should be followed by an endif statement."
+        "Not enough '# endif' lines. The if statement at line {0} should be followed by an endif statement."
     )]
-    NotEnoughEndIfs,
+    NotEnoughEndIfs(usize),
+
+    #[error("Unexpected '# {1}' at line {0}.")]
+    UnexpectedDirective(usize, &'static str),
+
+    #[error("Invalid expression at line {0}: {1}")]
+    InvalidExpression(usize, String),
 
     #[error("This Shader's format does not support processing shader defs.")]
     ShaderFormatDoesNotSupportShaderDefs,
@@ -989,26 +1225,25 @@ impl ShaderImportProcessor {
 pub static SHADER_IMPORT_PROCESSOR: Lazy<ShaderImportProcessor> =
     Lazy::new(ShaderImportProcessor::default);
 
-/// 预处理器：#ifdef, #ifndef, #else #endif
+/// 预处理器：#ifdef, #ifndef, #if, #elif, #else, #endif, #define, #undef
+/// * #if、#elif 支持整数表达式与 defined(X)，未定义的宏为0，只定义了名字的宏为1
+/// * #define NAME VALUE 定义的值宏（以及 Defineds 中带值的宏），会替换到代码中
 pub struct ShaderProcessor<L: CodeLoader> {
     ifdef_regex: Regex,
     ifndef_regex: Regex,
+    if_regex: Regex,
+    elif_regex: Regex,
     else_regex: Regex,
     endif_regex: Regex,
+    define_regex: Regex,
+    undef_regex: Regex,
     default_value_regex: Regex,
     loader: L,
 }
 
 impl Default for ShaderProcessor<CodeLoaderEmptyImpl> {
     fn default() -> Self {
-        Self {
-            ifdef_regex: Regex::new(r"^\s*#\s*ifdef\s*([\w|\d|_]+)").unwrap(),
-            ifndef_regex: Regex::new(r"^\s*#\s*ifndef\s*([\w|\d|_]+)").unwrap(),
-            else_regex: Regex::new(r"^\s*#\s*else").unwrap(),
-            endif_regex: Regex::new(r"^\s*#\s*endif").unwrap(),
-            default_value_regex: Regex::new(r#"^\s*@default\s*\(\s*([0-9.,\s]+)\)"#).unwrap(),
-            loader: CodeLoaderEmptyImpl,
-        }
+        Self::new(CodeLoaderEmptyImpl)
     }
 }
 
@@ -1017,8 +1252,13 @@ impl<L: CodeLoader> ShaderProcessor<L> {
         Self {
             ifdef_regex: Regex::new(r"^\s*#\s*ifdef\s*([\w|\d|_]+)").unwrap(),
             ifndef_regex: Regex::new(r"^\s*#\s*ifndef\s*([\w|\d|_]+)").unwrap(),
+            if_regex: Regex::new(r"^\s*#\s*if\s+(.+)$").unwrap(),
+            elif_regex: Regex::new(r"^\s*#\s*elif\s+(.+)$").unwrap(),
             else_regex: Regex::new(r"^\s*#\s*else").unwrap(),
             endif_regex: Regex::new(r"^\s*#\s*endif").unwrap(),
+            // 不匹配 #define_import_path 和 带参数的宏 #define F(x)
+            define_regex: Regex::new(r"^\s*#\s*define\s+([A-Za-z_]\w*)(\s+.*)?$").unwrap(),
+            undef_regex: Regex::new(r"^\s*#\s*undef\s+([A-Za-z_]\w*)").unwrap(),
             default_value_regex: Regex::new(r#"^\s*@default\s*\(\s*([0-9.,\s]+)\)"#).unwrap(),
             loader: l,
         }
@@ -1028,6 +1268,25 @@ impl<L: CodeLoader> ShaderProcessor<L> {
 pub trait Defineds {
     fn contains(&self, value: &str) -> bool;
     fn is_empty(&self) -> bool;
+    /// 宏的值（如 MAX_LIGHTS -> "8"），未定义或无值时返回None
+    fn value(&self, _name: &str) -> Option<&str> {
+        None
+    }
+}
+
+/// 带值的宏：key为宏名，value为值（可为空字符串）
+impl Defineds for XHashMap<String, String> {
+    fn contains(&self, value: &str) -> bool {
+        self.contains_key(value)
+    }
+
+    fn is_empty(&self) -> bool {
+        self.is_empty()
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        self.get(name).map(|r| r.as_str()).filter(|r| !r.is_empty())
+    }
 }
 
 impl Defineds for XHashSet<String> {
@@ -1052,6 +1311,63 @@ impl Defineds for AllDefineds {
     }
 }
 
+/// 外部传入的宏 叠加 shader 中 #define、#undef 的宏
+struct ScopedDefineds<'a> {
+    parent: &'a dyn Defineds,
+    /// None 表示被 #undef
+    locals: &'a XHashMap<String, Option<String>>,
+}
+
+impl<'a> ScopedDefineds<'a> {
+    /// 表达式求值用：未定义返回None，无值返回空字符串
+    fn resolve(&self, name: &str) -> Option<String> {
+        if self.contains(name) {
+            Some(self.value(name).unwrap_or("").to_string())
+        } else {
+            None
+        }
+    }
+}
+
+impl<'a> Defineds for ScopedDefineds<'a> {
+    fn contains(&self, value: &str) -> bool {
+        match self.locals.get(value) {
+            Some(r) => r.is_some(),
+            None => self.parent.contains(value),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.parent.is_empty() && !self.locals.values().any(|r| r.is_some())
+    }
+
+    fn value(&self, name: &str) -> Option<&str> {
+        match self.locals.get(name) {
+            Some(r) => r.as_deref().filter(|r| !r.is_empty()),
+            None => self.parent.value(name),
+        }
+    }
+}
+
+/// #if 块的状态
+struct IfScope {
+    /// 当前分支是否输出
+    active: bool,
+    /// 是否已有分支被选中，之后的 #elif、#else 不再选中
+    taken: bool,
+    /// 是否已遇到 #else
+    has_else: bool,
+    parent_active: bool,
+    /// #if 所在行，报错用
+    line: usize,
+}
+
+impl IfScope {
+    fn new(line: usize, parent_active: bool, cond: bool) -> Self {
+        Self { active: parent_active && cond, taken: cond, has_else: false, parent_active, line }
+    }
+}
+
 pub trait CodeLoader {
     fn load(&self, path: &PathBuf) -> Result<Vec<u8>, ProcessShaderError>;
 }
@@ -1080,7 +1396,31 @@ impl<L: CodeLoader> ShaderProcessor<L> {
         shader_defs: &D,
         shader_mgr: &mut ShaderCodeMgr,
     ) -> Result<ProcessedShader, ProcessShaderError> {
-        let (shader, _path) = shader_mgr.shaders.get(id).unwrap().clone();
+        self.process_inner(id, shader_defs, shader_mgr, &mut SourceMap::default())
+    }
+
+    /// 同 process，同时返回 处理后代码每一行 到 原始文件行 的映射
+    pub fn process_with_source_map<D: Defineds>(
+        &self,
+        id: &ShaderId,
+        shader_defs: &D,
+        shader_mgr: &mut ShaderCodeMgr,
+    ) -> Result<(ProcessedShader, SourceMap), ProcessShaderError> {
+        let mut source_map = SourceMap::default();
+        let processed = self.process_inner(id, shader_defs, shader_mgr, &mut source_map)?;
+        Ok((processed, source_map))
+    }
+
+    fn process_inner<D: Defineds>(
+        &self,
+        id: &ShaderId,
+        shader_defs: &D,
+        shader_mgr: &mut ShaderCodeMgr,
+        source_map: &mut SourceMap,
+    ) -> Result<ProcessedShader, ProcessShaderError> {
+        let (shader, path) = shader_mgr.shaders.get(id).unwrap().clone();
+        // 源码映射中的名称，无路径时使用 ShaderId
+        let source_name = path.unwrap_or_else(|| format!("{:?}", id));
         let shader_str = match &shader.source {
             Source::Wgsl(source) => source.deref(),
             Source::Glsl(source, _stage) => source.deref(),
@@ -1094,71 +1434,105 @@ impl<L: CodeLoader> ShaderProcessor<L> {
             }
         };
 
-        let mut scopes = vec![true];
+        let mut scopes = vec![IfScope::new(0, true, true)];
+        // 本 shader 中 #define、#undef 的宏，对其 #import 的 shader 同样生效
+        let mut locals: XHashMap<String, Option<String>> = XHashMap::default();
         let mut final_string = String::new();
 
-        // 逐行处理
-        for line in shader_str.lines() {
+        // 逐行处理，行号从1开始
+        for (index, line) in shader_str.lines().enumerate() {
+            let line_number = index + 1;
+            let active = scopes.last().unwrap().active;
             // 遇到 #ifdef
             if let Some(cap) = self.ifdef_regex.captures(line) {
                 // 取对应的 def
                 let def = cap.get(1).unwrap();
                 // 将 shader_defs 是否 含 该 def 的结果 加到 scopes 中
-                scopes.push(*scopes.last().unwrap() && shader_defs.contains(def.as_str()));
+                let defineds = ScopedDefineds { parent: shader_defs, locals: &locals };
+                scopes.push(IfScope::new(line_number, active, defineds.contains(def.as_str())));
             } else if let Some(cap) = self.ifndef_regex.captures(line) {
                 // #ifndef 就将结果 取反，然后加到 scopes中
                 let def = cap.get(1).unwrap();
-                scopes.push(*scopes.last().unwrap() && !shader_defs.contains(def.as_str()));
+                let defineds = ScopedDefineds { parent: shader_defs, locals: &locals };
+                scopes.push(IfScope::new(line_number, active, !defineds.contains(def.as_str())));
+            } else if let Some(cap) = self.if_regex.captures(line) {
+                // 父块不输出时，不对表达式求值
+                let cond = active && eval_condition(cap.get(1).unwrap().as_str(), shader_defs, &locals, line_number)?;
+                scopes.push(IfScope::new(line_number, active, cond));
+            } else if let Some(cap) = self.elif_regex.captures(line) {
+                let scope = scopes.last().unwrap();
+                if scopes.len() == 1 || scope.has_else {
+                    return Err(ProcessShaderError::UnexpectedDirective(line_number, "elif"));
+                }
+                let cond = scope.parent_active
+                    && !scope.taken
+                    && eval_condition(cap.get(1).unwrap().as_str(), shader_defs, &locals, line_number)?;
+                let scope = scopes.last_mut().unwrap();
+                scope.active = cond;
+                scope.taken |= cond;
             } else if self.else_regex.is_match(line) {
                 // 遇到 #else
-                let mut is_parent_scope_truthy = true;
-                if scopes.len() > 1 {
-                    is_parent_scope_truthy = scopes[scopes.len() - 2];
-                }
-                if let Some(last) = scopes.last_mut() {
-                    *last = is_parent_scope_truthy && !*last;
+                let scope = scopes.last_mut().unwrap();
+                if scope.line == 0 || scope.has_else {
+                    return Err(ProcessShaderError::UnexpectedDirective(line_number, "else"));
                 }
+                scope.active = scope.parent_active && !scope.taken;
+                scope.taken = true;
+                scope.has_else = true;
             } else if self.endif_regex.is_match(line) {
                 // 遇到 #endif，scopes 结束
+                if scopes.len() == 1 {
+                    return Err(ProcessShaderError::TooManyEndIfs(line_number));
+                }
                 scopes.pop();
-
-                if scopes.is_empty() {
-                    return Err(ProcessShaderError::TooManyEndIfs);
+            } else if let Some(cap) = self.define_regex.captures(line) {
+                if active {
+                    let value = cap.get(2).map(|r| r.as_str().trim().to_string()).unwrap_or_default();
+                    locals.insert(cap.get(1).unwrap().as_str().to_string(), Some(value));
+                }
+            } else if let Some(cap) = self.undef_regex.captures(line) {
+                if active {
+                    locals.insert(cap.get(1).unwrap().as_str().to_string(), None);
                 }
             } else if let Some(cap) = SHADER_IMPORT_PROCESSOR
                 .import_asset_path_regex
                 .captures(line)
             {
                 // 遇到 #import "..." 语句
-                let import_path = cap.get(1).unwrap().as_str();
-                // let import_path = match &path {
-                //     Some(p) => import_path.to_string(),
-                //     None => cap.get(1).unwrap().as_str().to_string(),
-                // };
-                let import = ShaderImport::Path(import_path.to_string());
-
-                self.apply_import(id, shader_defs, shader_mgr, &import, &mut final_string)?;
+                if active {
+                    let import_path = cap.get(1).unwrap().as_str();
+                    let import = ShaderImport::Path(import_path.to_string());
+                    let defineds = ScopedDefineds { parent: shader_defs, locals: &locals };
+                    self.apply_import_inner(id, &defineds, shader_mgr, &import, &mut final_string, source_map)?;
+                }
             } else if let Some(cap) = SHADER_IMPORT_PROCESSOR
                 .import_custom_path_regex
                 .captures(line)
             {
                 // 遇到 #import ... 语句
-                let import = ShaderImport::Custom(cap.get(1).unwrap().as_str().to_string());
-                self.apply_import(id, shader_defs, shader_mgr, &import, &mut final_string)?;
+                if active {
+                    let import = ShaderImport::Custom(cap.get(1).unwrap().as_str().to_string());
+                    let defineds = ScopedDefineds { parent: shader_defs, locals: &locals };
+                    self.apply_import_inner(id, &defineds, shader_mgr, &import, &mut final_string, source_map)?;
+                }
             } else if SHADER_IMPORT_PROCESSOR
                 .define_import_path_regex
                 .is_match(line)
                 || self.default_value_regex.is_match(line)
             {
                 // ignore import path lines
-            } else if *scopes.last().unwrap() {
-                final_string.push_str(line);
+            } else if active {
+                // 替换值宏
+                let defineds = ScopedDefineds { parent: shader_defs, locals: &locals };
+                let line = shader_expr::substitute(line, &|name: &str| defineds.value(name).map(|r| r.to_string()));
+                final_string.push_str(&line);
                 final_string.push('\n');
+                source_map.push_line(&source_name, line_number);
             }
         }
 
         if scopes.len() != 1 {
-            return Err(ProcessShaderError::NotEnoughEndIfs);
+            return Err(ProcessShaderError::NotEnoughEndIfs(scopes.last().unwrap().line));
         }
 
         let processed_source = Cow::from(final_string);
@@ -1181,6 +1555,18 @@ impl<L: CodeLoader> ShaderProcessor<L> {
         shader_mgr: &mut ShaderCodeMgr,
         import: &ShaderImport,
         final_string: &mut String,
+    ) -> Result<(), ProcessShaderError> {
+        self.apply_import_inner(id, shader_defs, shader_mgr, import, final_string, &mut SourceMap::default())
+    }
+
+    fn apply_import_inner<D: Defineds>(
+        &self,
+        id: &ShaderId,
+        shader_defs: &D,
+        shader_mgr: &mut ShaderCodeMgr,
+        import: &ShaderImport,
+        final_string: &mut String,
+        source_map: &mut SourceMap,
     ) -> Result<(), ProcessShaderError> {
         let imported_shader = match shader_mgr.import_shaders.get(import) {
             Some(r) => r.clone(),
@@ -1219,7 +1605,8 @@ impl<L: CodeLoader> ShaderProcessor<L> {
         //     .ok_or_else(|| {
 
         // 	})?;
-        let imported_processed = self.process(&imported_shader, shader_defs, shader_mgr)?;
+        // 被导入的代码整体追加到 final_string，其行映射按相同顺序记录
+        let imported_processed = self.process_inner(&imported_shader, shader_defs, shader_mgr, source_map)?;
 
         let (shader, _path) = shader_mgr.shaders.get(id).unwrap();
 
@@ -1248,6 +1635,19 @@ impl<L: CodeLoader> ShaderProcessor<L> {
     }
 }
 
+/// 对 #if、#elif 的表达式求值
+fn eval_condition(
+    expr: &str,
+    shader_defs: &dyn Defineds,
+    locals: &XHashMap<String, Option<String>>,
+    line: usize,
+) -> Result<bool, ProcessShaderError> {
+    let defineds = ScopedDefineds { parent: shader_defs, locals };
+    shader_expr::eval(expr, &|name: &str| defineds.resolve(name))
+        .map(|r| r != 0)
+        .map_err(|e| ProcessShaderError::InvalidExpression(line, e))
+}
+
 // fn relative_path(mut file_path: &str, mut dir: &str) -> String {
 //     let (file_path_len, dir_len) = (file_path.len(), dir.len());
 //     if file_path_len == 0 {
@@ -1312,7 +1712,8 @@ impl<L: CodeLoader> ShaderProcessor<L> {
 #[cfg(test)]
 mod tests {
     use crate::rhi::shader::{
-        ProcessShaderError, Shader, ShaderCodeMgr, ShaderImport, ShaderProcessor,
+        Defineds, ProcessShaderError, ProcessedShader, Shader, ShaderCodeMgr, ShaderImport, ShaderProcessor,
+        ShaderReflectError,
     };
     use naga::ShaderStage;
     use pi_hash::{XHashMap, XHashSet};
@@ -1618,7 +2019,7 @@ fn vertex(
                 import_shaders: XHashMap::default(),
             },
         );
-        assert_eq!(result, Err(ProcessShaderError::NotEnoughEndIfs));
+        assert_eq!(result, Err(ProcessShaderError::NotEnoughEndIfs(2)));
     }
 
     #[test]
@@ -1642,7 +2043,7 @@ fn vertex(
                 import_shaders: XHashMap::default(),
             },
         );
-        assert_eq!(result, Err(ProcessShaderError::TooManyEndIfs));
+        assert_eq!(result, Err(ProcessShaderError::TooManyEndIfs(2)));
     }
 
     #[test]
@@ -2182,6 +2583,143 @@ fn in_main() { }
         let _r = result.get_wgsl_source().unwrap();
         assert_eq!(result.get_wgsl_source().unwrap(), EXPECTED);
     }
+
+    fn process_wgsl<D: Defineds>(input: &'static str, shader_defs: &D) -> Result<ProcessedShader, ProcessShaderError> {
+        let shader = Shader::from_wgsl(input);
+        let id = shader.id();
+        let mut shaders = XHashMap::default();
+        shaders.insert(shader.id(), (shader, None));
+
+        ShaderProcessor::default().process(
+            &id,
+            shader_defs,
+            &mut ShaderCodeMgr {
+                shaders,
+                import_shaders: XHashMap::default(),
+            },
+        )
+    }
+
+    #[test]
+    fn process_if_elif_value_defines() {
+        #[rustfmt::skip]
+        const INPUT: &str = r"
+#if MAX_LIGHTS > 4 && defined(QUALITY)
+const LIGHTS: u32 = MAX_LIGHTS;
+#elif QUALITY == 2
+fn elif() { }
+#else
+fn other() { }
+#endif
+#if QUALITY == 1
+fn low() { }
+#elif QUALITY == 2
+fn mid() { }
+#else
+fn high() { }
+#endif
+";
+        #[rustfmt::skip]
+        const EXPECTED: &str = r"
+const LIGHTS: u32 = 8;
+fn mid() { }
+";
+        let mut shader_defs: XHashMap<String, String> = XHashMap::default();
+        shader_defs.insert("MAX_LIGHTS".to_string(), "8".to_string());
+        shader_defs.insert("QUALITY".to_string(), "2".to_string());
+
+        let result = process_wgsl(INPUT, &shader_defs).unwrap();
+        assert_eq!(result.get_wgsl_source().unwrap(), EXPECTED);
+    }
+
+    #[test]
+    fn process_define_undef() {
+        #[rustfmt::skip]
+        const INPUT: &str = r"
+#define SIZE 4
+#define HALF (SIZE / 2)
+#define FLAG
+var<private> a: array<f32, HALF>;
+#ifdef FLAG
+fn flag() { }
+#endif
+#undef FLAG
+#ifndef FLAG
+fn no_flag() { }
+#endif
+#if SIZE * 2 == 8 && !defined FLAG
+fn eight() { }
+#endif
+";
+        #[rustfmt::skip]
+        const EXPECTED: &str = r"
+var<private> a: array<f32, (4 / 2)>;
+fn flag() { }
+fn no_flag() { }
+fn eight() { }
+";
+        let result = process_wgsl(INPUT, &XHashSet::<String>::default()).unwrap();
+        assert_eq!(result.get_wgsl_source().unwrap(), EXPECTED);
+    }
+
+    #[test]
+    fn process_if_errors() {
+        let result = process_wgsl("\n#if FOO +\n#endif\n", &XHashSet::<String>::default());
+        assert!(matches!(result, Err(ProcessShaderError::InvalidExpression(2, _))));
+
+        let result = process_wgsl("\n#ifdef A\n#else\n#elif B\n#endif\n", &XHashSet::<String>::default());
+        assert_eq!(result, Err(ProcessShaderError::UnexpectedDirective(4, "elif")));
+    }
+
+    #[test]
+    fn process_source_map() {
+        #[rustfmt::skip]
+        const FOO: &str = r"
+fn foo() -> f32 {
+    return 1.0 +;
+}
+";
+        #[rustfmt::skip]
+        const INPUT: &str = r"
+#ifdef UNUSED
+fn unused() { }
+#endif
+#import FOO
+fn main() { }
+";
+        let mut shaders = XHashMap::default();
+        let shader = Shader::from_wgsl(INPUT);
+        let id = shader.id();
+        shaders.insert(shader.id(), (shader, Some("main.wgsl".to_string())));
+
+        let foo = Shader::from_wgsl(FOO);
+        let mut import_shaders = XHashMap::default();
+        import_shaders.insert(ShaderImport::Custom("FOO".to_string()), foo.id());
+        shaders.insert(foo.id(), (foo, Some("libs/foo.wgsl".to_string())));
+
+        let (processed, source_map) = ShaderProcessor::default()
+            .process_with_source_map(
+                &id,
+                &XHashSet::<String>::default(),
+                &mut ShaderCodeMgr {
+                    shaders,
+                    import_shaders,
+                },
+            )
+            .unwrap();
+        assert_eq!(source_map.len(), processed.get_wgsl_source().unwrap().lines().count());
+        assert_eq!(source_map.locate(1), Some(("main.wgsl", 1)));
+        assert_eq!(source_map.locate(4), Some(("libs/foo.wgsl", 3)));
+        assert_eq!(source_map.locate(6), Some(("main.wgsl", 6)));
+
+        match processed.reflect_with_source_map(&source_map) {
+            Err(ShaderReflectError::Mapped(locations, _)) => {
+                assert_eq!(locations[0].source, "libs/foo.wgsl");
+                assert_eq!(locations[0].line, 3);
+            }
+            _ => panic!("expect mapped error"),
+        }
+    }
 }
 
 // #[test]
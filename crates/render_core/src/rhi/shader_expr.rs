@@ -0,0 +1,362 @@
+//! 预处理表达式
+//! * #if、#elif 的条件求值，接近C预处理器：未定义的标识符为0，非0为真，支持 defined
+//! * 与C预处理器不同：只定义了名字（无值）的宏在表达式中视为1（C中为错误），以便 ShaderMeta 的开关宏直接用于 #if；宏值作为整体求值（相当于加了括号）；另支持 true、false
+//! * 宏值替换：将代码中的宏名替换为 #define 的值，无值的宏保持原样
+
+use std::borrow::Cow;
+
+/// 宏值递归展开的最大深度，防止循环定义
+const MAX_DEPTH: usize = 16;
+
+/// 按优先级由低到高，长操作符在前
+const OPS: [&str; 24] = [
+    "||", "&&", "==", "!=", "<=", ">=", "<<", ">>",
+    "+", "-", "*", "/", "%", "<", ">", "!", "~", "&", "|", "^", "(", ")", "?", ":",
+];
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Num(i64),
+    Ident(String),
+    Op(&'static str),
+}
+
+/// 求值
+/// * resolve - 查询宏：未定义返回None，定义但无值返回空字符串
+pub fn eval(expr: &str, resolve: &dyn Fn(&str) -> Option<String>) -> Result<i64, String> {
+    eval_depth(expr, resolve, 0)
+}
+
+fn eval_depth(expr: &str, resolve: &dyn Fn(&str) -> Option<String>, depth: usize) -> Result<i64, String> {
+    if depth > MAX_DEPTH {
+        return Err(format!("macro expansion too deep: '{}'", expr));
+    }
+    let tokens = tokenize(expr)?;
+    if tokens.is_empty() {
+        return Err("empty expression".to_string());
+    }
+    let mut parser = ExprParser { tokens, pos: 0, resolve, depth };
+    let value = parser.ternary()?;
+    match parser.tokens.get(parser.pos) {
+        Some(t) => Err(format!("unexpected token {:?} in '{}'", t, expr)),
+        None => Ok(value),
+    }
+}
+
+/// 将代码中的宏名替换为宏值，宏值中的宏会继续展开
+/// 无值的宏保持原样
+pub fn substitute<'a>(line: &'a str, resolve: &dyn Fn(&str) -> Option<String>) -> Cow<'a, str> {
+    let mut out = String::new();
+    if expand(line, resolve, &mut Vec::new(), &mut out) {
+        Cow::Owned(out)
+    } else {
+        Cow::Borrowed(line)
+    }
+}
+
+// 返回是否发生了替换
+fn expand(text: &str, resolve: &dyn Fn(&str) -> Option<String>, stack: &mut Vec<String>, out: &mut String) -> bool {
+    let bytes = text.as_bytes();
+    let mut replaced = false;
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        if text[i..].starts_with("//") {
+            out.push_str(&text[i..]);
+            break;
+        } else if c.is_ascii_digit() {
+            // 数字字面量（如 1e5、0x1F）整体跳过
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.' || bytes[i] == b'_') {
+                i += 1;
+            }
+            out.push_str(&text[start..i]);
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            let name = &text[start..i];
+            match resolve(name) {
+                Some(value) if !value.trim().is_empty() && stack.len() < MAX_DEPTH && !stack.iter().any(|r| r == name) => {
+                    stack.push(name.to_string());
+                    expand(value.trim(), resolve, stack, out);
+                    stack.pop();
+                    replaced = true;
+                }
+                _ => out.push_str(name),
+            }
+        } else {
+            let start = i;
+            i += c.len_utf8();
+            out.push_str(&text[start..i]);
+        }
+    }
+    replaced
+}
+
+fn tokenize(expr: &str) -> Result<Vec<Token>, String> {
+    let bytes = expr.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        let c = bytes[i] as char;
+        if c.is_ascii_whitespace() {
+            i += 1;
+        } else if expr[i..].starts_with("//") {
+            break;
+        } else if c.is_ascii_digit() {
+            let start = i;
+            while i < bytes.len() && bytes[i].is_ascii_alphanumeric() {
+                i += 1;
+            }
+            tokens.push(Token::Num(parse_int(&expr[start..i])?));
+        } else if c.is_ascii_alphabetic() || c == '_' {
+            let start = i;
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            tokens.push(Token::Ident(expr[start..i].to_string()));
+        } else {
+            match OPS.iter().find(|op| expr[i..].starts_with(**op)) {
+                Some(op) => {
+                    tokens.push(Token::Op(op));
+                    i += op.len();
+                }
+                None => return Err(format!("unexpected character '{}'", &expr[i..].chars().next().unwrap())),
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+fn parse_int(s: &str) -> Result<i64, String> {
+    let t = s.trim_end_matches(|c| matches!(c, 'u' | 'U' | 'l' | 'L'));
+    let r = match t.strip_prefix("0x").or_else(|| t.strip_prefix("0X")) {
+        Some(hex) => i64::from_str_radix(hex, 16),
+        None => t.parse::<i64>(),
+    };
+    r.map_err(|_| format!("invalid number '{}'", s))
+}
+
+fn precedence(op: &str) -> Option<u8> {
+    Some(match op {
+        "||" => 1,
+        "&&" => 2,
+        "|" => 3,
+        "^" => 4,
+        "&" => 5,
+        "==" | "!=" => 6,
+        "<" | ">" | "<=" | ">=" => 7,
+        "<<" | ">>" => 8,
+        "+" | "-" => 9,
+        "*" | "/" | "%" => 10,
+        _ => return None,
+    })
+}
+
+struct ExprParser<'a> {
+    tokens: Vec<Token>,
+    pos: usize,
+    resolve: &'a dyn Fn(&str) -> Option<String>,
+    depth: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn eat(&mut self, op: &str) -> bool {
+        if let Some(Token::Op(r)) = self.tokens.get(self.pos) {
+            if *r == op {
+                self.pos += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn expect(&mut self, op: &str) -> Result<(), String> {
+        if self.eat(op) {
+            Ok(())
+        } else {
+            Err(format!("expect '{}', found {:?}", op, self.tokens.get(self.pos)))
+        }
+    }
+
+    fn ternary(&mut self) -> Result<i64, String> {
+        let cond = self.binary(1)?;
+        if self.eat("?") {
+            let a = self.ternary()?;
+            self.expect(":")?;
+            let b = self.ternary()?;
+            Ok(if cond != 0 { a } else { b })
+        } else {
+            Ok(cond)
+        }
+    }
+
+    fn binary(&mut self, min_prec: u8) -> Result<i64, String> {
+        let mut lhs = self.unary()?;
+        loop {
+            let (op, prec) = match self.tokens.get(self.pos) {
+                Some(Token::Op(op)) => match precedence(op) {
+                    Some(prec) if prec >= min_prec => (*op, prec),
+                    _ => break,
+                },
+                _ => break,
+            };
+            self.pos += 1;
+            let rhs = self.binary(prec + 1)?;
+            lhs = match op {
+                "||" => ((lhs != 0) || (rhs != 0)) as i64,
+                "&&" => ((lhs != 0) && (rhs != 0)) as i64,
+                "|" => lhs | rhs,
+                "^" => lhs ^ rhs,
+                "&" => lhs & rhs,
+                "==" => (lhs == rhs) as i64,
+                "!=" => (lhs != rhs) as i64,
+                "<" => (lhs < rhs) as i64,
+                ">" => (lhs > rhs) as i64,
+                "<=" => (lhs <= rhs) as i64,
+                ">=" => (lhs >= rhs) as i64,
+                "<<" => lhs.checked_shl(rhs as u32).unwrap_or(0),
+                ">>" => lhs.checked_shr(rhs as u32).unwrap_or(0),
+                "+" => lhs.wrapping_add(rhs),
+                "-" => lhs.wrapping_sub(rhs),
+                "*" => lhs.wrapping_mul(rhs),
+                "/" | "%" if rhs == 0 => return Err("division by zero".to_string()),
+                "/" => lhs.wrapping_div(rhs),
+                "%" => lhs.wrapping_rem(rhs),
+                _ => unreachable!(),
+            };
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<i64, String> {
+        if self.eat("!") {
+            Ok((self.unary()? == 0) as i64)
+        } else if self.eat("~") {
+            Ok(!self.unary()?)
+        } else if self.eat("-") {
+            Ok(self.unary()?.wrapping_neg())
+        } else if self.eat("+") {
+            self.unary()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<i64, String> {
+        if self.eat("(") {
+            let v = self.ternary()?;
+            self.expect(")")?;
+            return Ok(v);
+        }
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        match token {
+            Some(Token::Num(v)) => Ok(v),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "defined" => {
+                    let paren = self.eat("(");
+                    let name = match self.tokens.get(self.pos) {
+                        Some(Token::Ident(r)) => r.clone(),
+                        r => return Err(format!("expect identifier after 'defined', found {:?}", r)),
+                    };
+                    self.pos += 1;
+                    if paren {
+                        self.expect(")")?;
+                    }
+                    Ok((self.resolve)(&name).is_some() as i64)
+                }
+                "true" => Ok(1),
+                "false" => Ok(0),
+                _ => match (self.resolve)(&name) {
+                    None => Ok(0),
+                    // 只定义了名字的宏视为1
+                    Some(value) if value.trim().is_empty() => Ok(1),
+                    Some(value) => eval_depth(&value, self.resolve, self.depth + 1),
+                },
+            },
+            r => Err(format!("unexpected token {:?}", r)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolve(name: &str) -> Option<String> {
+        match name {
+            "ONE" => Some("1".to_string()),
+            "TWO" => Some("ONE + ONE".to_string()),
+            "FLAG" => Some(String::new()),
+            "SELF" => Some("SELF + 1".to_string()),
+            "A" => Some("B".to_string()),
+            "B" => Some("A".to_string()),
+            _ => None,
+        }
+    }
+
+    fn eval_str(expr: &str) -> Result<i64, String> {
+        eval(expr, &resolve)
+    }
+
+    #[test]
+    fn expr_precedence() {
+        assert_eq!(eval_str("1 + 2 * 3"), Ok(7));
+        assert_eq!(eval_str("(1 + 2) * 3"), Ok(9));
+        assert_eq!(eval_str("1 << 2 + 1"), Ok(8));
+        assert_eq!(eval_str("1 | 2 & 0"), Ok(1));
+        assert_eq!(eval_str("1 || 0 && 0"), Ok(1));
+        assert_eq!(eval_str("2 > 1 == 1"), Ok(1));
+        assert_eq!(eval_str("0 ? 1 : 2 ? 3 : 4"), Ok(3));
+        assert_eq!(eval_str("TWO * 3"), Ok(6));
+        assert_eq!(eval_str("0x10 - 1u"), Ok(15));
+    }
+
+    #[test]
+    fn expr_defined() {
+        assert_eq!(eval_str("defined(ONE)"), Ok(1));
+        assert_eq!(eval_str("defined FLAG && !defined(NONE)"), Ok(1));
+        assert_eq!(eval_str("defined(NONE)"), Ok(0));
+        assert!(eval_str("defined(1)").is_err());
+        assert!(eval_str("defined(ONE").is_err());
+        // 未定义为0，无值的宏为1
+        assert_eq!(eval_str("NONE"), Ok(0));
+        assert_eq!(eval_str("FLAG"), Ok(1));
+    }
+
+    #[test]
+    fn expr_unary() {
+        assert_eq!(eval_str("!0"), Ok(1));
+        assert_eq!(eval_str("!ONE"), Ok(0));
+        assert_eq!(eval_str("~0"), Ok(-1));
+        assert_eq!(eval_str("-ONE + +2"), Ok(1));
+        assert_eq!(eval_str("- -3"), Ok(3));
+    }
+
+    #[test]
+    fn expr_error() {
+        assert!(eval_str("1 / 0").is_err());
+        assert!(eval_str("1 % (ONE - 1)").is_err());
+        assert!(eval_str("").is_err());
+        assert!(eval_str("1 2").is_err());
+        assert!(eval_str("1 $ 2").is_err());
+        // 循环定义在超过最大深度时截断
+        assert!(eval_str("SELF").unwrap_err().contains("too deep"));
+        assert!(eval_str("A").unwrap_err().contains("too deep"));
+    }
+
+    #[test]
+    fn substitute_macro() {
+        assert_eq!(substitute("float a = TWO;", &resolve), "float a = 1 + 1;");
+        // 无值、未定义的宏保持原样
+        assert!(matches!(substitute("FLAG NONE", &resolve), Cow::Borrowed("FLAG NONE")));
+        // 数字字面量与注释中的宏不替换
+        assert_eq!(substitute("1ONE ONE // ONE", &resolve), "1ONE 1 // ONE");
+        // 循环定义展开到已展开过的宏时停止
+        assert_eq!(substitute("SELF", &resolve), "SELF + 1");
+        assert_eq!(substitute("A", &resolve), "A");
+    }
+}
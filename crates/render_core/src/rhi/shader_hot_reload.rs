@@ -0,0 +1,431 @@
+//! 开发模式下的 Shader 热重载
+//!
+//! 轮询磁盘上的文件修改时间：
+//! * ShaderCodeMgr 中带路径的源码（.vert/.frag/.glsl/.wgsl）变化时，原地替换源码（ShaderId 不变），
+//!   并处理、反射所有受影响的 shader，失败则回滚到上一个可用版本
+//! * ShaderMeta 由外部提供的构建函数重新生成（如重新执行 render_compile 的解析，或读取其输出），
+//!   构建或编译失败时保留上一个可用版本
+//!
+//! 重载后需要重建依赖的 Shader 与 RenderPipeline：
+//! * 创建资产时通过 [`ShaderHotReload::depend_meta`] / [`ShaderHotReload::depend_pipeline`] 登记资产 Key 及构建用的宏，
+//!   重载时按登记的宏检查编译错误，成功后在 [`HotReloadReport::invalidated`] 中返回需要重建的资产 Key
+//! * pi_assets 的 AssetMgr 不支持移除资产，重建须使用新的 Key：
+//!   ShaderMeta 使用 [`ShaderHotReload::key_meta`]，源码使用 [`ShaderHotReload::key_version`] 参与资产 Key 的计算，
+//!   旧资产在资产管理器中自然超时释放
+
+use std::{
+    borrow::Cow,
+    path::{Path, PathBuf},
+    time::{Duration, Instant, SystemTime},
+};
+
+use pi_atom::Atom;
+use pi_hash::{XHashMap, XHashSet};
+use pi_share::Share;
+
+use crate::renderer::shader::KeyShaderMeta;
+
+use super::{
+    pipeline::RenderPipelineDescriptor,
+    shader::{ProcessedShader, ShaderCodeMgr, ShaderId, ShaderMeta, ShaderProcessor, Source},
+};
+
+/// 重新构建 ShaderMeta
+pub type ShaderMetaBuilder = Box<dyn Fn() -> Result<ShaderMeta, String> + Send + Sync>;
+
+struct WatchedMeta {
+    files: Vec<PathBuf>,
+    builder: ShaderMetaBuilder,
+    /// 最近一次可用的版本
+    meta: Share<ShaderMeta>,
+    version: usize,
+    /// 构建过的宏组合
+    defines: Vec<XHashSet<Atom>>,
+    /// 依赖该 ShaderMeta 的资产 Key
+    dependents: XHashSet<u64>,
+}
+
+/// 一次检查的结果
+#[derive(Debug, Default)]
+pub struct HotReloadReport {
+    /// 重载成功的 ShaderMeta（原始 Key）
+    pub metas: Vec<KeyShaderMeta>,
+    /// 重载成功的源码，包含直接或间接 #import 了变化文件的 shader
+    pub shaders: Vec<ShaderId>,
+    /// 编译错误，(文件或ShaderMeta名称, 错误描述)
+    pub errors: Vec<(String, String)>,
+    /// 需要用新 Key 重建的资产（登记时的 Key），已从登记中移除，重建后需重新登记
+    pub invalidated: Vec<u64>,
+}
+
+impl HotReloadReport {
+    pub fn is_empty(&self) -> bool {
+        self.metas.is_empty() && self.shaders.is_empty() && self.errors.is_empty()
+    }
+
+    fn invalidate(&mut self, dependents: XHashSet<u64>) {
+        for dependent in dependents {
+            if !self.invalidated.contains(&dependent) {
+                self.invalidated.push(dependent);
+            }
+        }
+    }
+}
+
+pub struct ShaderHotReload {
+    metas: XHashMap<KeyShaderMeta, WatchedMeta>,
+    /// shader -> 构建过的宏组合
+    shader_defines: XHashMap<ShaderId, Vec<XHashSet<String>>>,
+    /// shader -> 依赖它的资产 Key
+    shader_dependents: XHashMap<ShaderId, XHashSet<u64>>,
+    /// shader -> 重载次数
+    shader_versions: XHashMap<ShaderId, usize>,
+    /// 文件 -> 上次检查时的修改时间
+    modifieds: XHashMap<PathBuf, Option<SystemTime>>,
+    interval: Duration,
+    last_check: Option<Instant>,
+}
+
+impl Default for ShaderHotReload {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(500))
+    }
+}
+
+impl ShaderHotReload {
+    /// * interval - 两次检查磁盘的最小间隔
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            metas: XHashMap::default(),
+            shader_defines: XHashMap::default(),
+            shader_dependents: XHashMap::default(),
+            shader_versions: XHashMap::default(),
+            modifieds: XHashMap::default(),
+            interval,
+            last_check: None,
+        }
+    }
+
+    /// 监听 ShaderMeta
+    /// * files - 任意文件变化时调用 builder 重新构建
+    pub fn watch_meta(&mut self, key: KeyShaderMeta, meta: ShaderMeta, files: Vec<PathBuf>, builder: ShaderMetaBuilder) {
+        for file in files.iter() {
+            self.modifieds.insert(file.clone(), modified(file));
+        }
+        self.metas.insert(key, WatchedMeta {
+            files,
+            builder,
+            meta: Share::new(meta),
+            version: 0,
+            defines: Vec::new(),
+            dependents: XHashSet::default(),
+        });
+    }
+
+    /// 登记由 ShaderMeta 构建的资产（如 KeyShader 或 KeyRenderPipeline 的哈希值）及构建用的宏
+    /// * key - 原始 Key
+    pub fn depend_meta(&mut self, key: &KeyShaderMeta, defines: &XHashSet<Atom>, dependent: u64) {
+        if let Some(watched) = self.metas.get_mut(key) {
+            if !watched.defines.contains(defines) {
+                watched.defines.push(defines.clone());
+            }
+            watched.dependents.insert(dependent);
+        }
+    }
+
+    /// 登记由 ShaderCodeMgr 中的源码构建的管线（如 KeyRenderPipeline::to_u64）及各阶段的宏
+    pub fn depend_pipeline(&mut self, desc: &RenderPipelineDescriptor, dependent: u64) {
+        let stages = std::iter::once((desc.vertex.shader, &desc.vertex.shader_defs))
+            .chain(desc.fragment.as_ref().map(|r| (r.shader, &r.shader_defs)));
+        for (id, defs) in stages {
+            let defines: XHashSet<String> = defs.iter().cloned().collect();
+            let list = self.shader_defines.entry(id).or_default();
+            if !list.contains(&defines) {
+                list.push(defines);
+            }
+            self.shader_dependents.entry(id).or_default().insert(dependent);
+        }
+    }
+
+    /// 监听 ShaderCodeMgr 中所有带路径的源码
+    pub fn watch_code(&mut self, shader_mgr: &ShaderCodeMgr) {
+        for (_, path) in shader_mgr.shaders.values() {
+            if let Some(path) = path {
+                let path = PathBuf::from(path);
+                if !self.modifieds.contains_key(&path) {
+                    let time = modified(&path);
+                    self.modifieds.insert(path, time);
+                }
+            }
+        }
+    }
+
+    /// 最近一次可用的 ShaderMeta
+    pub fn meta(&self, key: &KeyShaderMeta) -> Option<Share<ShaderMeta>> {
+        self.metas.get(key).map(|r| r.meta.clone())
+    }
+
+    /// 带版本的 Key，创建 KeyShader 时应使用该值代替原始 Key
+    pub fn key_meta(&self, key: &KeyShaderMeta) -> KeyShaderMeta {
+        match self.metas.get(key) {
+            Some(r) if r.version > 0 => Atom::from(format!("{}#{}", key.as_str(), r.version)),
+            _ => key.clone(),
+        }
+    }
+
+    /// 源码的重载次数，由源码构建的资产应将其计入资产 Key
+    pub fn key_version(&self, id: &ShaderId) -> usize {
+        self.shader_versions.get(id).cloned().unwrap_or(0)
+    }
+
+    /// 检查文件变化，重载受影响的 shader
+    /// 距上次检查不足 interval 时直接返回空结果
+    pub fn update(&mut self, shader_mgr: &mut ShaderCodeMgr) -> HotReloadReport {
+        let mut report = HotReloadReport::default();
+        let now = Instant::now();
+        if let Some(last) = self.last_check {
+            if now.duration_since(last) < self.interval {
+                return report;
+            }
+        }
+        self.last_check = Some(now);
+
+        let mut changed = XHashSet::default();
+        for (path, time) in self.modifieds.iter_mut() {
+            let cur = modified(path);
+            if cur != *time {
+                *time = cur;
+                changed.insert(path.clone());
+            }
+        }
+        if changed.is_empty() {
+            return report;
+        }
+        log::info!("shader hot reload, changed files: {:?}", changed);
+
+        self.reload_code(&changed, shader_mgr, &mut report);
+        self.reload_meta(&changed, &mut report);
+        for (name, err) in report.errors.iter() {
+            log::error!("shader hot reload fail, keep last version, name={:?}, err={}", name, err);
+        }
+        report
+    }
+
+    fn reload_code(&mut self, changed: &XHashSet<PathBuf>, shader_mgr: &mut ShaderCodeMgr, report: &mut HotReloadReport) {
+        // 替换源码，记录旧源码以便回滚
+        let mut olds = Vec::new();
+        for (id, (shader, path)) in shader_mgr.shaders.iter_mut() {
+            let path = match path {
+                Some(r) if changed.contains(Path::new(r.as_str())) => r.clone(),
+                _ => continue,
+            };
+            let source = match std::fs::read_to_string(&path) {
+                Ok(code) => match shader.source() {
+                    Source::Wgsl(_) => Source::Wgsl(Cow::Owned(code)),
+                    Source::Glsl(_, stage) => Source::Glsl(Cow::Owned(code), *stage),
+                    #[cfg(feature="wgpu/spirv")]
+                    Source::SpirV(_) => continue,
+                },
+                Err(e) => {
+                    report.errors.push((path, e.to_string()));
+                    continue;
+                }
+            };
+            olds.push((*id, shader.replace_source(source)));
+        }
+        if olds.is_empty() {
+            return;
+        }
+
+        // 受影响的 shader：变化的 shader 及（间接）导入了它们的 shader
+        let mut affected: XHashSet<ShaderId> = olds.iter().map(|r| r.0).collect();
+        loop {
+            let len = affected.len();
+            for (id, (shader, _)) in shader_mgr.shaders.iter() {
+                if affected.contains(id) {
+                    continue;
+                }
+                let is_affected = shader.imports().any(|import| {
+                    shader_mgr.import_shaders.get(import).map_or(false, |r| affected.contains(r))
+                });
+                if is_affected {
+                    affected.insert(*id);
+                }
+            }
+            if affected.len() == len {
+                break;
+            }
+        }
+
+        // 只被导入的片段无法单独编译，由导入它的 shader 检查
+        let imported: XHashSet<ShaderId> = shader_mgr.import_shaders.values().cloned().collect();
+        let processor = ShaderProcessor::default();
+        let empty = [XHashSet::default()];
+        let mut errors = Vec::new();
+        for id in affected.iter() {
+            if imported.contains(id) {
+                continue;
+            }
+            // 按构建时使用过的宏检查，未登记时不开启任何宏
+            let defines = match self.shader_defines.get(id) {
+                Some(r) if !r.is_empty() => r.as_slice(),
+                _ => &empty[..],
+            };
+            for defines in defines.iter() {
+                // 错误位置映射到原始文件
+                let r = processor
+                    .process_with_source_map(id, defines, shader_mgr)
+                    .map_err(|e| e.to_string())
+                    .and_then(|(r, source_map)| r.reflect_with_source_map(&source_map).map(|_| ()).map_err(|e| e.to_string()));
+                if let Err(e) = r {
+                    let name = shader_mgr.shaders.get(id).and_then(|r| r.1.clone()).unwrap_or_else(|| format!("{:?}", id));
+                    errors.push((name, e));
+                    break;
+                }
+            }
+        }
+
+        if errors.is_empty() {
+            for id in affected.iter() {
+                *self.shader_versions.entry(*id).or_default() += 1;
+                if let Some(dependents) = self.shader_dependents.remove(id) {
+                    report.invalidate(dependents);
+                }
+            }
+            report.shaders.extend(affected.into_iter());
+        } else {
+            for (id, source) in olds.into_iter() {
+                if let Some((shader, _)) = shader_mgr.shaders.get_mut(&id) {
+                    shader.replace_source(source);
+                }
+            }
+            report.errors.extend(errors.into_iter());
+        }
+    }
+
+    fn reload_meta(&mut self, changed: &XHashSet<PathBuf>, report: &mut HotReloadReport) {
+        for (key, watched) in self.metas.iter_mut() {
+            if !watched.files.iter().any(|r| changed.contains(r)) {
+                continue;
+            }
+            let r = (watched.builder)().and_then(|meta| {
+                check_meta(&meta, &watched.defines)?;
+                Ok(meta)
+            });
+            match r {
+                Ok(meta) => {
+                    watched.meta = Share::new(meta);
+                    watched.version += 1;
+                    report.metas.push(key.clone());
+                    report.invalidate(std::mem::take(&mut watched.dependents));
+                }
+                Err(e) => report.errors.push((key.as_str().to_string(), e)),
+            }
+        }
+    }
+}
+
+/// 按构建过的宏组合（未登记时不开启任何宏）编译顶点、像素着色器，检查是否有错误
+fn check_meta(meta: &ShaderMeta, defines: &[XHashSet<Atom>]) -> Result<(), String> {
+    let empty = [XHashSet::default()];
+    let defines = if defines.is_empty() { &empty[..] } else { defines };
+    for defines in defines.iter() {
+        for (visibility, stage) in [
+            (wgpu::ShaderStages::VERTEX, naga::ShaderStage::Vertex),
+            (wgpu::ShaderStages::FRAGMENT, naga::ShaderStage::Fragment),
+        ] {
+            let (code, source_map) = meta.to_code_with_source_map(defines, visibility);
+            ProcessedShader::Glsl(Cow::Owned(code), stage)
+                .reflect_with_source_map(&source_map)
+                .map_err(|e| format!("{:?}: {}", stage, e))?;
+        }
+    }
+    Ok(())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|r| r.modified()).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs::File, io::Write};
+
+    use super::*;
+
+    const FRAG: &str = "#version 450
+layout(location = 0) out vec4 o_Target;
+void main() {
+#ifdef RED
+    o_Target = vec4(COLOR, 0.0, 0.0, 1.0);
+#else
+    o_Target = not_defined;
+#endif
+}
+";
+
+    fn write(path: &Path, code: &str, time: SystemTime) {
+        let mut file = File::create(path).unwrap();
+        file.write_all(code.as_bytes()).unwrap();
+        file.set_modified(time).unwrap();
+    }
+
+    fn code(shader_mgr: &ShaderCodeMgr, id: &ShaderId) -> String {
+        match shader_mgr.shaders[id].0.source() {
+            Source::Glsl(code, _) | Source::Wgsl(code) => code.to_string(),
+            #[allow(unreachable_patterns)]
+            _ => String::new(),
+        }
+    }
+
+    #[test]
+    fn reload_code_cycle() {
+        let dir = std::env::temp_dir().join(format!("pi_render_hot_reload_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("test.frag");
+        let time = SystemTime::now();
+        write(&path, &FRAG.replace("COLOR", "1.0"), time);
+
+        let mut shader_mgr = ShaderCodeMgr::default();
+        let id = ShaderId::new();
+        let code = std::fs::read_to_string(&path).unwrap();
+        shader_mgr.shaders.insert(id, (crate::rhi::shader::Shader::from_glsl(code, naga::ShaderStage::Fragment), Some(path.to_string_lossy().to_string())));
+
+        let mut hot_reload = ShaderHotReload::new(Duration::ZERO);
+        hot_reload.watch_code(&shader_mgr);
+        let desc = RenderPipelineDescriptor {
+            label: None,
+            layout: None,
+            vertex: crate::rhi::pipeline::VertexState { shader: id, shader_defs: vec!["RED".to_string()], entry_point: "main".into(), buffers: vec![] },
+            primitive: Default::default(),
+            depth_stencil: None,
+            multisample: Default::default(),
+            fragment: None,
+        };
+        hot_reload.depend_pipeline(&desc, 1);
+        assert!(hot_reload.update(&mut shader_mgr).is_empty());
+        assert_eq!(hot_reload.key_version(&id), 0);
+
+        // 修改时间变化，按登记的宏（RED）编译通过，替换源码并递增版本
+        write(&path, &FRAG.replace("COLOR", "0.5"), time + Duration::from_secs(10));
+        let report = hot_reload.update(&mut shader_mgr);
+        assert!(report.errors.is_empty(), "{:?}", report.errors);
+        assert_eq!(report.shaders, vec![id]);
+        assert_eq!(report.invalidated, vec![1]);
+        assert_eq!(hot_reload.key_version(&id), 1);
+        assert!(code(&shader_mgr, &id).contains("0.5"));
+
+        // 编译失败时回滚源码，版本不变
+        hot_reload.depend_pipeline(&desc, 2);
+        write(&path, "#version 450\nvoid main() { error }\n", time + Duration::from_secs(20));
+        let report = hot_reload.update(&mut shader_mgr);
+        assert_eq!(report.errors.len(), 1);
+        assert!(report.shaders.is_empty() && report.invalidated.is_empty());
+        assert_eq!(hot_reload.key_version(&id), 1);
+        assert!(code(&shader_mgr, &id).contains("0.5"));
+
+        // 文件未再变化，不重复检查
+        assert!(hot_reload.update(&mut shader_mgr).is_empty());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
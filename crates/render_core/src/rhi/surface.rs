@@ -0,0 +1,352 @@
+//! 表面管理
+//! * 持有 SurfaceConfiguration，获取帧失败时 (Outdated / Lost) 重新配置后重试，Timeout 时跳过该帧
+//!     - 重新配置时 防抖中的新尺寸立即生效
+//! * present_mode 取 RenderOptions::present_mode，表面不支持时按 fallback 顺序降级，Fifo 总是支持
+//! * 窗口尺寸改变时，尺寸需连续 resize_debounce_frames 帧不变才重新配置，避免拖动窗口时每帧重建交换链
+//! * 统计获取帧的耗时
+//! * 通过 TSurface 访问表面，wgpu::Surface 之外 可接入其他窗口系统的表面 或 测试用的假表面
+
+use pi_share::Share;
+
+use super::{device::RenderDevice, options::RenderOptions, texture::PiRenderDefault};
+
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ESurfaceError {
+    #[error("surface size is zero")]
+    ZeroSize,
+    #[error("acquire surface texture timeout")]
+    Timeout,
+    #[error("surface lost after reconfigure")]
+    Lost,
+    #[error("out of memory when acquire surface texture")]
+    OutOfMemory,
+}
+
+/// 可呈现的表面
+pub trait TSurface: 'static {
+    /// 获取到的一帧
+    type Frame: 'static;
+
+    fn configure(&self, device: &RenderDevice, config: &wgpu::SurfaceConfiguration);
+    fn acquire(&self) -> Result<Self::Frame, wgpu::SurfaceError>;
+    fn texture(frame: &Self::Frame) -> &wgpu::Texture;
+    /// 表面属性已改变，仍可呈现，但应重新配置
+    fn suboptimal(frame: &Self::Frame) -> bool;
+    fn present(frame: Self::Frame);
+}
+
+impl TSurface for wgpu::Surface<'static> {
+    type Frame = wgpu::SurfaceTexture;
+
+    fn configure(&self, device: &RenderDevice, config: &wgpu::SurfaceConfiguration) {
+        device.configure_surface(self, config);
+    }
+    fn acquire(&self) -> Result<Self::Frame, wgpu::SurfaceError> {
+        self.get_current_texture()
+    }
+    fn texture(frame: &Self::Frame) -> &wgpu::Texture {
+        &frame.texture
+    }
+    fn suboptimal(frame: &Self::Frame) -> bool {
+        frame.suboptimal
+    }
+    fn present(frame: Self::Frame) {
+        frame.present()
+    }
+}
+
+/// 获取一帧，Outdated / Lost 时调用 reconfigure 后重试一次
+pub fn acquire_frame<S: TSurface>(surface: &S, reconfigure: impl FnOnce(wgpu::SurfaceError)) -> Result<S::Frame, ESurfaceError> {
+    let err = match surface.acquire() {
+        Ok(r) => return Ok(r),
+        Err(err @ wgpu::SurfaceError::Outdated) | Err(err @ wgpu::SurfaceError::Lost) => err,
+        Err(wgpu::SurfaceError::Timeout) => return Err(ESurfaceError::Timeout),
+        Err(wgpu::SurfaceError::OutOfMemory) => return Err(ESurfaceError::OutOfMemory),
+    };
+    reconfigure(err);
+    match surface.acquire() {
+        Ok(r) => Ok(r),
+        Err(wgpu::SurfaceError::Timeout) => Err(ESurfaceError::Timeout),
+        Err(wgpu::SurfaceError::OutOfMemory) => Err(ESurfaceError::OutOfMemory),
+        Err(_) => Err(ESurfaceError::Lost),
+    }
+}
+
+/// present_mode 不支持时的降级顺序
+pub fn present_mode_fallbacks(mode: wgpu::PresentMode) -> &'static [wgpu::PresentMode] {
+    use wgpu::PresentMode::*;
+    match mode {
+        Mailbox => &[Mailbox, Immediate, Fifo],
+        Immediate => &[Immediate, Mailbox, Fifo],
+        FifoRelaxed => &[FifoRelaxed, Fifo],
+        AutoVsync => &[AutoVsync],
+        AutoNoVsync => &[AutoNoVsync],
+        Fifo => &[Fifo],
+    }
+}
+
+/// 在表面支持的模式中选择 present_mode
+pub fn select_present_mode(requested: wgpu::PresentMode, supported: &[wgpu::PresentMode]) -> wgpu::PresentMode {
+    match requested {
+        // Auto 由 wgpu 自行选择
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => requested,
+        _ => present_mode_fallbacks(requested).iter().find(|r| supported.contains(r)).copied().unwrap_or(wgpu::PresentMode::Fifo),
+    }
+}
+
+/// 获取帧的统计
+#[derive(Debug, Clone, Default)]
+pub struct SurfaceStats {
+    /// 重新配置的次数（含尺寸改变）
+    pub configure_count: u32,
+    pub outdated_count: u32,
+    pub lost_count: u32,
+    pub timeout_count: u32,
+    /// 最近一次获取帧的耗时
+    #[cfg(not(target_arch = "wasm32"))]
+    pub acquire_latency: Duration,
+    /// 获取帧耗时的滑动平均
+    #[cfg(not(target_arch = "wasm32"))]
+    pub average_acquire_latency: Duration,
+    #[cfg(not(target_arch = "wasm32"))]
+    pub max_acquire_latency: Duration,
+}
+
+/// 尺寸改变的防抖
+#[derive(Debug, Clone, Default)]
+struct PendingResize {
+    width: u32,
+    height: u32,
+    // 尺寸保持不变的帧数
+    stable_frames: u32,
+}
+
+pub struct SurfaceManager<S: TSurface = wgpu::Surface<'static>> {
+    surface: Share<S>,
+    config: wgpu::SurfaceConfiguration,
+    supported_present_modes: Vec<wgpu::PresentMode>,
+    pending: Option<PendingResize>,
+    /// 尺寸需连续不变多少帧才重新配置，0 为立即配置
+    pub resize_debounce_frames: u32,
+    texture: Option<Share<S::Frame>>,
+    view: Option<Share<wgpu::TextureView>>,
+    stats: SurfaceStats,
+}
+
+impl SurfaceManager<wgpu::Surface<'static>> {
+    /// 按表面能力与 RenderOptions 创建配置，并立即配置表面
+    pub fn new(device: &RenderDevice, adapter: &wgpu::Adapter, surface: wgpu::Surface<'static>, width: u32, height: u32, options: &RenderOptions) -> Self {
+        let capabilities = surface.get_capabilities(adapter);
+        let default_format = wgpu::TextureFormat::pi_render_default();
+        let format = if capabilities.formats.is_empty() || capabilities.formats.contains(&default_format) {
+            default_format
+        } else {
+            capabilities.formats[0]
+        };
+        let present_mode = select_present_mode(options.present_mode, &capabilities.present_modes);
+        if present_mode != options.present_mode {
+            log::warn!("present mode {:?} is not supported, fallback to {:?}", options.present_mode, present_mode);
+        }
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: capabilities.alpha_modes.first().copied().unwrap_or(wgpu::CompositeAlphaMode::Auto),
+            view_formats: vec![],
+        };
+        Self::with_config(device, surface, config, capabilities.present_modes)
+    }
+}
+
+impl<S: TSurface> SurfaceManager<S> {
+    /// 使用给定的配置，并立即配置表面；supported_present_modes 为空时 只支持 Fifo
+    pub fn with_config(device: &RenderDevice, surface: S, config: wgpu::SurfaceConfiguration, supported_present_modes: Vec<wgpu::PresentMode>) -> Self {
+        let mut r = Self {
+            surface: Share::new(surface),
+            config,
+            supported_present_modes,
+            pending: None,
+            resize_debounce_frames: 6,
+            texture: None,
+            view: None,
+            stats: SurfaceStats::default(),
+        };
+        r.configure(device);
+        r
+    }
+
+    pub fn surface(&self) -> &Share<S> {
+        &self.surface
+    }
+    pub fn config(&self) -> &wgpu::SurfaceConfiguration {
+        &self.config
+    }
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.config.format
+    }
+    pub fn width(&self) -> u32 {
+        self.config.width
+    }
+    pub fn height(&self) -> u32 {
+        self.config.height
+    }
+    pub fn stats(&self) -> &SurfaceStats {
+        &self.stats
+    }
+    /// 当前帧的纹理，next_frame 成功后 present 之前存在
+    pub fn texture(&self) -> Option<&Share<S::Frame>> {
+        self.texture.as_ref()
+    }
+    pub fn view(&self) -> Option<&Share<wgpu::TextureView>> {
+        self.view.as_ref()
+    }
+
+    /// 设置 present_mode，不支持时降级，返回实际使用的模式
+    pub fn set_present_mode(&mut self, device: &RenderDevice, mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        let mode = select_present_mode(mode, &self.supported_present_modes);
+        if mode != self.config.present_mode {
+            self.config.present_mode = mode;
+            self.configure(device);
+        }
+        mode
+    }
+
+    /// 窗口尺寸改变，按防抖规则在之后的 next_frame 中重新配置
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width == self.config.width && height == self.config.height {
+            self.pending = None;
+            return;
+        }
+        match &mut self.pending {
+            Some(r) if r.width == width && r.height == height => (),
+            _ => self.pending = Some(PendingResize { width, height, stable_frames: 0 }),
+        }
+    }
+
+    /// 立即按新尺寸重新配置
+    pub fn resize_immediately(&mut self, device: &RenderDevice, width: u32, height: u32) {
+        self.pending = None;
+        if width == 0 || height == 0 {
+            return;
+        }
+        self.config.width = width;
+        self.config.height = height;
+        self.configure(device);
+    }
+
+    /// 获取下一帧；已获取且未 present 时 直接返回
+    /// * 返回 ZeroSize（窗口最小化）、Timeout 时应跳过该帧的渲染
+    pub fn next_frame(&mut self, device: &RenderDevice) -> Result<(), ESurfaceError> {
+        if self.texture.is_some() {
+            return Ok(());
+        }
+        self.apply_pending(device)?;
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let time = Instant::now();
+        let surface = self.surface.clone();
+        let texture = acquire_frame(&*surface, |err| {
+            if err == wgpu::SurfaceError::Outdated {
+                self.stats.outdated_count += 1;
+            } else {
+                self.stats.lost_count += 1;
+            }
+            // 表面已失效，防抖中的尺寸不必再等待（apply_pending 已排除尺寸为0的情况）
+            if let Some(pending) = self.pending.take() {
+                self.config.width = pending.width;
+                self.config.height = pending.height;
+            }
+            self.configure(device);
+        });
+        let texture = match texture {
+            Ok(r) => r,
+            Err(ESurfaceError::Timeout) => return Err(self.timeout()),
+            Err(err) => return Err(err),
+        };
+        #[cfg(not(target_arch = "wasm32"))]
+        self.record_latency(time.elapsed());
+
+        if S::suboptimal(&texture) {
+            // 下一帧重新配置
+            self.pending = Some(PendingResize { width: self.config.width, height: self.config.height, stable_frames: self.resize_debounce_frames });
+        }
+        let view = S::texture(&texture).create_view(&Default::default());
+        self.texture = Some(Share::new(texture));
+        self.view = Some(Share::new(view));
+        Ok(())
+    }
+
+    /// 提交当前帧
+    pub fn present(&mut self) {
+        self.view = None;
+        if let Some(texture) = self.texture.take() {
+            match Share::try_unwrap(texture) {
+                Ok(r) => S::present(r),
+                Err(_) => log::warn!("surface texture is still referenced, skip present"),
+            }
+        }
+    }
+
+    fn apply_pending(&mut self, device: &RenderDevice) -> Result<(), ESurfaceError> {
+        if let Some(pending) = &mut self.pending {
+            // 最小化时 保持原配置，跳过渲染
+            if pending.width == 0 || pending.height == 0 {
+                return Err(ESurfaceError::ZeroSize);
+            }
+            if pending.stable_frames >= self.resize_debounce_frames {
+                self.config.width = pending.width;
+                self.config.height = pending.height;
+                self.pending = None;
+                self.configure(device);
+            } else {
+                pending.stable_frames += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn configure(&mut self, device: &RenderDevice) {
+        // 旧的帧必须先释放
+        self.view = None;
+        self.texture = None;
+        self.surface.configure(device, &self.config);
+        self.stats.configure_count += 1;
+    }
+
+    fn timeout(&mut self) -> ESurfaceError {
+        self.stats.timeout_count += 1;
+        ESurfaceError::Timeout
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn record_latency(&mut self, latency: Duration) {
+        let stats = &mut self.stats;
+        stats.acquire_latency = latency;
+        stats.max_acquire_latency = stats.max_acquire_latency.max(latency);
+        stats.average_acquire_latency = if stats.average_acquire_latency.is_zero() {
+            latency
+        } else {
+            stats.average_acquire_latency.mul_f32(0.9) + latency.mul_f32(0.1)
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn present_mode_fallback() {
+        let supported = [wgpu::PresentMode::Fifo, wgpu::PresentMode::Immediate];
+        assert_eq!(select_present_mode(wgpu::PresentMode::Mailbox, &supported), wgpu::PresentMode::Immediate);
+        assert_eq!(select_present_mode(wgpu::PresentMode::FifoRelaxed, &supported), wgpu::PresentMode::Fifo);
+        assert_eq!(select_present_mode(wgpu::PresentMode::Immediate, &supported), wgpu::PresentMode::Immediate);
+        assert_eq!(select_present_mode(wgpu::PresentMode::AutoVsync, &supported), wgpu::PresentMode::AutoVsync);
+    }
+}
@@ -0,0 +1,271 @@
+//! 多表面
+//! * 多个窗口各自一个表面，各有自己的配置与格式，共用同一个 RenderDevice
+//! * 依赖图中渲染到窗口的最终节点 通过 bind_node 绑定到表面：表面记录在节点的 Bind 上，节点设为终点（set_finish）
+//!     - 运行时按 NodeId 从依赖图取得该窗口当前帧的视图，只有终点节点才有视图
+//! * 每个窗口独立获取帧、独立呈现，一个窗口最小化或超时 不影响其他窗口
+//! * TODO(pi_hal): 需求要求表面经由 pi_hal 的窗口抽象创建，但 pi_hal（外部 crate）目前没有窗口、表面创建接口，
+//!   需先在 pi_hal 中增加；在此之前 窗口由调用方（winit 等）创建，以 wgpu::SurfaceTarget 传入 create_surface，
+//!   pi_hal 提供接口后 create_surface 改为经由该接口，SurfaceSet 的其余部分不变
+
+use pi_null::Null;
+use pi_share::{Share, ThreadSync};
+use pi_slotmap::{new_key_type, SlotMap};
+
+use crate::depend_graph::{graph::DependGraph, GraphError, NodeId};
+
+use super::{device::RenderDevice, options::RenderOptions, surface::{ESurfaceError, SurfaceManager, TSurface}};
+
+new_key_type! {
+    /// 表面 ID
+    pub struct SurfaceId;
+}
+
+/// 依赖图节点的 Bind，可记录节点渲染到的表面
+pub trait TSurfaceBind: ThreadSync + Null + Clone + From<SurfaceId> + 'static {
+    fn surface_id(&self) -> Option<SurfaceId>;
+}
+
+impl TSurfaceBind for SurfaceId {
+    fn surface_id(&self) -> Option<SurfaceId> {
+        if self.is_null() { None } else { Some(*self) }
+    }
+}
+
+pub struct SurfaceSet<S: TSurface = wgpu::Surface<'static>> {
+    device: RenderDevice,
+    surfaces: SlotMap<SurfaceId, SurfaceManager<S>>,
+}
+
+impl SurfaceSet<wgpu::Surface<'static>> {
+    /// 为窗口创建表面并加入
+    /// * 暂时直接使用 wgpu::SurfaceTarget，见模块文档的 TODO(pi_hal)
+    pub fn create_surface(
+        &mut self, instance: &wgpu::Instance, adapter: &wgpu::Adapter, window: impl Into<wgpu::SurfaceTarget<'static>>,
+        width: u32, height: u32, options: &RenderOptions,
+    ) -> Result<SurfaceId, wgpu::CreateSurfaceError> {
+        let surface = instance.create_surface(window)?;
+        let manager = SurfaceManager::new(&self.device, adapter, surface, width, height, options);
+        Ok(self.insert(manager))
+    }
+}
+
+impl<S: TSurface> SurfaceSet<S> {
+    pub fn new(device: RenderDevice) -> Self {
+        Self {
+            device,
+            surfaces: SlotMap::default(),
+        }
+    }
+
+    pub fn device(&self) -> &RenderDevice {
+        &self.device
+    }
+
+    pub fn insert(&mut self, surface: SurfaceManager<S>) -> SurfaceId {
+        self.surfaces.insert(surface)
+    }
+
+    /// 移除表面（窗口关闭），绑定到该表面的节点不再有视图，可用 unbind_node 取消其终点
+    pub fn remove(&mut self, id: SurfaceId) -> Option<SurfaceManager<S>> {
+        self.surfaces.remove(id)
+    }
+
+    pub fn get(&self, id: SurfaceId) -> Option<&SurfaceManager<S>> {
+        self.surfaces.get(id)
+    }
+
+    pub fn get_mut(&mut self, id: SurfaceId) -> Option<&mut SurfaceManager<S>> {
+        self.surfaces.get_mut(id)
+    }
+
+    pub fn len(&self) -> usize {
+        self.surfaces.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.surfaces.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (SurfaceId, &SurfaceManager<S>)> {
+        self.surfaces.iter()
+    }
+
+    /// 将依赖图的节点绑定到表面，并设为终点
+    pub fn bind_node<C: ThreadSync + 'static, B: TSurfaceBind>(&self, graph: &mut DependGraph<C, B>, node: NodeId, id: SurfaceId) -> Result<(), GraphError> {
+        graph.set_finish(node, true)?;
+        graph.set_bind(node, B::from(id));
+        Ok(())
+    }
+
+    /// 解除节点与表面的绑定，并取消终点
+    pub fn unbind_node<C: ThreadSync + 'static, B: TSurfaceBind>(&self, graph: &mut DependGraph<C, B>, node: NodeId) -> Result<(), GraphError> {
+        graph.set_finish(node, false)?;
+        graph.set_bind(node, B::null());
+        Ok(())
+    }
+
+    /// 终点节点绑定的表面，表面已移除时 为 None
+    pub fn node_surface<C: ThreadSync + 'static, B: TSurfaceBind>(&self, graph: &DependGraph<C, B>, node: NodeId) -> Option<SurfaceId> {
+        if !graph.is_finish(node) {
+            return None;
+        }
+        graph.get_bind(node).surface_id().filter(|id| self.surfaces.contains_key(*id))
+    }
+
+    /// 节点绑定的表面 当前帧的视图与格式，该表面本帧未获取到帧时 为 None（节点应跳过渲染）
+    pub fn node_view<C: ThreadSync + 'static, B: TSurfaceBind>(&self, graph: &DependGraph<C, B>, node: NodeId) -> Option<(&Share<wgpu::TextureView>, wgpu::TextureFormat)> {
+        let surface = self.surfaces.get(self.node_surface(graph, node)?)?;
+        surface.view().map(|r| (r, surface.format()))
+    }
+
+    pub fn resize(&mut self, id: SurfaceId, width: u32, height: u32) {
+        if let Some(r) = self.surfaces.get_mut(id) {
+            r.resize(width, height);
+        }
+    }
+
+    /// 获取一个表面的下一帧
+    pub fn next_frame(&mut self, id: SurfaceId) -> Option<Result<(), ESurfaceError>> {
+        let device = &self.device;
+        self.surfaces.get_mut(id).map(|r| r.next_frame(device))
+    }
+
+    /// 获取所有表面的下一帧，返回获取失败的表面
+    pub fn next_frame_all(&mut self) -> Vec<(SurfaceId, ESurfaceError)> {
+        let device = &self.device;
+        self.surfaces.iter_mut().filter_map(|(id, r)| r.next_frame(device).err().map(|e| (id, e))).collect()
+    }
+
+    /// 呈现一个表面
+    pub fn present(&mut self, id: SurfaceId) {
+        if let Some(r) = self.surfaces.get_mut(id) {
+            r.present();
+        }
+    }
+
+    /// 呈现所有已获取到帧的表面
+    pub fn present_all(&mut self) {
+        self.surfaces.iter_mut().for_each(|(_, r)| r.present());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use pi_share::ShareMutex;
+
+    use crate::{depend_graph::graph::InternalNodeEmptyImpl, rhi::test_util::run};
+
+    use super::*;
+
+    struct FakeFrame {
+        texture: Share<wgpu::Texture>,
+        presented: Share<AtomicUsize>,
+    }
+
+    /// 以离屏纹理模拟的表面，可预设获取帧时的错误
+    #[derive(Default)]
+    struct FakeSurface {
+        texture: ShareMutex<Option<Share<wgpu::Texture>>>,
+        errors: ShareMutex<Vec<wgpu::SurfaceError>>,
+        presented: Share<AtomicUsize>,
+    }
+
+    impl TSurface for FakeSurface {
+        type Frame = FakeFrame;
+
+        fn configure(&self, device: &RenderDevice, config: &wgpu::SurfaceConfiguration) {
+            let texture = (**device).create_texture(&wgpu::TextureDescriptor {
+                label: Some("fake surface"),
+                size: wgpu::Extent3d { width: config.width, height: config.height, depth_or_array_layers: 1 },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: config.format,
+                usage: config.usage,
+                view_formats: &[],
+            });
+            *self.texture.lock() = Some(Share::new(texture));
+        }
+        fn acquire(&self) -> Result<Self::Frame, wgpu::SurfaceError> {
+            if let Some(err) = self.errors.lock().pop() {
+                return Err(err);
+            }
+            Ok(FakeFrame { texture: self.texture.lock().clone().unwrap(), presented: self.presented.clone() })
+        }
+        fn texture(frame: &Self::Frame) -> &wgpu::Texture {
+            &frame.texture
+        }
+        fn suboptimal(_frame: &Self::Frame) -> bool {
+            false
+        }
+        fn present(frame: Self::Frame) {
+            frame.presented.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn config(format: wgpu::TextureFormat, width: u32, height: u32) -> wgpu::SurfaceConfiguration {
+        wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            alpha_mode: wgpu::CompositeAlphaMode::Auto,
+            view_formats: vec![],
+        }
+    }
+
+    #[test]
+    fn multi_surface_present() {
+        run(|device, _queue| {
+            let mut set: SurfaceSet<FakeSurface> = SurfaceSet::new(device.clone());
+            let main = FakeSurface::default();
+            main.errors.lock().push(wgpu::SurfaceError::Outdated);
+            let main_presented = main.presented.clone();
+            let main = set.insert(SurfaceManager::with_config(&device, main, config(wgpu::TextureFormat::Bgra8Unorm, 64, 32), vec![]));
+            let tool = FakeSurface::default();
+            tool.errors.lock().push(wgpu::SurfaceError::Timeout);
+            let tool_presented = tool.presented.clone();
+            let tool = set.insert(SurfaceManager::with_config(&device, tool, config(wgpu::TextureFormat::Rgba8Unorm, 16, 16), vec![]));
+
+            let mut graph: DependGraph<(), SurfaceId> = DependGraph::default();
+            let main_node = graph.add_node("main", InternalNodeEmptyImpl, NodeId::null(), true).unwrap();
+            let tool_node = graph.add_node("tool", InternalNodeEmptyImpl, NodeId::null(), true).unwrap();
+            set.bind_node(&mut graph, main_node, main).unwrap();
+            set.bind_node(&mut graph, tool_node, tool).unwrap();
+            assert!(graph.is_finish(main_node) && graph.is_finish(tool_node));
+
+            // 主窗口 Outdated 后重新配置成功，工具窗口超时 跳过本帧
+            assert_eq!(set.next_frame_all(), vec![(tool, ESurfaceError::Timeout)]);
+            assert_eq!(set.get(main).unwrap().stats().outdated_count, 1);
+            assert_eq!(set.node_view(&graph, main_node).map(|r| r.1), Some(wgpu::TextureFormat::Bgra8Unorm));
+            assert!(set.node_view(&graph, tool_node).is_none());
+            set.present_all();
+            assert_eq!((main_presented.load(Ordering::Relaxed), tool_presented.load(Ordering::Relaxed)), (1, 0));
+
+            assert!(set.next_frame_all().is_empty());
+            assert_eq!(set.node_view(&graph, tool_node).map(|r| r.1), Some(wgpu::TextureFormat::Rgba8Unorm));
+            set.present(tool);
+            assert_eq!((main_presented.load(Ordering::Relaxed), tool_presented.load(Ordering::Relaxed)), (1, 1));
+
+            // 防抖中的尺寸在 Outdated 重新配置时立即生效
+            set.present(main);
+            set.resize(main, 128, 64);
+            set.get(main).unwrap().surface().errors.lock().push(wgpu::SurfaceError::Outdated);
+            assert_eq!(set.next_frame(main), Some(Ok(())));
+            let surface = set.get(main).unwrap();
+            assert_eq!((surface.width(), surface.height()), (128, 64));
+            assert_eq!(surface.stats().outdated_count, 2);
+
+            set.remove(tool);
+            assert_eq!(set.node_surface(&graph, tool_node), None);
+            assert_eq!(set.node_surface(&graph, main_node), Some(main));
+            set.unbind_node(&mut graph, main_node).unwrap();
+            assert!(!graph.is_finish(main_node));
+            assert_eq!(set.node_surface(&graph, main_node), None);
+        });
+    }
+}
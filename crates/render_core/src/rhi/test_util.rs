@@ -0,0 +1,60 @@
+//! 测试用的 GPU 环境
+//! * 在多媒体运行时中创建设备 并执行测试
+//! * 测试中的 panic 会传回测试线程；找不到适配器、超时 都会使测试失败，而不是一直等待
+
+use std::{panic::AssertUnwindSafe, sync::mpsc, time::Duration};
+
+use pi_async_rt::rt::AsyncRuntime;
+
+use super::{device::{initialize_renderer, RenderDevice}, options::RenderOptions, RenderQueue};
+
+/// 等待测试结束的最长时间
+const TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 创建设备后执行 f
+pub(crate) fn run(f: impl FnOnce(RenderDevice, RenderQueue) + Send + 'static) {
+    let (sender, receiver) = mpsc::channel();
+
+    let options = RenderOptions::default();
+    let instance = wgpu::Instance::new(wgpu::InstanceDescriptor { backends: options.backends, ..Default::default() });
+
+    pi_hal::runtime::MULTI_MEDIA_RUNTIME.spawn(async move {
+        let request_adapter_options = wgpu::RequestAdapterOptions {
+            power_preference: options.power_preference,
+            compatible_surface: None,
+            ..Default::default()
+        };
+        let mut alloter = pi_assets::allocator::Allocator::new(32 * 1024 * 1024);
+        // 找不到适配器时 在此 panic，sender 被释放，测试线程收到 Disconnected
+        let (device, queue, _adapter_info) = initialize_renderer(&instance, &options, &request_adapter_options, &mut alloter).await;
+        let _ = sender.send(std::panic::catch_unwind(AssertUnwindSafe(|| f(device, queue))));
+    }).unwrap();
+
+    match receiver.recv_timeout(TIMEOUT) {
+        Ok(Ok(())) => {}
+        Ok(Err(payload)) => std::panic::resume_unwind(payload),
+        Err(mpsc::RecvTimeoutError::Timeout) => panic!("gpu test timeout after {:?}", TIMEOUT),
+        Err(mpsc::RecvTimeoutError::Disconnected) => panic!("gpu test task exited without result, no adapter?"),
+    }
+}
+
+/// 读取 level 级 (x, y) 处的像素，格式为每像素 4 字节
+pub(crate) fn read_pixel(device: &RenderDevice, queue: &RenderQueue, texture: &wgpu::Texture, level: u32, x: u32, y: u32) -> [u8; 4] {
+    let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 256,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_buffer(
+        wgpu::ImageCopyTexture { texture, mip_level: level, origin: wgpu::Origin3d { x, y, z: 0 }, aspect: wgpu::TextureAspect::All },
+        wgpu::ImageCopyBuffer { buffer: &buffer, layout: wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(256), rows_per_image: None } },
+        wgpu::Extent3d { width: 1, height: 1, depth_or_array_layers: 1 },
+    );
+    queue.submit(Some(encoder.finish()));
+    buffer.slice(..).map_async(wgpu::MapMode::Read, |_| {});
+    (**device).poll(wgpu::Maintain::Wait);
+    let data = buffer.slice(..).get_mapped_range();
+    [data[0], data[1], data[2], data[3]]
+}
@@ -0,0 +1,121 @@
+//! 图片 Alpha 分析
+//! * 不带 Alpha 通道的格式直接视为不透明，不扫描像素
+//! * 带 Alpha 通道时逐像素扫描，遇到半透明即停止
+//! * 可选 预乘 Alpha
+
+use pi_hal::image::DynamicImage;
+
+/// 图片的透明类型
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, Default)]
+pub enum EAlphaMode {
+    #[default]
+    Opaque,
+    /// Alpha 只有 0 与 1，可用 alpha test 在不透明队列绘制
+    Mask,
+    /// 存在半透明像素
+    Translucent,
+}
+impl EAlphaMode {
+    pub fn is_opacity(&self) -> bool {
+        *self == EAlphaMode::Opaque
+    }
+    /// 只有 是否不透明 时的近似
+    pub fn from_opacity(is_opacity: bool) -> Self {
+        if is_opacity { EAlphaMode::Opaque } else { EAlphaMode::Translucent }
+    }
+}
+
+fn analyze<T: Copy + PartialEq>(alphas: impl Iterator<Item = T>, zero: T, one: T) -> EAlphaMode {
+    let mut result = EAlphaMode::Opaque;
+    for alpha in alphas {
+        if alpha == one {
+            continue;
+        } else if alpha == zero {
+            result = EAlphaMode::Mask;
+        } else {
+            return EAlphaMode::Translucent;
+        }
+    }
+    result
+}
+
+/// 8 位数据，每像素 channels 个通道，Alpha 为最后一个
+pub fn alpha_mode_u8(data: &[u8], channels: usize) -> EAlphaMode {
+    analyze(data.chunks_exact(channels).map(|p| p[channels - 1]), 0, u8::MAX)
+}
+
+/// 16 位数据，每像素 channels 个通道，Alpha 为最后一个
+pub fn alpha_mode_u16(data: &[u16], channels: usize) -> EAlphaMode {
+    analyze(data.chunks_exact(channels).map(|p| p[channels - 1]), 0, u16::MAX)
+}
+
+/// 浮点数据，每像素 channels 个通道，Alpha 为最后一个；大于 1 视为 1，小于 0 视为 0
+pub fn alpha_mode_f32(data: &[f32], channels: usize) -> EAlphaMode {
+    analyze(data.chunks_exact(channels).map(|p| p[channels - 1].clamp(0., 1.)), 0., 1.)
+}
+
+/// 按图片格式分析透明类型
+pub fn image_alpha_mode(image: &DynamicImage) -> EAlphaMode {
+    match image {
+        DynamicImage::ImageLumaA8(buffer) => alpha_mode_u8(buffer.as_raw(), 2),
+        DynamicImage::ImageRgba8(buffer) => alpha_mode_u8(buffer.as_raw(), 4),
+        DynamicImage::ImageLumaA16(buffer) => alpha_mode_u16(buffer.as_raw(), 2),
+        DynamicImage::ImageRgba16(buffer) => alpha_mode_u16(buffer.as_raw(), 4),
+        DynamicImage::ImageRgba32F(buffer) => alpha_mode_f32(buffer.as_raw(), 4),
+        _ => EAlphaMode::Opaque,
+    }
+}
+
+/// 颜色通道预乘 Alpha，返回是否做了处理
+/// * 数据按原样相乘，sRGB 数据未转线性
+pub fn premultiply_alpha(image: &mut DynamicImage) -> bool {
+    match image {
+        DynamicImage::ImageLumaA8(buffer) => premultiply_u8(&mut **buffer, 2),
+        DynamicImage::ImageRgba8(buffer) => premultiply_u8(&mut **buffer, 4),
+        DynamicImage::ImageLumaA16(buffer) => premultiply_u16(&mut **buffer, 2),
+        DynamicImage::ImageRgba16(buffer) => premultiply_u16(&mut **buffer, 4),
+        DynamicImage::ImageRgba32F(buffer) => {
+            buffer.chunks_exact_mut(4).for_each(|p| {
+                let alpha = p[3];
+                p[0..3].iter_mut().for_each(|v| *v *= alpha);
+            });
+        },
+        _ => return false,
+    }
+    true
+}
+
+fn premultiply_u8(data: &mut [u8], channels: usize) {
+    data.chunks_exact_mut(channels).for_each(|p| {
+        let alpha = p[channels - 1] as u32;
+        if alpha != 255 {
+            p[0..channels - 1].iter_mut().for_each(|v| *v = ((*v as u32 * alpha + 127) / 255) as u8);
+        }
+    });
+}
+
+fn premultiply_u16(data: &mut [u16], channels: usize) {
+    data.chunks_exact_mut(channels).for_each(|p| {
+        let alpha = p[channels - 1] as u32;
+        if alpha != 65535 {
+            p[0..channels - 1].iter_mut().for_each(|v| *v = ((*v as u32 * alpha + 32767) / 65535) as u16);
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn alpha_mode_and_premultiply() {
+        assert_eq!(alpha_mode_u8(&[1, 2, 3, 255, 4, 5, 6, 255], 4), EAlphaMode::Opaque);
+        assert_eq!(alpha_mode_u8(&[1, 2, 3, 255, 4, 5, 6, 0], 4), EAlphaMode::Mask);
+        assert_eq!(alpha_mode_u8(&[1, 2, 3, 0, 4, 5, 6, 128], 4), EAlphaMode::Translucent);
+        assert_eq!(alpha_mode_f32(&[1., 1., 1., 2.], 4), EAlphaMode::Opaque);
+
+        let mut data = [200, 100, 50, 128, 10, 20, 30, 255];
+        premultiply_u8(&mut data, 4);
+        assert_eq!(data, [100, 50, 25, 128, 10, 20, 30, 255]);
+    }
+}
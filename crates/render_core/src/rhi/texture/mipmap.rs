@@ -0,0 +1,341 @@
+//! GPU 生成 mipmap
+//!
+//! 逐级渲染：以上一级为源，每个像素取 2x2 个源像素的平均值，写入下一级
+//! * sRGB 格式的纹理，采样时硬件解码为线性空间，写入时编码，过滤结果正确
+//! * sRGB 数据存放在非 sRGB 格式中时（如 Rgba8Unorm），在 shader 中手动解码、编码
+//! * 图集：只处理指定区域，采样限制在区域内，相邻图块不会互相渗透
+//! * 只支持 2D 纹理（含数组），3D 纹理的 mip 每级深度减半，不能逐层生成，直接跳过
+
+use pi_hash::XHashMap;
+use pi_share::ShareMutex;
+use wgpu::util::BufferInitDescriptor;
+
+use crate::rhi::{bind_group_layout::BindGroupLayout, device::RenderDevice, pipeline::RenderPipeline, RenderQueue};
+
+use super::Sampler;
+
+const SHADER: &str = r#"
+struct VertexOutput {
+    @builtin(position) position: vec4<f32>,
+    @location(0) uv: vec2<f32>,
+    @location(1) bounds: vec4<f32>,
+    @location(2) texel: vec2<f32>,
+};
+
+@vertex
+fn vs_main(
+    @builtin(vertex_index) index: u32,
+    @location(0) dst: vec4<f32>,
+    @location(1) src: vec4<f32>,
+    @location(2) bounds: vec4<f32>,
+    @location(3) texel: vec2<f32>,
+) -> VertexOutput {
+    let x = f32(index & 1u);
+    let y = f32(index >> 1u);
+    var out: VertexOutput;
+    out.position = vec4<f32>(mix(dst.x, dst.z, x), mix(dst.y, dst.w, y), 0.0, 1.0);
+    out.uv = vec2<f32>(mix(src.x, src.z, x), mix(src.y, src.w, y));
+    out.bounds = bounds;
+    out.texel = texel;
+    return out;
+}
+
+@group(0) @binding(0) var src_texture: texture_2d<f32>;
+@group(0) @binding(1) var src_sampler: sampler;
+
+fn tap(uv: vec2<f32>, bounds: vec4<f32>) -> vec4<f32> {
+    return textureSampleLevel(src_texture, src_sampler, clamp(uv, bounds.xy, bounds.zw), 0.0);
+}
+
+fn srgb_to_linear(c: vec3<f32>) -> vec3<f32> {
+    return select(pow((c + 0.055) / 1.055, vec3<f32>(2.4)), c / 12.92, c <= vec3<f32>(0.04045));
+}
+
+fn linear_to_srgb(c: vec3<f32>) -> vec3<f32> {
+    return select(1.055 * pow(c, vec3<f32>(1.0 / 2.4)) - 0.055, c * 12.92, c <= vec3<f32>(0.0031308));
+}
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let t = in.texel;
+    return (tap(in.uv + vec2<f32>(-t.x, -t.y), in.bounds)
+        + tap(in.uv + vec2<f32>(t.x, -t.y), in.bounds)
+        + tap(in.uv + vec2<f32>(-t.x, t.y), in.bounds)
+        + tap(in.uv + vec2<f32>(t.x, t.y), in.bounds)) * 0.25;
+}
+
+@fragment
+fn fs_main_srgb(in: VertexOutput) -> @location(0) vec4<f32> {
+    let t = in.texel;
+    let a = tap(in.uv + vec2<f32>(-t.x, -t.y), in.bounds);
+    let b = tap(in.uv + vec2<f32>(t.x, -t.y), in.bounds);
+    let c = tap(in.uv + vec2<f32>(-t.x, t.y), in.bounds);
+    let d = tap(in.uv + vec2<f32>(t.x, t.y), in.bounds);
+    let rgb = (srgb_to_linear(a.rgb) + srgb_to_linear(b.rgb) + srgb_to_linear(c.rgb) + srgb_to_linear(d.rgb)) * 0.25;
+    return vec4<f32>(linear_to_srgb(rgb), (a.a + b.a + c.a + d.a) * 0.25);
+}
+"#;
+
+/// 每个区域一个实例：dst(4) src(4) bounds(4) texel(2)
+type Instance = [f32; 14];
+
+/// 完整 mip 链的级数
+pub fn mip_level_count(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+/// 可以用 GPU 生成 mipmap 的格式：非压缩、可过滤、可作为渲染目标的颜色格式
+pub fn is_mipmap_generatable(format: wgpu::TextureFormat) -> bool {
+    use wgpu::TextureFormat::*;
+    matches!(
+        format,
+        R8Unorm | Rg8Unorm | Rgba8Unorm | Rgba8UnormSrgb | Bgra8Unorm | Bgra8UnormSrgb | R16Float | Rg16Float | Rgba16Float | Rgb10a2Unorm
+    )
+}
+
+/// 带 mip 链的纹理 相对 单级纹理 的内存大小
+pub fn mipmap_size(size: usize, mip_level_count: u32) -> usize {
+    if mip_level_count > 1 { size * 4 / 3 } else { size }
+}
+
+pub struct MipmapGenerator {
+    shader: wgpu::ShaderModule,
+    sampler: Sampler,
+    bind_group_layout: BindGroupLayout,
+    pipeline_layout: wgpu::PipelineLayout,
+    /// (目标格式, 是否手动 sRGB 转换) -> 管线
+    pipelines: ShareMutex<XHashMap<(wgpu::TextureFormat, bool), RenderPipeline>>,
+}
+
+impl MipmapGenerator {
+    pub fn new(device: &RenderDevice) -> Self {
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("mipmap"),
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("mipmap"),
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some("mipmap"),
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                        view_dimension: wgpu::TextureViewDimension::D2,
+                        multisampled: false,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 1,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+            ],
+        });
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("mipmap"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        Self { shader, sampler, bind_group_layout, pipeline_layout, pipelines: ShareMutex::new(XHashMap::default()) }
+    }
+
+    /// 以第0级为源，生成整张纹理（所有层）的其余各级，非 2D 纹理不处理
+    /// * srgb - 纹理为非 sRGB 格式，但存放的是 sRGB 数据
+    pub fn generate(&self, device: &RenderDevice, queue: &RenderQueue, texture: &wgpu::Texture, srgb: bool) {
+        let region = (0, 0, texture.width(), texture.height());
+        for layer in 0..texture.depth_or_array_layers() {
+            self.generate_regions(device, queue, texture, layer, &[region], srgb);
+        }
+    }
+
+    /// 只生成 layer 层中 regions (x, y, width, height; 第0级像素坐标) 的其余各级，采样不越过区域边界
+    pub fn generate_regions(
+        &self,
+        device: &RenderDevice,
+        queue: &RenderQueue,
+        texture: &wgpu::Texture,
+        layer: u32,
+        regions: &[(u32, u32, u32, u32)],
+        srgb: bool,
+    ) {
+        let format = texture.format();
+        let levels = texture.mip_level_count();
+        if texture.dimension() != wgpu::TextureDimension::D2 {
+            log::warn!("mipmap generate only support 2D texture, dimension: {:?}", texture.dimension());
+            return;
+        }
+        if levels < 2 || regions.is_empty() || !is_mipmap_generatable(format) {
+            return;
+        }
+        let pipeline = self.pipeline(device, format, srgb && !format.is_srgb());
+        let (width, height) = (texture.width(), texture.height());
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor { label: Some("mipmap") });
+        // 保持各级的 buffer、bind group 存活到 encoder 提交
+        let mut resources = Vec::with_capacity(levels as usize);
+        for level in 1..levels {
+            let (sw, sh) = ((width >> (level - 1)).max(1), (height >> (level - 1)).max(1));
+            let (dw, dh) = ((width >> level).max(1), (height >> level).max(1));
+            let instances: Vec<Instance> = regions.iter().map(|(x, y, w, h)| {
+                // 目标区域向外取整，源区域同理；采样限制在源区域的像素中心以内
+                let (dx0, dy0, dx1, dy1) = level_rect(*x, *y, *w, *h, level, dw, dh);
+                let (sx0, sy0, sx1, sy1) = level_rect(*x, *y, *w, *h, level - 1, sw, sh);
+                let (dw, dh, sw, sh) = (dw as f32, dh as f32, sw as f32, sh as f32);
+                [
+                    dx0 / dw * 2.0 - 1.0, 1.0 - dy0 / dh * 2.0, dx1 / dw * 2.0 - 1.0, 1.0 - dy1 / dh * 2.0,
+                    dx0 / dw, dy0 / dh, dx1 / dw, dy1 / dh,
+                    (sx0 + 0.5) / sw, (sy0 + 0.5) / sh, (sx1 - 0.5) / sw, (sy1 - 0.5) / sh,
+                    0.5 / sw, 0.5 / sh,
+                ]
+            }).collect();
+            let buffer = device.create_buffer_with_data(&BufferInitDescriptor {
+                label: Some("mipmap"),
+                contents: bytemuck::cast_slice(&instances),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+            let view = |level: u32| texture.create_view(&wgpu::TextureViewDescriptor {
+                label: Some("mipmap"),
+                format: Some(format),
+                dimension: Some(wgpu::TextureViewDimension::D2),
+                aspect: wgpu::TextureAspect::All,
+                base_mip_level: level,
+                mip_level_count: Some(1),
+                base_array_layer: layer,
+                array_layer_count: Some(1),
+            });
+            let (src, dst) = (view(level - 1), view(level));
+            let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                label: Some("mipmap"),
+                layout: &self.bind_group_layout,
+                entries: &[
+                    wgpu::BindGroupEntry { binding: 0, resource: wgpu::BindingResource::TextureView(&src) },
+                    wgpu::BindGroupEntry { binding: 1, resource: wgpu::BindingResource::Sampler(&self.sampler) },
+                ],
+            });
+            {
+                let mut pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                    label: Some("mipmap"),
+                    color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                        view: &dst,
+                        resolve_target: None,
+                        // 图集中 其他区域 保持不变
+                        ops: wgpu::Operations { load: wgpu::LoadOp::Load, store: wgpu::StoreOp::Store },
+                    })],
+                    depth_stencil_attachment: None,
+                    timestamp_writes: None,
+                    occlusion_query_set: None,
+                });
+                pass.set_pipeline(&pipeline);
+                pass.set_bind_group(0, &bind_group, &[]);
+                pass.set_vertex_buffer(0, buffer.slice(..));
+                pass.draw(0..4, 0..instances.len() as u32);
+            }
+            resources.push((buffer, bind_group, src, dst));
+        }
+        queue.submit(Some(encoder.finish()));
+    }
+
+    fn pipeline(&self, device: &RenderDevice, format: wgpu::TextureFormat, srgb: bool) -> RenderPipeline {
+        let mut pipelines = self.pipelines.lock();
+        if let Some(pipeline) = pipelines.get(&(format, srgb)) {
+            return pipeline.clone();
+        }
+        let pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("mipmap"),
+            layout: Some(&self.pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &self.shader,
+                entry_point: "vs_main",
+                buffers: &[wgpu::VertexBufferLayout {
+                    array_stride: std::mem::size_of::<Instance>() as wgpu::BufferAddress,
+                    step_mode: wgpu::VertexStepMode::Instance,
+                    attributes: &wgpu::vertex_attr_array![0 => Float32x4, 1 => Float32x4, 2 => Float32x4, 3 => Float32x2],
+                }],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &self.shader,
+                entry_point: if srgb { "fs_main_srgb" } else { "fs_main" },
+                targets: &[Some(wgpu::ColorTargetState { format, blend: None, write_mask: wgpu::ColorWrites::ALL })],
+            }),
+            primitive: wgpu::PrimitiveState { topology: wgpu::PrimitiveTopology::TriangleStrip, ..Default::default() },
+            depth_stencil: None,
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+        pipelines.insert((format, srgb), pipeline.clone());
+        pipeline
+    }
+}
+
+/// 第0级的区域 在 level 级中覆盖的像素范围 (x0, y0, x1, y1)，至少一个像素
+fn level_rect(x: u32, y: u32, w: u32, h: u32, level: u32, max_w: u32, max_h: u32) -> (f32, f32, f32, f32) {
+    let round = (1 << level) - 1;
+    let x0 = (x >> level).min(max_w - 1);
+    let y0 = (y >> level).min(max_h - 1);
+    let x1 = ((x + w + round) >> level).clamp(x0 + 1, max_w);
+    let y1 = ((y + h + round) >> level).clamp(y0 + 1, max_h);
+    (x0 as f32, y0 as f32, x1 as f32, y1 as f32)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{renderer::texture::{ImageTextureFrame, RenderTexture}, rhi::test_util::{read_pixel, run}};
+
+    use super::*;
+
+    #[test]
+    fn mip_level_count_and_rect() {
+        assert_eq!(mip_level_count(1, 1), 1);
+        assert_eq!(mip_level_count(256, 100), 9);
+        assert_eq!(mip_level_count(255, 1), 8);
+        // 向外取整，至少一个像素
+        assert_eq!(level_rect(3, 5, 6, 2, 2, 8, 8), (0.0, 1.0, 3.0, 2.0));
+        assert_eq!(level_rect(7, 7, 1, 1, 3, 1, 1), (0.0, 0.0, 1.0, 1.0));
+        assert_eq!(mipmap_size(300, 1), 300);
+        assert_eq!(mipmap_size(300, 3), 400);
+    }
+
+    #[test]
+    fn generate_with_mips() {
+        run(|device, queue| {
+            let generator = MipmapGenerator::new(&device);
+
+            // 左半红、右半蓝，最后一级为平均值
+            let target = RenderTexture::color_with_mipmaps(&device, 4, 4, wgpu::TextureFormat::Rgba8Unorm);
+            assert_eq!(target.texture.mip_level_count(), 3);
+            let data: Vec<u8> = (0..16).flat_map(|i| if i % 4 < 2 { [255, 0, 0, 255] } else { [0, 0, 255, 255] }).collect();
+            queue.write_texture(
+                wgpu::ImageCopyTexture { texture: &target.texture, mip_level: 0, origin: wgpu::Origin3d::ZERO, aspect: wgpu::TextureAspect::All },
+                &data,
+                wgpu::ImageDataLayout { offset: 0, bytes_per_row: Some(16), rows_per_image: None },
+                wgpu::Extent3d { width: 4, height: 4, depth_or_array_layers: 1 },
+            );
+            target.generate_mipmaps(&generator, &device, &queue);
+            let pixel = read_pixel(&device, &queue, &target.texture, 2, 0, 0);
+            assert!(pixel[0].abs_diff(128) <= 1 && pixel[1] == 0 && pixel[2].abs_diff(128) <= 1 && pixel[3] == 255, "{:?}", pixel);
+
+            // 格式不支持生成时只有1级
+            let target = RenderTexture::color_with_mipmaps(&device, 4, 4, wgpu::TextureFormat::R32Float);
+            assert_eq!(target.texture.mip_level_count(), 1);
+
+            // 3D 纹理不处理，不会创建非法的 2D 视图
+            let key = pi_atom::Atom::from("mipmap_3d");
+            let texture = ImageTextureFrame::create_data_texture_with_mips(&device, &queue, &key, 4, 4, wgpu::TextureFormat::Rgba8Unorm, wgpu::TextureViewDimension::D3, true, 4, 3, None, None, 0);
+            assert_eq!(texture.texture.mip_level_count(), 3);
+            generator.generate(&device, &queue, &texture.texture, false);
+        });
+    }
+}
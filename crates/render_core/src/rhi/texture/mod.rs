@@ -1,4 +1,6 @@
 pub mod texture_cache;
+pub mod mipmap;
+pub mod alpha;
 mod texture_impl;
 
 pub use texture_impl::*;
@@ -2,7 +2,7 @@ use pi_share::Share;
 use std::ops::Deref;
 use wgpu::{SurfaceConfiguration, SurfaceTexture};
 
-use crate::rhi::device::RenderDevice;
+use crate::rhi::{device::RenderDevice, surface::{acquire_frame, ESurfaceError}};
 
 /// 提供 可 Clone的 Texture
 #[derive(Clone, Debug)]
@@ -161,21 +161,15 @@ impl ScreenTexture {
         &self.texture
     }
 
+	/// 获取下一帧，与 SurfaceManager::next_frame 的错误处理一致
+	/// * Outdated / Lost 时按 config 重新配置后重试一次
+	/// * 返回 Err 时应跳过该帧的渲染
 	#[inline]
-    pub fn next_frame(&mut self, device: &RenderDevice, config: &SurfaceConfiguration) {
+    pub fn next_frame(&mut self, device: &RenderDevice, config: &SurfaceConfiguration) -> Result<(), ESurfaceError> {
         assert_eq!(self.texture.is_some(), self.view.is_some());
         
         if self.texture.is_none() {
-            let t = match self.surface.get_current_texture() {
-                Ok(swap_chain_frame) => swap_chain_frame,
-                Err(wgpu::SurfaceError::Outdated) => {
-                    device.configure_surface(&self.surface, config);
-                    self.surface
-                        .get_current_texture()
-                        .expect("Error reconfiguring surface")
-                }
-                err => err.expect("Failed to acquire next swap chain texture!"),
-            };
+            let t = acquire_frame(&self.surface, |_| device.configure_surface(&self.surface, config))?;
 
             let t = Share::new(t);
             let v = Share::new(t.texture.create_view(&Default::default()));
@@ -183,6 +177,7 @@ impl ScreenTexture {
             self.texture = Some(t);
             self.view = Some(v);
         }
+        Ok(())
     }
 
 	#[inline]